@@ -0,0 +1,162 @@
+/// A server that speaks RESP and understands PING and ECHO.
+///
+/// Commands are parsed with the same parser the client uses to parse replies
+/// and replies are encoded with the same encoder.
+///
+/// # Examples
+///
+/// ```terminal
+/// cargo run --example echo_server 127.0.0.1:6390
+///
+/// redis-cli -p 6390 PING          -> PONG
+/// redis-cli -p 6390 ECHO Hello    -> "Hello"
+/// ```
+use miette::{IntoDiagnostic, Result};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{error, info};
+
+use redis::data_type::DataType;
+use redis::{reply, resp};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+  tracing_subscriber::fmt::init();
+
+  let addr = std::env::args()
+    .nth(1)
+    .unwrap_or_else(|| String::from("127.0.0.1:6390"));
+
+  let listener = TcpListener::bind(&addr).await.into_diagnostic()?;
+
+  info!(%addr, "listening");
+
+  serve(listener).await
+}
+
+/// Accepts connections forever, handling each one in its own task.
+async fn serve(listener: TcpListener) -> Result<()> {
+  loop {
+    let (socket, addr) = listener.accept().await.into_diagnostic()?;
+
+    info!(%addr, "accepted connection");
+
+    tokio::spawn(async move {
+      if let Err(err) = handle_connection(socket).await {
+        error!(%addr, ?err, "connection failed");
+      }
+    });
+  }
+}
+
+/// Replies to every command sent through `socket` until the client disconnects.
+async fn handle_connection(mut socket: TcpStream) -> Result<()> {
+  // Bytes read from the socket that have not been parsed yet.
+  let mut buffer = Vec::new();
+
+  let mut chunk = vec![0; 4096];
+
+  loop {
+    // A single read may contain part of a command or more than one command.
+    while !resp::is_complete(&buffer)? {
+      let bytes_read = socket.read(&mut chunk).await.into_diagnostic()?;
+
+      if bytes_read == 0 {
+        return Ok(());
+      }
+
+      buffer.extend_from_slice(&chunk[..bytes_read]);
+    }
+
+    let (command, command_ends_at) = resp::parse_at(&buffer, 0)?;
+
+    buffer.drain(..command_ends_at);
+
+    let reply = execute(command);
+
+    socket
+      .write_all(&resp::encode_value(&reply))
+      .await
+      .into_diagnostic()?;
+  }
+}
+
+/// Returns the reply to `command`.
+fn execute(command: DataType) -> DataType {
+  let args = match command {
+    DataType::Array(args) => args,
+    _ => return reply::error("ERR", "Protocol error: expected an array of bulk strings"),
+  };
+
+  let args: Vec<Vec<u8>> = match args
+    .into_iter()
+    .map(|arg| match arg {
+      DataType::BulkString(bytes) => Some(bytes),
+      _ => None,
+    })
+    .collect()
+  {
+    Some(args) => args,
+    None => return reply::error("ERR", "Protocol error: expected an array of bulk strings"),
+  };
+
+  let name = match args.first() {
+    None => return reply::error("ERR", "Protocol error: empty command"),
+    Some(name) => String::from_utf8_lossy(name).to_uppercase(),
+  };
+
+  match (name.as_str(), &args[1..]) {
+    ("PING", []) => DataType::SimpleString(String::from("PONG")),
+    ("PING", [message]) => reply::bulk(message.clone()),
+    ("ECHO", [message]) => reply::bulk(message.clone()),
+    ("PING", _) | ("ECHO", _) => reply::error(
+      "ERR",
+      &format!(
+        "wrong number of arguments for '{}' command",
+        name.to_lowercase()
+      ),
+    ),
+    _ => reply::error("ERR", &format!("unknown command '{}'", name)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use redis::command::Command;
+  use redis::redis::{Redis, Reply};
+
+  #[tokio::test]
+  async fn ping_and_echo() -> Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.into_diagnostic()?;
+    let addr = listener.local_addr().into_diagnostic()?;
+
+    tokio::spawn(serve(listener));
+
+    let mut redis = Redis::connect(&addr.to_string()).await?;
+
+    assert_eq!(
+      Reply::Ok(DataType::SimpleString(String::from("PONG"))),
+      redis.send_command(Command::new("PING")).await?
+    );
+
+    assert_eq!(
+      Reply::Ok(DataType::BulkString(b"Hello World".to_vec())),
+      redis
+        .send_command(Command::new("ECHO").arg("Hello World"))
+        .await?
+    );
+
+    assert_eq!(
+      Reply::Ok(DataType::BulkString(b"Hello".to_vec())),
+      redis.send("ECHO Hello").await?
+    );
+
+    assert_eq!(
+      Reply::Error(String::from("ERR unknown command 'FOO'")),
+      redis.send_command(Command::new("FOO")).await?
+    );
+
+    Ok(())
+  }
+}
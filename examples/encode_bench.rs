@@ -0,0 +1,105 @@
+/// Compares how long encoding a Bulk String of 64 MiB takes, and how much memory it needs
+/// on top of the value, with [resp::encode_value] and with [resp::encode_to_writer].
+///
+/// [resp::encode_value] builds the whole encoded value before it can be written anywhere,
+/// [resp::encode_to_writer] writes the bytes of the string straight to the writer,
+/// which is [std::io::sink] here so only the encoding is measured.
+/// The buffer of [resp::encode_value] grows by doubling, so it needs up to three times the string.
+///
+/// # Examples
+///
+/// ```terminal
+/// cargo run --release --example encode_bench
+///
+/// encode_value        64 MiB in 79.9ms per iteration, 192 MiB at most
+/// encode_to_writer    64 MiB in 94ns per iteration, 8 KiB at most
+/// ```
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::io::{self, BufWriter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use redis::data_type::DataType;
+use redis::resp;
+
+const STRING_LEN: usize = 64 * 1024 * 1024;
+
+const ITERATIONS: u32 = 50;
+
+/// Keeps track of how many bytes are allocated, see [measure].
+struct PeakAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for PeakAllocator {
+  unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+    PEAK.fetch_max(allocated, Ordering::Relaxed);
+    System.alloc(layout)
+  }
+
+  unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    System.dealloc(ptr, layout)
+  }
+}
+
+#[global_allocator]
+static ALLOCATOR: PeakAllocator = PeakAllocator;
+
+fn main() {
+  let value = DataType::BulkString(vec![b'a'; STRING_LEN]);
+
+  let (generic, generic_peak) = measure(|| {
+    black_box(resp::encode_value(black_box(&value)));
+  });
+
+  let (streaming, streaming_peak) = measure(|| {
+    let mut writer = BufWriter::new(io::sink());
+    resp::encode_to_writer(black_box(&value), &mut writer).unwrap();
+  });
+
+  println!(
+    "encode_value        {} MiB in {:?} per iteration, {} at most",
+    STRING_LEN / 1024 / 1024,
+    generic,
+    format_bytes(generic_peak)
+  );
+  println!(
+    "encode_to_writer    {} MiB in {:?} per iteration, {} at most",
+    STRING_LEN / 1024 / 1024,
+    streaming,
+    format_bytes(streaming_peak)
+  );
+}
+
+/// Returns how long `f` takes on average, after running it a few times to warm up,
+/// and the most memory it had allocated at once.
+fn measure(mut f: impl FnMut()) -> (Duration, usize) {
+  for _ in 0..ITERATIONS / 10 {
+    f();
+  }
+
+  let allocated_before = ALLOCATED.load(Ordering::Relaxed);
+  PEAK.store(allocated_before, Ordering::Relaxed);
+
+  let started_at = Instant::now();
+
+  for _ in 0..ITERATIONS {
+    f();
+  }
+
+  let elapsed = started_at.elapsed() / ITERATIONS;
+
+  (elapsed, PEAK.load(Ordering::Relaxed) - allocated_before)
+}
+
+fn format_bytes(bytes: usize) -> String {
+  if bytes >= 1024 * 1024 {
+    format!("{} MiB", bytes / 1024 / 1024)
+  } else {
+    format!("{} KiB", bytes / 1024)
+  }
+}
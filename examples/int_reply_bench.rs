@@ -0,0 +1,88 @@
+/// Compares how long sending INCR and reading its reply takes
+/// with [Redis::send] and with [Redis::send_expect_int].
+///
+/// The server is an in-memory stream that sends every reply before the benchmark starts,
+/// so the time is spent in the client instead of in the network or waiting for the server.
+///
+/// # Examples
+///
+/// ```terminal
+/// cargo run --release --example int_reply_bench
+///
+/// send               INCR in 523ns per iteration
+/// send_expect_int    INCR in 347ns per iteration
+/// ```
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use miette::Result;
+use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+use redis::data_type::DataType;
+use redis::redis::{Redis, Reply};
+
+const ITERATIONS: u32 = 100_000;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+  let generic = measure(
+    async |redis| match redis.send("INCR counter").await.unwrap() {
+      Reply::Ok(DataType::Int(n)) => {
+        black_box(n);
+      }
+      reply => panic!("unexpected reply {:?}", reply),
+    },
+  )
+  .await;
+
+  let fast = measure(async |redis| {
+    black_box(redis.send_expect_int("INCR counter").await.unwrap());
+  })
+  .await;
+
+  println!("send               INCR in {:?} per iteration", generic);
+  println!("send_expect_int    INCR in {:?} per iteration", fast);
+
+  Ok(())
+}
+
+/// Returns a client connected to a server that has already sent a ":1000\r\n" reply
+/// for every command the benchmark sends, so reading a reply never waits for the server
+/// and the time measured is the time the client spends encoding commands and parsing replies.
+fn integer_server() -> Redis<DuplexStream> {
+  let replies = (ITERATIONS + ITERATIONS / 10) as usize;
+
+  // Big enough for every command and every reply, so neither side waits for the other.
+  let (client, mut server) = duplex(replies * 32);
+
+  tokio::spawn(async move {
+    server
+      .write_all(&b":1000\r\n".repeat(replies))
+      .await
+      .unwrap();
+
+    // Discards the commands until the client is dropped.
+    let mut buffer = vec![0; 64 * 1024];
+    while let Ok(1..) = server.read(&mut buffer).await {}
+  });
+
+  Redis::from_stream(client)
+}
+
+/// Returns how long `f` takes on average with a client connected to its own [integer_server],
+/// after running it a few times to warm up.
+async fn measure(mut f: impl AsyncFnMut(&mut Redis<DuplexStream>)) -> Duration {
+  let mut redis = integer_server();
+
+  for _ in 0..ITERATIONS / 10 {
+    f(&mut redis).await;
+  }
+
+  let started_at = Instant::now();
+
+  for _ in 0..ITERATIONS {
+    f(&mut redis).await;
+  }
+
+  started_at.elapsed() / ITERATIONS
+}
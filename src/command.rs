@@ -0,0 +1,197 @@
+/// Building commands to send to a Redis server.
+///
+/// A client sends the Redis server a RESP Array consisting of just Bulk Strings.
+///
+/// Since every argument is sent as a Bulk String prefixed by its length,
+/// arguments are binary safe and may contain any byte, including "\r\n".
+/// The server never interprets the contents of an argument as protocol,
+/// so arguments built from untrusted input can't inject other commands.
+///
+/// # Examples
+///
+/// ```terminal
+/// Command::new("APPEND").arg("mykey").arg(" World")
+/// ```
+///
+/// is sent as:
+///
+/// ```terminal
+/// "*3\r\n$6\r\nAPPEND\r\n$5\r\nmykey\r\n$6\r\n World\r\n"
+/// ```
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Command {
+  /// The command name followed by its arguments.
+  args: Vec<Vec<u8>>,
+}
+
+impl Command {
+  pub fn new(name: &str) -> Self {
+    Self {
+      args: vec![name.as_bytes().to_vec()],
+    }
+  }
+
+  /// Appends an argument to the command.
+  pub fn arg(mut self, arg: impl AsRef<[u8]>) -> Self {
+    self.args.push(arg.as_ref().to_vec());
+    self
+  }
+
+  /// Appends an integer argument, formatted with [format_int].
+  pub fn arg_int(self, n: impl Into<i128>) -> Self {
+    self.arg(format_int(n))
+  }
+
+  /// Appends `duration` as a number of seconds, like "0.5",
+  /// for commands that take a timeout in seconds, like BLPOP.
+  pub fn arg_seconds(self, duration: Duration) -> Self {
+    self.arg(duration.as_secs_f64().to_string())
+  }
+
+  /// Appends `duration` as a whole number of milliseconds, like "500",
+  /// for commands that take a timeout in milliseconds, like WAIT.
+  pub fn arg_milliseconds(self, duration: Duration) -> Self {
+    self.arg_int(u64::try_from(duration.as_millis()).unwrap_or(u64::MAX))
+  }
+
+  /// Appends the number of `keys` followed by the keys, the way commands
+  /// that take a variable number of keys, like SINTERCARD, ZUNION or LMPOP, expect them.
+  ///
+  /// # Examples
+  ///
+  /// ```terminal
+  /// Command::new("SINTERCARD").args_with_numkeys(&["a", "b", "c"])   -> SINTERCARD 3 a b c
+  /// ```
+  pub fn args_with_numkeys(self, keys: &[impl AsRef<[u8]>]) -> Self {
+    keys
+      .iter()
+      .fold(self.arg_int(keys.len() as u64), |command, key| {
+        command.arg(key)
+      })
+  }
+
+  /// Returns the command name, like "APPEND".
+  pub fn name(&self) -> String {
+    String::from_utf8_lossy(&self.args[0]).to_string()
+  }
+
+  /// Returns how many arguments the command has, counting its name,
+  /// which is how Redis counts the arity of a command.
+  pub(crate) fn len(&self) -> usize {
+    self.args.len()
+  }
+
+  /// Encodes the command as a RESP Array of Bulk Strings.
+  pub fn encode(&self) -> Vec<u8> {
+    let mut buffer = format!("*{}\r\n", self.args.len()).into_bytes();
+
+    for arg in &self.args {
+      buffer.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+      buffer.extend_from_slice(arg);
+      buffer.extend_from_slice(b"\r\n");
+    }
+
+    buffer
+  }
+}
+
+/// Formats `n` the way Redis parses integer arguments: in decimal,
+/// with a "-" if it's negative and without a "+" or leading zeros.
+///
+/// # Examples
+///
+/// ```terminal
+/// 0           -> "0"
+/// -1          -> "-1"
+/// i64::MIN    -> "-9223372036854775808"
+/// ```
+pub fn format_int(n: impl Into<i128>) -> String {
+  n.into().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn encode() {
+    let tests = vec![
+      (Command::new("PING"), "*1\r\n$4\r\nPING\r\n"),
+      (
+        Command::new("LLEN").arg("mylist"),
+        "*2\r\n$4\r\nLLEN\r\n$6\r\nmylist\r\n",
+      ),
+      (
+        Command::new("APPEND").arg("mykey").arg(b"\r\n\x00"),
+        "*3\r\n$6\r\nAPPEND\r\n$5\r\nmykey\r\n$3\r\n\r\n\x00\r\n",
+      ),
+    ];
+
+    for (command, expected) in tests {
+      assert_eq!(expected.as_bytes().to_vec(), command.encode());
+    }
+  }
+
+  #[test]
+  fn integers() {
+    let tests = vec![
+      (0, "$1\r\n0\r\n"),
+      (-1, "$2\r\n-1\r\n"),
+      (10, "$2\r\n10\r\n"),
+      (i64::MAX, "$19\r\n9223372036854775807\r\n"),
+      (i64::MIN, "$20\r\n-9223372036854775808\r\n"),
+    ];
+
+    for (n, expected) in tests {
+      let encoded = Command::new("INCRBY").arg_int(n).encode();
+      assert_eq!(
+        format!("*2\r\n$6\r\nINCRBY\r\n{}", expected).into_bytes(),
+        encoded
+      );
+    }
+
+    assert_eq!("18446744073709551615", format_int(u64::MAX));
+    assert_eq!("0", format_int(0u8));
+  }
+
+  #[test]
+  fn durations() {
+    let tests = vec![
+      (Duration::from_millis(500), "0.5", "500"),
+      (Duration::from_secs(2), "2", "2000"),
+      (Duration::from_millis(1250), "1.25", "1250"),
+      (Duration::ZERO, "0", "0"),
+    ];
+
+    for (duration, seconds, milliseconds) in tests {
+      assert_eq!(
+        Command::new("BLPOP").arg(seconds),
+        Command::new("BLPOP").arg_seconds(duration)
+      );
+      assert_eq!(
+        Command::new("WAIT").arg(milliseconds),
+        Command::new("WAIT").arg_milliseconds(duration)
+      );
+    }
+  }
+
+  #[test]
+  fn numkeys() {
+    assert_eq!(
+      Command::new("SINTERCARD")
+        .arg("3")
+        .arg("a")
+        .arg("b")
+        .arg("c"),
+      Command::new("SINTERCARD").args_with_numkeys(&[b"a", b"b", b"c"])
+    );
+
+    let no_keys: [&str; 0] = [];
+    assert_eq!(
+      Command::new("ZUNION").arg("0"),
+      Command::new("ZUNION").args_with_numkeys(&no_keys)
+    );
+  }
+}
@@ -0,0 +1,292 @@
+/// Compressing large values before storing them and decompressing them when they are read,
+/// see [Redis::enable_compression].
+///
+/// A compressed value starts with a header, [MAGIC] followed by the id of the codec
+/// and the length of the original value as 8 little endian bytes, so [Redis::get]
+/// can tell compressed values apart from the others.
+///
+/// Values shorter than the threshold are stored as they are, except the ones that
+/// happen to start with [MAGIC], which are stored behind a header that says
+/// they are not compressed so they are not mistaken for compressed values.
+/// They are escaped whether compression is enabled or not.
+///
+/// # Examples
+///
+/// ```terminal
+/// redis.enable_compression(Compression { threshold: 1024, codec: Codec::Deflate });
+///
+/// redis.set("mykey", &large_json).await?;
+/// redis.get("mykey").await?      -> Some(large_json)
+/// ```
+use std::borrow::Cow;
+
+use miette::Result;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::redis::{Redis, RedisError};
+
+/// The bytes compressed values start with.
+pub const MAGIC: &[u8; 4] = b"\x00RZ\x01";
+
+/// The length of [MAGIC], the codec id and the length of the original value.
+const HEADER_LEN: usize = MAGIC.len() + 1 + 8;
+
+/// The id of values stored behind a header without being compressed.
+const STORED: u8 = 0;
+
+const DEFLATE: u8 = 1;
+
+/// How the values are compressed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Codec {
+  /// DEFLATE, as implemented by miniz_oxide.
+  Deflate,
+}
+
+impl Codec {
+  fn id(self) -> u8 {
+    match self {
+      Codec::Deflate => DEFLATE,
+    }
+  }
+}
+
+/// When and how values are compressed, see [Redis::enable_compression].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Compression {
+  /// Values of at least this many bytes are compressed.
+  pub threshold: usize,
+  pub codec: Codec,
+}
+
+impl Compression {
+  /// Returns `value` the way it should be stored, compressed and behind a header
+  /// if it's at least [Compression::threshold] bytes long.
+  ///
+  /// Values that don't get smaller are stored behind a header without being compressed.
+  pub(crate) fn compress<'a>(&self, value: &'a [u8]) -> Cow<'a, [u8]> {
+    if value.len() < self.threshold {
+      return escape(value);
+    }
+
+    let compressed = match self.codec {
+      Codec::Deflate => miniz_oxide::deflate::compress_to_vec(value, 6),
+    };
+
+    if compressed.len() < value.len() {
+      Cow::Owned(with_header(self.codec.id(), value.len(), &compressed))
+    } else {
+      Cow::Owned(with_header(STORED, value.len(), value))
+    }
+  }
+}
+
+/// Returns `value` the way it should be stored without being compressed,
+/// behind a header if it starts with [MAGIC] so it's not mistaken for a compressed value.
+pub(crate) fn escape(value: &[u8]) -> Cow<'_, [u8]> {
+  if value.starts_with(MAGIC) {
+    Cow::Owned(with_header(STORED, value.len(), value))
+  } else {
+    Cow::Borrowed(value)
+  }
+}
+
+/// Returns `body` behind a header saying it was encoded with `codec` from a value of `len` bytes.
+fn with_header(codec: u8, len: usize, body: &[u8]) -> Vec<u8> {
+  let mut stored = Vec::with_capacity(HEADER_LEN + body.len());
+  stored.extend_from_slice(MAGIC);
+  stored.push(codec);
+  stored.extend_from_slice(&(len as u64).to_le_bytes());
+  stored.extend_from_slice(body);
+  stored
+}
+
+/// Returns the original bytes of `value`, decompressing it if it starts with a header.
+pub(crate) fn decompress(value: Vec<u8>) -> Result<Vec<u8>, RedisError> {
+  if !value.starts_with(MAGIC) {
+    return Ok(value);
+  }
+
+  if value.len() < HEADER_LEN {
+    return Err(RedisError::CorruptCompressedValue(String::from(
+      "the header is incomplete",
+    )));
+  }
+
+  let codec = value[MAGIC.len()];
+
+  let mut len = [0; 8];
+  len.copy_from_slice(&value[MAGIC.len() + 1..HEADER_LEN]);
+  let len = u64::from_le_bytes(len) as usize;
+
+  let body = &value[HEADER_LEN..];
+
+  let original = match codec {
+    STORED => body.to_vec(),
+    // The limit stops a corrupted value from decompressing into much more than it should,
+    // it's twice the length because the output grows by doubling and may go past the length.
+    DEFLATE => miniz_oxide::inflate::decompress_to_vec_with_limit(body, len.saturating_mul(2))
+      .map_err(|status| {
+        RedisError::CorruptCompressedValue(format!("inflating failed: {:?}", status))
+      })?,
+    codec => {
+      return Err(RedisError::CorruptCompressedValue(format!(
+        "unknown codec {}",
+        codec
+      )))
+    }
+  };
+
+  if original.len() != len {
+    return Err(RedisError::CorruptCompressedValue(format!(
+      "expected {} bytes, got {}",
+      len,
+      original.len()
+    )));
+  }
+
+  Ok(original)
+}
+
+impl<S> Redis<S>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  /// Compresses the values written with [Redis::set] that are at least
+  /// [Compression::threshold] bytes long with [Compression::codec].
+  ///
+  /// [Redis::get] decompresses values whether compression is enabled or not.
+  pub fn enable_compression(&mut self, compression: Compression) {
+    self.compression = Some(compression);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::data_type::DataType;
+  use crate::redis::Reply;
+  use crate::resp;
+  use crate::test_server::stateful_server;
+  use std::collections::HashMap;
+  use tokio::io::DuplexStream;
+  use tokio::task::JoinHandle;
+
+  /// Returns a client connected to an in-memory server that understands SET, GET and STRLEN.
+  fn string_server() -> (Redis<DuplexStream>, JoinHandle<Vec<DataType>>) {
+    let mut strings: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
+
+    stateful_server(move |args| {
+      let reply = match args {
+        [name, key, value] if name == b"SET" => {
+          strings.insert(key.clone(), value.clone());
+          DataType::SimpleString(String::from("OK"))
+        }
+        [name, key] if name == b"GET" => match strings.get(key) {
+          Some(value) => DataType::BulkString(value.clone()),
+          None => DataType::Null,
+        },
+        [name, key] if name == b"STRLEN" => {
+          DataType::Int(strings.get(key).map_or(0, Vec::len) as i64)
+        }
+        args => panic!("unexpected command {:?}", args),
+      };
+
+      resp::encode_value(&reply)
+    })
+  }
+
+  #[tokio::test]
+  async fn round_trip() -> Result<()> {
+    let (mut redis, server) = string_server();
+
+    redis.enable_compression(Compression {
+      threshold: 1024,
+      codec: Codec::Deflate,
+    });
+
+    let large: Vec<u8> = (0..1000)
+      .map(|i| format!(r#"{{"id":{},"name":"user {}","active":true}}"#, i, i))
+      .collect::<Vec<_>>()
+      .join(",")
+      .into_bytes();
+
+    redis.set("large", &large).await?;
+    redis.set("small", b"Hello").await?;
+    redis.set("looks_compressed", b"\x00RZ\x01abc").await?;
+
+    let stored = match redis.send("STRLEN large").await? {
+      Reply::Ok(DataType::Int(len)) => len as usize,
+      reply => panic!("unexpected reply {:?}", reply),
+    };
+    assert!(
+      stored < large.len() / 4,
+      "{} bytes were stored for a value of {} bytes",
+      stored,
+      large.len()
+    );
+
+    assert_reply_int!(redis.send("STRLEN small").await?, 5);
+
+    assert_eq!(Some(large), redis.get("large").await?);
+    assert_eq!(Some(b"Hello".to_vec()), redis.get("small").await?);
+    assert_eq!(
+      Some(b"\x00RZ\x01abc".to_vec()),
+      redis.get("looks_compressed").await?
+    );
+    assert_eq!(None, redis.get("missing").await?);
+
+    drop(redis);
+    server.await.unwrap();
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn values_that_look_compressed_without_compression() -> Result<()> {
+    let (mut redis, server) = string_server();
+
+    redis.set("looks_compressed", b"\x00RZ\x01abc").await?;
+
+    // Stored behind a header even though compression is not enabled.
+    assert_reply_int!(
+      redis.send("STRLEN looks_compressed").await?,
+      (HEADER_LEN + 7) as i64
+    );
+    assert_eq!(
+      Some(b"\x00RZ\x01abc".to_vec()),
+      redis.get("looks_compressed").await?
+    );
+
+    drop(redis);
+    server.await.unwrap();
+
+    Ok(())
+  }
+
+  #[test]
+  fn corrupt_values() {
+    let compression = Compression {
+      threshold: 0,
+      codec: Codec::Deflate,
+    };
+
+    let mut stored = compression.compress(&[b'a'; 100]).into_owned();
+    assert_eq!(DEFLATE, stored[MAGIC.len()]);
+
+    // Claims the original value was shorter than it was.
+    stored[MAGIC.len() + 1] = 50;
+
+    assert!(matches!(
+      decompress(stored),
+      Err(RedisError::CorruptCompressedValue(_))
+    ));
+
+    assert_eq!(
+      Err(RedisError::CorruptCompressedValue(String::from(
+        "the header is incomplete"
+      ))),
+      decompress(MAGIC.to_vec())
+    );
+  }
+}
@@ -1,3 +1,6 @@
+use miette::Diagnostic;
+use thiserror::Error;
+
 /// RESP is actually a serialization protocol that supports the following data types: Simple Strings, Errors, Integers, Bulk Strings and Arrays.
 ///
 /// In RESP, the type of some data depends on the first byte:
@@ -8,9 +11,28 @@
 /// For Bulk Strings the first byte of the reply is "$"
 /// For Arrays the first byte of the reply is "*"
 ///
+/// RESP3 adds more types:
+///
+/// For Nulls the first byte of the reply is "_"
+/// For Doubles the first byte of the reply is ","
+/// For Booleans the first byte of the reply is "#"
+/// For Blob Errors the first byte of the reply is "!"
+/// For Verbatim Strings the first byte of the reply is "="
+/// For Big Numbers the first byte of the reply is "("
+/// For Maps the first byte of the reply is "%"
+/// For Sets the first byte of the reply is "~"
+/// For Attributes the first byte of the reply is "|"
+/// For Pushes the first byte of the reply is ">"
+///
+/// Blob Errors are parsed as Errors, Verbatim Strings as Bulk Strings
+/// and Attributes are skipped, since they only carry auxiliary data about the reply that follows them.
+///
 /// In RESP different parts of the protocol are always terminated with
 /// "\r\n" (CRLF).
-#[derive(Debug, PartialEq)]
+///
+/// A `DataType` owns its data: the parser copies strings out of the input,
+/// so a parsed value can outlive the buffer it was parsed from.
+#[derive(Debug, Clone, PartialEq)]
 pub enum DataType {
   /// When the first byte of the data is "+"
   ///
@@ -40,6 +62,18 @@ pub enum DataType {
   /// ```
   // TODO: is i64 enough?
   Int(i64),
+  /// When the first byte of the data is ","
+  ///
+  /// Doubles were added in RESP3 and represent a floating point number.
+  /// Positive and negative infinity are represented as "inf" and "-inf".
+  ///
+  /// # Examples
+  ///
+  /// ```terminal
+  /// ",1.23\r\n"
+  /// ",inf\r\n"
+  /// ```
+  Double(f64),
   /// When the first byte of the data is "$"
   ///
   /// Bulk Strings are used in order to represent a single binary safe string up to 512 MB in length.
@@ -61,7 +95,7 @@ pub enum DataType {
   /// ```terminal
   /// "$0\r\n\r\n"
   /// ```
-  BulkString(String),
+  BulkString(Vec<u8>),
   /// When the first byte of the data is "*"
   ///
   /// RESP Arrays are sent using the following format:
@@ -97,6 +131,58 @@ pub enum DataType {
   /// "*3\r\n$3\r\nfoo\r\n:1\r\n:2\r\n"
   /// ```
   Array(Vec<DataType>),
+  /// When the first byte of the data is "#"
+  ///
+  /// # Examples
+  ///
+  /// ```terminal
+  /// "#t\r\n"
+  /// "#f\r\n"
+  /// ```
+  Boolean(bool),
+  /// When the first byte of the data is "("
+  ///
+  /// Big Numbers represent integers outside of the range of a signed 64 bit integer,
+  /// they are kept as the string of digits sent by the server.
+  ///
+  /// # Examples
+  ///
+  /// ```terminal
+  /// "(3492890328409238509324850943850943825024385\r\n"
+  /// ```
+  BigNumber(String),
+  /// When the first byte of the data is "%"
+  ///
+  /// Maps are like Arrays, except that the length is the number of key-value pairs
+  /// and every pair is sent as the key followed by the value.
+  ///
+  /// # Examples
+  ///
+  /// ```terminal
+  /// "%2\r\n+first\r\n:1\r\n+second\r\n:2\r\n"
+  /// ```
+  Map(Vec<(DataType, DataType)>),
+  /// When the first byte of the data is "~"
+  ///
+  /// Sets are sent like Arrays, but the server guarantees the elements are unique.
+  ///
+  /// # Examples
+  ///
+  /// ```terminal
+  /// "~2\r\n+orange\r\n+apple\r\n"
+  /// ```
+  Set(Vec<DataType>),
+  /// When the first byte of the data is ">"
+  ///
+  /// Pushes are sent like Arrays, but they are not the reply to a command:
+  /// the server sends them on its own, like the messages of a subscribed channel.
+  ///
+  /// # Examples
+  ///
+  /// ```terminal
+  /// ">3\r\n+message\r\n+channel\r\n+hello\r\n"
+  /// ```
+  Push(Vec<DataType>),
   /// When a Bulk String is used to signal non-existence of a value using
   /// a special format that is used to represent a Null value.
   ///
@@ -113,5 +199,570 @@ pub enum DataType {
   /// "$-1\r\n"
   /// "*-1\r\n"
   /// ```
+  ///
+  /// RESP3 has a single Null type:
+  ///
+  /// ```terminal
+  /// "_\r\n"
+  /// ```
+  Null,
+}
+
+/// The type of a [DataType], without its contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DataTypeKind {
+  SimpleString,
+  Error,
+  Int,
+  Double,
+  BulkString,
+  Array,
+  Boolean,
+  BigNumber,
+  Map,
+  Set,
+  Push,
   Null,
 }
+
+impl std::fmt::Display for DataTypeKind {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let name = match self {
+      DataTypeKind::SimpleString => "simple string",
+      DataTypeKind::Error => "error",
+      DataTypeKind::Int => "integer",
+      DataTypeKind::Double => "double",
+      DataTypeKind::BulkString => "bulk string",
+      DataTypeKind::Array => "array",
+      DataTypeKind::Boolean => "boolean",
+      DataTypeKind::BigNumber => "big number",
+      DataTypeKind::Map => "map",
+      DataTypeKind::Set => "set",
+      DataTypeKind::Push => "push",
+      DataTypeKind::Null => "null",
+    };
+
+    f.write_str(name)
+  }
+}
+
+impl DataType {
+  /// Returns the type of the value.
+  pub fn kind(&self) -> DataTypeKind {
+    match self {
+      DataType::SimpleString(_) => DataTypeKind::SimpleString,
+      DataType::Error(_) => DataTypeKind::Error,
+      DataType::Int(_) => DataTypeKind::Int,
+      DataType::Double(_) => DataTypeKind::Double,
+      DataType::BulkString(_) => DataTypeKind::BulkString,
+      DataType::Array(_) => DataTypeKind::Array,
+      DataType::Boolean(_) => DataTypeKind::Boolean,
+      DataType::BigNumber(_) => DataTypeKind::BigNumber,
+      DataType::Map(_) => DataTypeKind::Map,
+      DataType::Set(_) => DataTypeKind::Set,
+      DataType::Push(_) => DataTypeKind::Push,
+      DataType::Null => DataTypeKind::Null,
+    }
+  }
+
+  /// Returns the size of the value: the number of elements of an aggregate,
+  /// the number of entries of a Map, the number of bytes of a string
+  /// and the number of decimal digits of an Integer, without its sign.
+  ///
+  /// Null has no size, a Double is as long as its decimal representation and a Boolean has a size of 1.
+  pub fn len(&self) -> usize {
+    match self {
+      DataType::SimpleString(string) | DataType::Error(string) | DataType::BigNumber(string) => {
+        string.len()
+      }
+      DataType::BulkString(bytes) => bytes.len(),
+      DataType::Int(int) => int.unsigned_abs().to_string().len(),
+      DataType::Double(double) => double.to_string().len(),
+      DataType::Boolean(_) => 1,
+      DataType::Array(elements) | DataType::Set(elements) | DataType::Push(elements) => {
+        elements.len()
+      }
+      DataType::Map(entries) => entries.len(),
+      DataType::Null => 0,
+    }
+  }
+
+  /// Returns true if [DataType::len] is 0, like for an empty string, an empty Array or Null.
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// Compares two values like `==` does, except that
+  /// Doubles are considered equal when they are within `epsilon` of each other.
+  ///
+  /// Useful because values like 3.3 can't be represented exactly.
+  pub fn approx_eq(&self, other: &DataType, epsilon: f64) -> bool {
+    match (self, other) {
+      (DataType::Double(a), DataType::Double(b)) => a == b || (a - b).abs() <= epsilon,
+      (DataType::Array(a), DataType::Array(b))
+      | (DataType::Set(a), DataType::Set(b))
+      | (DataType::Push(a), DataType::Push(b)) => {
+        a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.approx_eq(b, epsilon))
+      }
+      (DataType::Map(a), DataType::Map(b)) => {
+        a.len() == b.len()
+          && a.iter().zip(b).all(|((a_key, a_value), (b_key, b_value))| {
+            a_key.approx_eq(b_key, epsilon) && a_value.approx_eq(b_value, epsilon)
+          })
+      }
+      _ => self == other,
+    }
+  }
+
+  /// Returns a hash of the type and contents of the value, recursing into aggregates.
+  ///
+  /// Values that are equal have the same hash, and the hash does not change
+  /// between runs or versions of Rust, so it can be used as a cache key or stored in snapshots.
+  pub fn content_hash(&self) -> u64 {
+    let mut hasher = Fnv1a::new();
+    self.hash_into(&mut hasher);
+    hasher.finish()
+  }
+
+  fn hash_into(&self, hasher: &mut Fnv1a) {
+    match self {
+      DataType::SimpleString(string) => {
+        hasher.write(b"+");
+        hasher.write_bytes(string.as_bytes());
+      }
+      DataType::Error(message) => {
+        hasher.write(b"-");
+        hasher.write_bytes(message.as_bytes());
+      }
+      DataType::Int(int) => {
+        hasher.write(b":");
+        hasher.write(&int.to_le_bytes());
+      }
+      DataType::Double(double) => {
+        hasher.write(b",");
+        // 0.0 and -0.0 are equal but have different bits.
+        let double = if *double == 0.0 { 0.0 } else { *double };
+        hasher.write(&double.to_bits().to_le_bytes());
+      }
+      DataType::BulkString(bytes) => {
+        hasher.write(b"$");
+        hasher.write_bytes(bytes);
+      }
+      DataType::Array(elements) => {
+        hasher.write(b"*");
+        hash_elements(elements, hasher);
+      }
+      DataType::Boolean(boolean) => {
+        hasher.write(b"#");
+        hasher.write(&[*boolean as u8]);
+      }
+      DataType::BigNumber(number) => {
+        hasher.write(b"(");
+        hasher.write_bytes(number.as_bytes());
+      }
+      DataType::Map(entries) => {
+        hasher.write(b"%");
+        hasher.write(&(entries.len() as u64).to_le_bytes());
+        for (key, value) in entries {
+          key.hash_into(hasher);
+          value.hash_into(hasher);
+        }
+      }
+      DataType::Set(elements) => {
+        hasher.write(b"~");
+        hash_elements(elements, hasher);
+      }
+      DataType::Push(elements) => {
+        hasher.write(b">");
+        hash_elements(elements, hasher);
+      }
+      DataType::Null => hasher.write(b"_"),
+    }
+  }
+}
+
+fn hash_elements(elements: &[DataType], hasher: &mut Fnv1a) {
+  hasher.write(&(elements.len() as u64).to_le_bytes());
+  for element in elements {
+    element.hash_into(hasher);
+  }
+}
+
+/// The 64 bit FNV-1a hash function.
+///
+/// Used instead of [std::collections::hash_map::DefaultHasher]
+/// because its algorithm may change between versions of Rust.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+  fn new() -> Self {
+    Self(0xcbf29ce484222325)
+  }
+
+  fn write(&mut self, bytes: &[u8]) {
+    for byte in bytes {
+      self.0 ^= *byte as u64;
+      self.0 = self.0.wrapping_mul(0x100000001b3);
+    }
+  }
+
+  /// Writes the length of `bytes` before them, so ["ab", "c"] and ["a", "bc"] hash differently.
+  fn write_bytes(&mut self, bytes: &[u8]) {
+    self.write(&(bytes.len() as u64).to_le_bytes());
+    self.write(bytes);
+  }
+
+  fn finish(&self) -> u64 {
+    self.0
+  }
+}
+
+/// A value without the shape a conversion expected, see the [TryFrom] implementations for tuples.
+#[derive(Debug, PartialEq, Diagnostic, Error)]
+pub enum ConversionError {
+  #[error("expected an array, got {0}")]
+  #[diagnostic()]
+  NotAnArray(DataTypeKind),
+  #[error("expected an array of {expected} elements, got {got} elements")]
+  #[diagnostic()]
+  WrongLength { expected: usize, got: usize },
+  #[error("expected element {index} to be {expected}, got {got:?}")]
+  #[diagnostic()]
+  WrongElement {
+    index: usize,
+    expected: &'static str,
+    got: DataType,
+  },
+}
+
+/// A type an element of a fixed-shape Array can be converted into,
+/// used by the [TryFrom] implementations for tuples.
+///
+/// # Examples
+///
+/// ```terminal
+/// let (key, element): (String, Vec<u8>) = reply.try_into()?;
+/// ```
+pub trait FromElement: Sized {
+  /// What the element should be, like "a bulk string", used in [ConversionError::WrongElement].
+  const EXPECTED: &'static str;
+
+  /// Converts `element`, giving it back if it can't be converted.
+  fn from_element(element: DataType) -> Result<Self, DataType>;
+}
+
+impl FromElement for DataType {
+  const EXPECTED: &'static str = "any value";
+
+  fn from_element(element: DataType) -> Result<Self, DataType> {
+    Ok(element)
+  }
+}
+
+impl FromElement for Vec<DataType> {
+  const EXPECTED: &'static str = "an array";
+
+  fn from_element(element: DataType) -> Result<Self, DataType> {
+    match element {
+      DataType::Array(elements) | DataType::Set(elements) => Ok(elements),
+      element => Err(element),
+    }
+  }
+}
+
+impl FromElement for Vec<u8> {
+  const EXPECTED: &'static str = "a string";
+
+  fn from_element(element: DataType) -> Result<Self, DataType> {
+    match element {
+      DataType::BulkString(bytes) => Ok(bytes),
+      DataType::SimpleString(string) => Ok(string.into_bytes()),
+      element => Err(element),
+    }
+  }
+}
+
+impl FromElement for String {
+  const EXPECTED: &'static str = "a UTF-8 string";
+
+  fn from_element(element: DataType) -> Result<Self, DataType> {
+    match element {
+      DataType::BulkString(bytes) => {
+        String::from_utf8(bytes).map_err(|err| DataType::BulkString(err.into_bytes()))
+      }
+      DataType::SimpleString(string) => Ok(string),
+      element => Err(element),
+    }
+  }
+}
+
+impl FromElement for i64 {
+  const EXPECTED: &'static str = "an integer";
+
+  fn from_element(element: DataType) -> Result<Self, DataType> {
+    match element {
+      DataType::Int(int) => Ok(int),
+      element => Err(element),
+    }
+  }
+}
+
+/// Also accepts a Bulk String of digits, since that's how cursors, like the one SCAN returns, are sent.
+impl FromElement for u64 {
+  const EXPECTED: &'static str = "a non-negative integer";
+
+  fn from_element(element: DataType) -> Result<Self, DataType> {
+    let int = match &element {
+      DataType::Int(int) => u64::try_from(*int).ok(),
+      DataType::BulkString(bytes) => std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|digits| digits.parse().ok()),
+      _ => None,
+    };
+
+    int.ok_or(element)
+  }
+}
+
+/// Also accepts a Bulk String, since that's how RESP2 sends scores.
+impl FromElement for f64 {
+  const EXPECTED: &'static str = "a double";
+
+  fn from_element(element: DataType) -> Result<Self, DataType> {
+    let double = match &element {
+      DataType::Double(double) => Some(*double),
+      DataType::Int(int) => Some(*int as f64),
+      DataType::BulkString(bytes) => std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|double| double.parse().ok()),
+      _ => None,
+    };
+
+    double.ok_or(element)
+  }
+}
+
+/// Implements [TryFrom] for a tuple of the given arity, converting an Array of exactly as many elements.
+macro_rules! impl_try_from_array_for_tuple {
+  ($len:expr; $($index:tt $element:ident $value:ident),+) => {
+    impl<$($element: FromElement),+> TryFrom<DataType> for ($($element,)+) {
+      type Error = ConversionError;
+
+      fn try_from(data_type: DataType) -> Result<Self, Self::Error> {
+        let elements = match data_type {
+          DataType::Array(elements) => elements,
+          data_type => return Err(ConversionError::NotAnArray(data_type.kind())),
+        };
+
+        let elements = <[DataType; $len]>::try_from(elements).map_err(|elements| {
+          ConversionError::WrongLength {
+            expected: $len,
+            got: elements.len(),
+          }
+        })?;
+
+        let [$($value),+] = elements;
+
+        Ok(($(
+          $element::from_element($value).map_err(|got| ConversionError::WrongElement {
+            index: $index,
+            expected: $element::EXPECTED,
+            got,
+          })?,
+        )+))
+      }
+    }
+  };
+}
+
+impl_try_from_array_for_tuple!(2; 0 A a, 1 B b);
+impl_try_from_array_for_tuple!(3; 0 A a, 1 B b, 2 C c);
+impl_try_from_array_for_tuple!(4; 0 A a, 1 B b, 2 C c, 3 D d);
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::resp;
+
+  #[test]
+  fn len() {
+    let tests = vec![
+      (DataType::SimpleString(String::from("OK")), 2),
+      (DataType::Error(String::from("ERR unknown")), 11),
+      (DataType::BulkString(b"foo\r\nbar".to_vec()), 8),
+      (DataType::BulkString(vec![]), 0),
+      (DataType::Int(0), 1),
+      (DataType::Int(1000), 4),
+      (DataType::Int(-1000), 4),
+      (DataType::Int(i64::MIN), 19),
+      (DataType::Double(1.5), 3),
+      (DataType::Boolean(false), 1),
+      (
+        DataType::BigNumber(String::from("3492890328409238509324850943850943825024385")),
+        43,
+      ),
+      (DataType::Array(vec![DataType::Null, DataType::Int(1)]), 2),
+      (DataType::Array(vec![]), 0),
+      (DataType::Set(vec![DataType::Int(1)]), 1),
+      (
+        DataType::Push(vec![DataType::Int(1), DataType::Int(2), DataType::Int(3)]),
+        3,
+      ),
+      (
+        DataType::Map(vec![
+          (DataType::Int(1), DataType::Int(2)),
+          (DataType::Int(3), DataType::Int(4)),
+        ]),
+        2,
+      ),
+      (DataType::Null, 0),
+    ];
+
+    for (value, expected) in tests {
+      assert_eq!(expected, value.len(), "{:?}", value);
+      assert_eq!(expected == 0, value.is_empty(), "{:?}", value);
+    }
+  }
+
+  #[test]
+  fn approx_eq() {
+    let parsed = resp::parse(b",3.3\r\n".to_vec()).unwrap();
+
+    assert!(parsed.approx_eq(&DataType::Double(3.3), 1e-9));
+    assert!(parsed.approx_eq(&DataType::Double(3.3000001), 1e-6));
+    assert!(!parsed.approx_eq(&DataType::Double(3.31), 1e-6));
+    assert!(!parsed.approx_eq(&DataType::Int(3), 1.0));
+
+    assert!(DataType::Double(f64::INFINITY).approx_eq(&DataType::Double(f64::INFINITY), 1e-9));
+
+    assert!(
+      DataType::Array(vec![DataType::Int(1), DataType::Double(0.1 + 0.2)]).approx_eq(
+        &DataType::Array(vec![DataType::Int(1), DataType::Double(0.3)]),
+        1e-9
+      )
+    );
+    assert!(!DataType::Array(vec![DataType::Double(0.3)]).approx_eq(
+      &DataType::Array(vec![DataType::Double(0.3), DataType::Null]),
+      1e-9
+    ));
+  }
+
+  #[test]
+  fn content_hash() {
+    let parse = |input: &str| resp::parse(input.as_bytes().to_vec()).unwrap();
+
+    let nested = "*3\r\n*2\r\n:1\r\n$3\r\nfoo\r\n*0\r\n*1\r\n*1\r\n$-1\r\n";
+
+    assert_eq!(parse(nested).content_hash(), parse(nested).content_hash());
+    assert_eq!(
+      parse(nested).content_hash(),
+      DataType::Array(vec![
+        DataType::Array(vec![
+          DataType::Int(1),
+          DataType::BulkString(b"foo".to_vec())
+        ]),
+        DataType::Array(vec![]),
+        DataType::Array(vec![DataType::Array(vec![DataType::Null])]),
+      ])
+      .content_hash()
+    );
+
+    // The hash must not change between versions, since it may be stored.
+    assert_eq!(0xaf64124c8602484e, DataType::Null.content_hash());
+
+    let different = vec![
+      nested,
+      "*3\r\n*2\r\n:1\r\n$3\r\nfoo\r\n*0\r\n*1\r\n*0\r\n",
+      "*3\r\n*2\r\n:1\r\n$3\r\nbar\r\n*0\r\n*1\r\n*1\r\n$-1\r\n",
+      "*2\r\n*2\r\n:1\r\n$3\r\nfoo\r\n*0\r\n",
+      "*2\r\n$2\r\nab\r\n$1\r\nc\r\n",
+      "*2\r\n$1\r\na\r\n$2\r\nbc\r\n",
+      "*1\r\n:1\r\n",
+      "*1\r\n$1\r\n1\r\n",
+      "*1\r\n+1\r\n",
+      "~1\r\n:1\r\n",
+      ">1\r\n:1\r\n",
+      "%1\r\n:1\r\n:1\r\n",
+      "*2\r\n:1\r\n:1\r\n",
+    ];
+
+    for (i, a) in different.iter().enumerate() {
+      for b in &different[i + 1..] {
+        assert_ne!(
+          parse(a).content_hash(),
+          parse(b).content_hash(),
+          "{:?} and {:?}",
+          a,
+          b
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn arrays_into_tuples() {
+    let reply = DataType::Array(vec![
+      DataType::BulkString(b"mylist".to_vec()),
+      DataType::BulkString(b"\x00\xff".to_vec()),
+    ]);
+    assert_eq!(
+      Ok((String::from("mylist"), vec![0, 255])),
+      <(String, Vec<u8>)>::try_from(reply)
+    );
+
+    let reply = DataType::Array(vec![
+      DataType::BulkString(b"17".to_vec()),
+      DataType::Array(vec![DataType::BulkString(b"key".to_vec())]),
+    ]);
+    assert_eq!(
+      Ok((17, vec![DataType::BulkString(b"key".to_vec())])),
+      <(u64, Vec<DataType>)>::try_from(reply)
+    );
+
+    let reply = DataType::Array(vec![
+      DataType::Int(1),
+      DataType::Double(1.5),
+      DataType::Null,
+    ]);
+    assert_eq!(
+      Ok((1, 1.5, DataType::Null)),
+      <(i64, f64, DataType)>::try_from(reply)
+    );
+  }
+
+  #[test]
+  fn mismatched_arrays_into_tuples() {
+    let reply = DataType::Array(vec![
+      DataType::BulkString(b"a".to_vec()),
+      DataType::BulkString(b"b".to_vec()),
+      DataType::BulkString(b"c".to_vec()),
+    ]);
+    let err = <(String, Vec<u8>)>::try_from(reply).unwrap_err();
+    assert_eq!(
+      ConversionError::WrongLength {
+        expected: 2,
+        got: 3
+      },
+      err
+    );
+    assert_eq!(
+      "expected an array of 2 elements, got 3 elements",
+      err.to_string()
+    );
+
+    let reply = DataType::Array(vec![DataType::Int(-1), DataType::Array(vec![])]);
+    assert_eq!(
+      Err(ConversionError::WrongElement {
+        index: 0,
+        expected: "a non-negative integer",
+        got: DataType::Int(-1),
+      }),
+      <(u64, Vec<DataType>)>::try_from(reply)
+    );
+
+    assert_eq!(
+      Err(ConversionError::NotAnArray(DataTypeKind::Null)),
+      <(String, Vec<u8>)>::try_from(DataType::Null)
+    );
+  }
+}
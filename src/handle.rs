@@ -0,0 +1,224 @@
+/// Sharing a single connection between many tasks.
+///
+/// A [Redis] client needs `&mut self` to send a command, so sharing it
+/// between tasks would require wrapping it in a mutex, where a task holding the lock
+/// blocks every other task and tasks are not guaranteed to get the lock in order.
+///
+/// Instead, a background task owns the client and tasks submit commands to it
+/// through a channel. Commands are sent in the order they are submitted and each
+/// reply is delivered back to the task that submitted the command.
+///
+/// # Examples
+///
+/// ```terminal
+/// let handle = Redis::connect("127.0.0.1:6379").await?.into_handle();
+///
+/// let other_handle = handle.clone();
+/// tokio::spawn(async move { other_handle.send_command(Command::new("PING")).await });
+///
+/// handle.send_command(Command::new("PING")).await?;
+/// ```
+///
+/// While no command is being sent, the background task keeps reading from the connection,
+/// so it notices right away when the server closes it, see [RedisHandle::connection_closed].
+use std::future::Future;
+
+use miette::Result;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{mpsc, oneshot, watch};
+
+use crate::command::Command;
+use crate::redis::{Redis, RedisError, Reply};
+
+/// How many commands can be waiting to be sent before tasks submitting commands have to wait.
+const QUEUE_CAPACITY: usize = 1024;
+
+/// A command submitted to the background task and where to send its reply.
+struct Request {
+  command: Command,
+  reply: oneshot::Sender<Result<Reply>>,
+}
+
+/// A cloneable handle to a connection owned by a background task.
+#[derive(Debug, Clone)]
+pub struct RedisHandle {
+  requests: mpsc::Sender<Request>,
+  /// Set to true by the background task once the connection is closed.
+  closed: watch::Receiver<bool>,
+}
+
+impl<S> Redis<S>
+where
+  S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+  /// Moves the client to a background task and returns a handle to it.
+  ///
+  /// The background task stops when every handle has been dropped.
+  pub fn into_handle(mut self) -> RedisHandle {
+    let (sender, mut receiver) = mpsc::channel::<Request>(QUEUE_CAPACITY);
+    let (closed_sender, closed) = watch::channel(false);
+
+    tokio::spawn(async move {
+      loop {
+        tokio::select! {
+          request = receiver.recv() => {
+            let request = match request {
+              Some(request) => request,
+              None => break,
+            };
+
+            let reply = self.send_command(request.command).await;
+
+            let connection_closed = matches!(
+              reply.as_ref().map_err(|err| err.downcast_ref::<RedisError>()),
+              Err(Some(RedisError::ConnectionClosed))
+            );
+
+            // The task that submitted the command may not be waiting for the reply anymore.
+            let _ = request.reply.send(reply);
+
+            if connection_closed {
+              break;
+            }
+          }
+          // Nothing is expected from the server between commands, except for Pushes,
+          // which are kept in the buffer until the next command reads them.
+          result = self.fill_buffer() => {
+            if result.is_err() {
+              break;
+            }
+          }
+        }
+      }
+
+      let _ = closed_sender.send(true);
+    });
+
+    RedisHandle {
+      requests: sender,
+      closed,
+    }
+  }
+}
+
+impl RedisHandle {
+  /// Sends a command through the shared connection and waits for its reply.
+  pub async fn send_command(&self, command: Command) -> Result<Reply> {
+    let (sender, receiver) = oneshot::channel();
+
+    self
+      .requests
+      .send(Request {
+        command,
+        reply: sender,
+      })
+      .await
+      .map_err(|_| RedisError::ConnectionClosed)?;
+
+    receiver.await.map_err(|_| RedisError::ConnectionClosed)?
+  }
+
+  /// Returns a future that resolves once the connection is closed, when the server closes it
+  /// or reading from it fails, without having to send a command to find out.
+  ///
+  /// Commands sent through the handle after that fail with [RedisError::ConnectionClosed].
+  pub fn connection_closed(&self) -> impl Future<Output = ()> {
+    let mut closed = self.closed.clone();
+
+    async move {
+      while !*closed.borrow() {
+        // The background task is gone, and the connection with it.
+        if closed.changed().await.is_err() {
+          return;
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::data_type::DataType;
+  use std::time::Duration;
+  use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+  const TEST_REDIS_IP: &str = "127.0.0.1:6380";
+
+  #[tokio::test]
+  async fn concurrent_commands() -> Result<()> {
+    let handle = Redis::connect(TEST_REDIS_IP).await?.into_handle();
+
+    let tasks: Vec<_> = (0..100)
+      .map(|i| {
+        let handle = handle.clone();
+
+        tokio::spawn(async move {
+          let message = format!("message {}", i);
+
+          let reply = handle
+            .send_command(Command::new("ECHO").arg(&message))
+            .await
+            .unwrap();
+
+          assert_eq!(Reply::Ok(DataType::BulkString(message.into_bytes())), reply);
+        })
+      })
+      .collect();
+
+    for task in tasks {
+      task.await.unwrap();
+    }
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn connection_closed() -> Result<()> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+
+    let (close, closed_by_server) = oneshot::channel::<()>();
+
+    let server = tokio::spawn(async move {
+      let (mut socket, _) = listener.accept().await.unwrap();
+
+      let mut buffer = [0; 64];
+      assert!(socket.read(&mut buffer).await.unwrap() > 0);
+      socket.write_all(b"+PONG\r\n").await.unwrap();
+
+      closed_by_server.await.unwrap();
+    });
+
+    let handle = Redis::connect(&addr).await?.into_handle();
+
+    assert_eq!(
+      Reply::Ok(DataType::SimpleString(String::from("PONG"))),
+      handle.send_command(Command::new("PING")).await?
+    );
+
+    let connection_closed = handle.connection_closed();
+    tokio::pin!(connection_closed);
+
+    assert!(
+      tokio::time::timeout(Duration::from_millis(50), &mut connection_closed)
+        .await
+        .is_err()
+    );
+
+    close.send(()).unwrap();
+    server.await.unwrap();
+
+    tokio::time::timeout(Duration::from_secs(1), connection_closed)
+      .await
+      .expect("the connection was closed by the server");
+
+    let err = handle.send_command(Command::new("PING")).await.unwrap_err();
+    assert_eq!(
+      Some(&RedisError::ConnectionClosed),
+      err.downcast_ref::<RedisError>()
+    );
+
+    Ok(())
+  }
+}
@@ -0,0 +1,212 @@
+/// Parsing replies where the same strings repeat many times, like the field names
+/// of the records returned by HGETALL or by a Lua script, with [parse_interned].
+///
+/// Strings are shared instead of copied: every Simple String and Bulk String with the same bytes
+/// in a reply points to the same [Arc], so a field name that appears in every record
+/// is allocated once per reply instead of once per record.
+///
+/// Interning only pays off when strings repeat, since every string is hashed to find
+/// its copy, see `cargo run --release --example intern_bench`.
+///
+/// # Examples
+///
+/// ```terminal
+/// "*4\r\n$4\r\nname\r\n$3\r\nfoo\r\n$4\r\nname\r\n$3\r\nbar\r\n"
+///   -> Array([BulkString(name), BulkString(foo), BulkString(name), BulkString(bar)])
+///      where both "name" point to the same bytes
+/// ```
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::data_type::DataType;
+use crate::resp::{self, Event, ParseIter, ParserError};
+
+/// A value parsed with [parse_interned], a [DataType] whose strings are shared.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InternedValue {
+  SimpleString(Arc<[u8]>),
+  Error(String),
+  Int(i64),
+  Double(f64),
+  /// A Bulk String or a RESP3 Verbatim String, without its format.
+  BulkString(Arc<[u8]>),
+  Boolean(bool),
+  BigNumber(String),
+  Array(Vec<InternedValue>),
+  Map(Vec<(InternedValue, InternedValue)>),
+  Set(Vec<InternedValue>),
+  Push(Vec<InternedValue>),
+  Null,
+}
+
+impl From<InternedValue> for DataType {
+  /// Copies the shared strings into a [DataType], which is what [resp::parse] returns for the same input.
+  fn from(value: InternedValue) -> Self {
+    let all = |values: Vec<InternedValue>| values.into_iter().map(DataType::from).collect();
+
+    match value {
+      InternedValue::SimpleString(string) => {
+        DataType::SimpleString(String::from_utf8_lossy(&string).to_string())
+      }
+      InternedValue::Error(message) => DataType::Error(message),
+      InternedValue::Int(int) => DataType::Int(int),
+      InternedValue::Double(double) => DataType::Double(double),
+      InternedValue::BulkString(bytes) => DataType::BulkString(bytes.to_vec()),
+      InternedValue::Boolean(boolean) => DataType::Boolean(boolean),
+      InternedValue::BigNumber(number) => DataType::BigNumber(number),
+      InternedValue::Array(elements) => DataType::Array(all(elements)),
+      InternedValue::Map(entries) => DataType::Map(
+        entries
+          .into_iter()
+          .map(|(key, value)| (DataType::from(key), DataType::from(value)))
+          .collect(),
+      ),
+      InternedValue::Set(elements) => DataType::Set(all(elements)),
+      InternedValue::Push(elements) => DataType::Push(all(elements)),
+      InternedValue::Null => DataType::Null,
+    }
+  }
+}
+
+/// Parses the value at the start of `input` like [resp::parse] does,
+/// sharing the strings that appear more than once instead of copying each of them.
+///
+/// Attributes are skipped, like [resp::parse] does.
+pub fn parse_interned(input: &[u8]) -> Result<InternedValue, ParserError> {
+  Interner {
+    input,
+    events: resp::parse_iter(input),
+    strings: HashMap::new(),
+  }
+  .value()
+}
+
+/// Builds an [InternedValue] from the events of the input, remembering the strings seen so far.
+struct Interner<'a> {
+  input: &'a [u8],
+  events: ParseIter<'a>,
+  /// The strings seen so far, keyed by their bytes in the input.
+  strings: HashMap<&'a [u8], Arc<[u8]>>,
+}
+
+impl<'a> Interner<'a> {
+  fn value(&mut self) -> Result<InternedValue, ParserError> {
+    let event = match self.events.next() {
+      Some(event) => event?,
+      None => {
+        return Err(ParserError::UnexpectedEndOfInput {
+          src: String::from_utf8_lossy(self.input).to_string(),
+          span: (self.input.len(), 0).into(),
+        })
+      }
+    };
+
+    Ok(match event {
+      Event::SimpleString(string) => InternedValue::SimpleString(self.intern(string)),
+      Event::Error(message) => InternedValue::Error(String::from_utf8_lossy(message).to_string()),
+      Event::Int(int) => InternedValue::Int(int),
+      Event::Double(double) => InternedValue::Double(double),
+      Event::BulkStringRef(bytes) => InternedValue::BulkString(self.intern(bytes)),
+      Event::Boolean(boolean) => InternedValue::Boolean(boolean),
+      Event::BigNumber(number) => {
+        InternedValue::BigNumber(String::from_utf8_lossy(number).to_string())
+      }
+      Event::Null => InternedValue::Null,
+      Event::ArrayStart(len) => InternedValue::Array(self.values(len)?),
+      Event::SetStart(len) => InternedValue::Set(self.values(len)?),
+      Event::PushStart(len) => InternedValue::Push(self.values(len)?),
+      Event::MapStart(len) => {
+        let mut entries = Vec::with_capacity(len.min(self.input.len()));
+
+        for _ in 0..len {
+          entries.push((self.value()?, self.value()?));
+        }
+
+        InternedValue::Map(entries)
+      }
+      // An Attribute is followed by the value it describes.
+      Event::AttributeStart(len) => {
+        self.values(len * 2)?;
+        return self.value();
+      }
+    })
+  }
+
+  fn values(&mut self, len: usize) -> Result<Vec<InternedValue>, ParserError> {
+    // The length comes from the input, every element takes at least one byte of it.
+    let mut values = Vec::with_capacity(len.min(self.input.len()));
+
+    for _ in 0..len {
+      values.push(self.value()?);
+    }
+
+    Ok(values)
+  }
+
+  /// Returns the shared copy of `bytes`, making one if it's the first time they are seen.
+  fn intern(&mut self, bytes: &'a [u8]) -> Arc<[u8]> {
+    Arc::clone(
+      self
+        .strings
+        .entry(bytes)
+        .or_insert_with(|| Arc::from(bytes)),
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn same_value_as_parse() {
+    let tests = vec![
+      "+OK\r\n",
+      "-ERR unknown command\r\n",
+      ":1000\r\n",
+      "$-1\r\n",
+      "*4\r\n$4\r\nname\r\n$3\r\nfoo\r\n$4\r\nname\r\n$3\r\nbar\r\n",
+      "*2\r\n*2\r\n+id\r\n:1\r\n*2\r\n+id\r\n:2\r\n",
+      "%2\r\n+first\r\n,1.5\r\n+second\r\n#t\r\n",
+      "~2\r\n(12345678901234567890\r\n_\r\n",
+      ">2\r\n$7\r\nmessage\r\n=8\r\ntxt:news\r\n",
+      "|1\r\n+ttl\r\n:3600\r\n$5\r\nvalue\r\n",
+    ];
+
+    for input in tests {
+      assert_eq!(
+        resp::parse(input.as_bytes().to_vec()),
+        parse_interned(input.as_bytes()).map(DataType::from),
+        "input: {:?}",
+        input
+      );
+    }
+
+    assert!(matches!(
+      parse_interned(b"*2\r\n$4\r\nname\r\n"),
+      Err(ParserError::UnexpectedEndOfInput { .. })
+    ));
+  }
+
+  #[test]
+  fn identical_strings_share_storage() {
+    let value =
+      parse_interned(b"*4\r\n$4\r\nname\r\n$3\r\nfoo\r\n$4\r\nname\r\n+name\r\n").unwrap();
+
+    let strings: Vec<Arc<[u8]>> = match value {
+      InternedValue::Array(elements) => elements
+        .into_iter()
+        .map(|element| match element {
+          InternedValue::BulkString(bytes) | InternedValue::SimpleString(bytes) => bytes,
+          element => panic!("unexpected element {:?}", element),
+        })
+        .collect(),
+      value => panic!("unexpected value {:?}", value),
+    };
+
+    assert!(Arc::ptr_eq(&strings[0], &strings[2]));
+    assert!(Arc::ptr_eq(&strings[0], &strings[3]));
+    assert!(!Arc::ptr_eq(&strings[0], &strings[1]));
+    assert_eq!(b"name", &*strings[0]);
+  }
+}
@@ -15,119 +15,7576 @@
 /// client: "*2\r\n$4\r\nLLEN\r\n$6mylist\r\n" -- the request
 /// server: ":48293\r\n"                       -- the reply
 /// ```
-use miette::{IntoDiagnostic, Result};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use miette::{Diagnostic, IntoDiagnostic, Result};
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpStream;
-use tracing::info;
+use tracing::{info, info_span, Instrument};
+
+use crate::command::Command;
+#[cfg(feature = "compression")]
+use crate::compression::{self, Compression};
+use crate::data_type::{ConversionError, DataType};
+use crate::resp::{self, Protocol};
+
+pub struct Redis<S = TcpStream> {
+  stream: S,
+  /// The address the client connected to, used to reconnect.
+  addr: Option<String>,
+  /// Bytes read from the stream that are not part of a reply that has been returned yet.
+  buffer: Vec<u8>,
+  /// Hooks called after every command, see [Redis::on_command].
+  hooks: Vec<CommandHook>,
+  /// Handlers called with every Push sent by the server, see [Redis::on_push].
+  push_handlers: Vec<PushHandler>,
+  /// The protocol replies are parsed with, see [Redis::set_protocol].
+  protocol: Protocol,
+  /// Whether to switch to RESP3 when a reply uses a RESP3 type, see [Redis::auto_detect_protocol].
+  detect_protocol: bool,
+  /// The protocol switched to with [Redis::hello], switched to again by [Redis::reconnect].
+  hello_protocol: Option<Protocol>,
+  /// How many channels the client is subscribed to, see [Redis::subscribe].
+  subscriptions: usize,
+  /// The channels the client is subscribed to, subscribed to again by [Redis::reconnect].
+  channels: Vec<String>,
+  /// The username and password sent with [Redis::auth], sent again by [Redis::reconnect].
+  credentials: Option<(Option<String>, String)>,
+  /// The database selected with [Redis::select], selected again by [Redis::reconnect].
+  db: u64,
+  /// Messages read while looking for something else, like the reply to a command,
+  /// that have not been returned by [Redis::next_message] yet.
+  messages: VecDeque<Message>,
+  /// The commands the server knows about, see [Redis::refresh_command_table].
+  command_table: Option<CommandTable>,
+  /// What the replies parsed so far were, see [Redis::enable_parse_stats].
+  parse_stats: Option<resp::ParseStats>,
+  /// The types of the keys read with [Redis::get_typed], see [Redis::enable_type_cache].
+  type_cache: Option<TypeCache>,
+  /// How many elements of an [ElementStream] dropped before the end are left to skip
+  /// before the next reply is read.
+  unread_elements: usize,
+  /// How long to keep retrying commands while the server is loading, see [Redis::retry_while_loading].
+  loading_timeout: Option<Duration>,
+  /// The version of the server, once it's known, see [Redis::server_version].
+  server_version: Option<(u64, u64, u64)>,
+  /// The longest string [Redis::setrange] can make, see [Redis::set_max_string_len].
+  max_string_len: Option<u64>,
+  /// How the values written with [Redis::set] are compressed, see [Redis::enable_compression].
+  #[cfg(feature = "compression")]
+  pub(crate) compression: Option<Compression>,
+}
+
+type CommandHook = Box<dyn Fn(&CommandMetrics) + Send + Sync>;
+
+type PushHandler = Box<dyn Fn(Vec<DataType>) + Send>;
+
+impl<S: fmt::Debug> fmt::Debug for Redis<S> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let mut debug = f.debug_struct("Redis");
+
+    debug
+      .field("stream", &self.stream)
+      .field("addr", &self.addr)
+      .field("buffer", &String::from_utf8_lossy(&self.buffer))
+      .field("hooks", &self.hooks.len())
+      .field("push_handlers", &self.push_handlers.len())
+      .field("protocol", &self.protocol)
+      .field("detect_protocol", &self.detect_protocol)
+      .field("hello_protocol", &self.hello_protocol)
+      .field("subscriptions", &self.subscriptions)
+      .field("channels", &self.channels)
+      // The password is not shown.
+      .field(
+        "credentials",
+        &self.credentials.as_ref().map(|(username, _)| username),
+      )
+      .field("db", &self.db)
+      .field("messages", &self.messages.len())
+      .field(
+        "command_table",
+        &self.command_table.as_ref().map(CommandTable::len),
+      )
+      .field("parse_stats", &self.parse_stats)
+      .field("type_cache", &self.type_cache)
+      .field("unread_elements", &self.unread_elements)
+      .field("loading_timeout", &self.loading_timeout)
+      .field("server_version", &self.server_version)
+      .field("max_string_len", &self.max_string_len);
+
+    #[cfg(feature = "compression")]
+    debug.field("compression", &self.compression);
+
+    debug.finish()
+  }
+}
+
+/// What happened when a command was sent to the server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandMetrics {
+  /// The command name in uppercase, like "LLEN".
+  pub command: String,
+  /// How many bytes were written to the server.
+  pub bytes_sent: usize,
+  /// How many bytes were read from the server.
+  pub bytes_received: usize,
+  /// How long it took from sending the command to parsing its reply.
+  pub duration: Duration,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Reply {
+  Error(String),
+  Ok(DataType),
+}
+
+impl Reply {
+  /// Returns the data sent by the server or an error if the server replied with an error.
+  fn into_data(self) -> Result<DataType, RedisError> {
+    match self {
+      Reply::Error(message) => Err(server_error(message)),
+      Reply::Ok(data_type) => Ok(data_type),
+    }
+  }
+
+  /// Returns true if this is the "+QUEUED" reply sent to the commands of a transaction.
+  pub fn is_queued(&self) -> bool {
+    matches!(self, Reply::Ok(DataType::SimpleString(status)) if status == "QUEUED")
+  }
+
+  /// Formats the reply the way redis-cli prints it.
+  ///
+  /// # Examples
+  ///
+  /// ```terminal
+  /// 1) "foo"
+  /// 2) 1) (integer) 1
+  ///    2) (nil)
+  /// ```
+  pub fn to_cli_string(&self) -> String {
+    let mut output = match self {
+      Reply::Error(message) => cli_format(&DataType::Error(message.clone()), ""),
+      Reply::Ok(data_type) => cli_format(data_type, ""),
+    };
+
+    // Every line ends with "\n", including the last one.
+    output.pop();
+
+    output
+  }
+}
+
+impl fmt::Display for Reply {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.to_cli_string())
+  }
+}
+
+#[derive(Debug, PartialEq, Diagnostic, Error)]
+pub enum RedisError {
+  #[error("the server replied with an error: {0}")]
+  #[diagnostic()]
+  Server(String),
+  #[error("unexpected reply: {0:?}")]
+  #[diagnostic()]
+  UnexpectedReply(DataType),
+  #[error("the connection is closed")]
+  #[diagnostic()]
+  ConnectionClosed,
+  #[error("invalid argument: {0}")]
+  #[diagnostic()]
+  InvalidArgument(String),
+  #[error("could not connect to any address: {}", .0.join(", "))]
+  #[diagnostic()]
+  NoAddressConnected(Vec<String>),
+  #[error("{0} can't be sent while subscribed to a channel")]
+  #[diagnostic(help("RESP2 only allows (UN)SUBSCRIBE, P(UN)SUBSCRIBE, S(UN)SUBSCRIBE, PING, RESET and QUIT while subscribed, negotiate RESP3 with HELLO 3 to send any command"))]
+  NotAllowedWhileSubscribed(String),
+  #[error("not subscribed to any channel")]
+  #[diagnostic()]
+  NotSubscribed,
+  #[error("wrong number of arguments for '{0}' command")]
+  #[diagnostic()]
+  WrongArity(String),
+  #[error("can't reconnect a client that was not created with Redis::connect")]
+  #[diagnostic()]
+  NoAddressToReconnect,
+  #[error("authentication failed: {0}")]
+  #[diagnostic(help("check the username and password, or send AUTH credentials with HELLO if the server requires them"))]
+  AuthFailed(String),
+  #[error("the server does not support the protocol: {0}")]
+  #[diagnostic()]
+  UnsupportedProtocol(String),
+  #[error("the server is loading its dataset: {0}")]
+  #[diagnostic(help("wait until the server finishes loading, or retry automatically with Redis::retry_while_loading"))]
+  Loading(String),
+  #[error("unexpected reply: {0}")]
+  #[diagnostic()]
+  UnexpectedShape(#[from] ConversionError),
+  #[error("no such key: {0}")]
+  #[diagnostic()]
+  NoSuchKey(String),
+  /// Only returned with the compression feature, see [Redis::enable_compression].
+  #[error("the compressed value is corrupt: {0}")]
+  #[diagnostic()]
+  CorruptCompressedValue(String),
+}
+
+/// The commands a RESP2 connection accepts while it's subscribed to a channel.
+const ALLOWED_WHILE_SUBSCRIBED: [&str; 9] = [
+  "SUBSCRIBE",
+  "UNSUBSCRIBE",
+  "PSUBSCRIBE",
+  "PUNSUBSCRIBE",
+  "SSUBSCRIBE",
+  "SUNSUBSCRIBE",
+  "PING",
+  "RESET",
+  "QUIT",
+];
+
+/// How long to wait before retrying the first command that failed because the server was loading,
+/// doubled after each retry up to [MAX_LOADING_BACKOFF], see [Redis::retry_while_loading].
+const INITIAL_LOADING_BACKOFF: Duration = Duration::from_millis(10);
+
+const MAX_LOADING_BACKOFF: Duration = Duration::from_secs(1);
+
+/// How many fields [Redis::hgetall_smart] asks for with each HSCAN.
+const HSCAN_COUNT: u64 = 1000;
+
+/// The commands that read a key without changing it, which leave the type of the key cached,
+/// see [Redis::enable_type_cache].
+const TYPE_CACHE_READS: [&str; 6] = ["TYPE", "GET", "LRANGE", "SMEMBERS", "HGETALL", "ZRANGE"];
+
+/// The commands that may change every key, which clear the type cache.
+const TYPE_CACHE_CLEARS: [&str; 5] = ["FLUSHALL", "FLUSHDB", "SWAPDB", "SELECT", "RESET"];
+
+/// The types of keys, each one cached until `ttl` passes or a command that may write to the key is sent.
+#[derive(Debug)]
+struct TypeCache {
+  ttl: Duration,
+  types: HashMap<Vec<u8>, (String, Instant)>,
+}
+
+impl TypeCache {
+  fn get(&self, key: &str) -> Option<String> {
+    match self.types.get(key.as_bytes()) {
+      Some((key_type, cached_at)) if cached_at.elapsed() < self.ttl => Some(key_type.clone()),
+      _ => None,
+    }
+  }
+
+  /// Forgets the types of the keys the encoded `command` named `name` may write to.
+  ///
+  /// Every argument is assumed to be a key, forgetting a type that is still right
+  /// only costs a TYPE round trip.
+  fn invalidate(&mut self, name: &str, command: &[u8]) {
+    let name = name.to_uppercase();
+
+    if self.types.is_empty() || TYPE_CACHE_READS.contains(&name.as_str()) {
+      return;
+    }
+
+    if TYPE_CACHE_CLEARS.contains(&name.as_str()) {
+      self.types.clear();
+      return;
+    }
+
+    match resp::parse(command.to_vec()) {
+      Ok(DataType::Array(args)) => {
+        for arg in args.iter().skip(1) {
+          if let DataType::BulkString(arg) = arg {
+            self.types.remove(arg);
+          }
+        }
+      }
+      // Commands sent inline are not parsed.
+      _ => self.types.clear(),
+    }
+  }
+}
+
+/// Bit offsets must be less than 2^32 because strings are limited to 512 MB.
+const MAX_BIT_OFFSET: u64 = 1 << 32;
+
+/// The largest string a Redis server stores by default: 512 MB, see [Redis::set_max_string_len].
+const MAX_STRING_LEN: u64 = 512 * 1024 * 1024;
+
+/// The unit of the indexes of a range, see [Redis::bitcount].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitUnit {
+  Byte,
+  Bit,
+}
+
+impl BitUnit {
+  fn as_str(&self) -> &'static str {
+    match self {
+      BitUnit::Byte => "BYTE",
+      BitUnit::Bit => "BIT",
+    }
+  }
+}
+
+/// The client side caching state of a connection, see [Redis::client_trackinginfo].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackingInfo {
+  /// Like "on", "bcast", "optin", "optout", "caching-yes", "noloop" or "broken_redirect",
+  /// or only "off" when tracking is off.
+  pub flags: Vec<String>,
+  /// The id of the client invalidation messages are redirected to,
+  /// 0 when they are not redirected and -1 when tracking is off.
+  pub redirect: i64,
+  /// The key prefixes tracked in broadcasting mode.
+  pub prefixes: Vec<Vec<u8>>,
+}
+
+/// Information about a stream, see [Redis::xinfo_stream].
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamInfo {
+  /// The number of entries in the stream.
+  pub length: u64,
+  /// The id of the last entry added to the stream.
+  pub last_generated_id: String,
+  /// The number of consumer groups of the stream.
+  pub groups: u64,
+  pub first_entry: Option<StreamEntry>,
+  pub last_entry: Option<StreamEntry>,
+}
+
+/// An entry of a stream.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamEntry {
+  pub id: String,
+  pub fields: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+/// The messages of a consumer group that were delivered but not acknowledged yet,
+/// see [Redis::xpending_summary].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PendingSummary {
+  /// The number of pending messages.
+  pub count: u64,
+  /// The smallest id of the pending messages, `None` if there are none.
+  pub min_id: Option<String>,
+  /// The greatest id of the pending messages, `None` if there are none.
+  pub max_id: Option<String>,
+  /// Each consumer with pending messages and how many it has.
+  pub consumers: Vec<(String, u64)>,
+}
+
+/// A message that was delivered to a consumer but not acknowledged yet, see [Redis::xpending_extended].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingMessage {
+  pub id: String,
+  /// The consumer the message was delivered to.
+  pub consumer: String,
+  /// How long ago the message was last delivered.
+  pub idle: Duration,
+  /// How many times the message was delivered.
+  pub deliveries: u64,
+}
+
+/// The id of a new stream entry, see [Redis::xadd].
+#[derive(Debug, Clone, PartialEq)]
+pub enum XId {
+  /// Lets the server generate an id from the current time, "*".
+  Auto,
+  /// An id like "1526919030474-55", which must be greater than the id of the last entry.
+  Explicit(String),
+}
+
+impl XId {
+  fn as_str(&self) -> &str {
+    match self {
+      XId::Auto => "*",
+      XId::Explicit(id) => id,
+    }
+  }
+}
+
+/// What the server says about itself in reply to HELLO, see [Redis::hello].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerHello {
+  /// The name of the server, "redis".
+  pub server: String,
+  /// The version of the server, like "7.0.0".
+  pub version: String,
+  /// The protocol the connection switched to.
+  pub protocol: Protocol,
+  /// The id of the connection, the one CLIENT ID returns.
+  pub id: i64,
+  /// "standalone", "sentinel" or "cluster".
+  pub mode: String,
+  /// "master" or "replica".
+  pub role: String,
+}
+
+/// A command that took longer than the slowlog threshold, see [Redis::slowlog_get].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlowlogEntry {
+  /// Ids are unique and increase with each entry, they are not reset when the log is.
+  pub id: i64,
+  /// The unix time, in seconds, when the command was run.
+  pub timestamp: i64,
+  /// How long the command took to run.
+  pub duration: Duration,
+  /// The name and the arguments of the command, which the server may have truncated.
+  pub args: Vec<Vec<u8>>,
+  /// The address of the client that sent the command, empty before Redis 4.0.
+  pub client_addr: String,
+  /// The name of the client that sent the command, empty if it has none or before Redis 4.0.
+  pub client_name: String,
+}
+
+/// The options of the SORT command, see [Redis::sort].
+///
+/// Options can be set in any order, they are sent in the order the server expects.
+///
+/// # Examples
+///
+/// ```terminal
+/// Sort::new().by("weight_*").limit(0, 10).get("object_*").desc().alpha()
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Sort {
+  by: Option<String>,
+  limit: Option<(i64, i64)>,
+  get: Vec<String>,
+  descending: bool,
+  alpha: bool,
+}
+
+impl Sort {
+  /// Sorts elements as numbers in ascending order.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Sorts elements using the values of the keys that match `pattern`,
+  /// where the first "*" is replaced with each element.
+  pub fn by(mut self, pattern: &str) -> Self {
+    self.by = Some(pattern.to_owned());
+    self
+  }
+
+  /// Returns `count` elements, skipping the first `offset` elements.
+  pub fn limit(mut self, offset: i64, count: i64) -> Self {
+    self.limit = Some((offset, count));
+    self
+  }
+
+  /// Returns the values of the keys that match `pattern` instead of the elements.
+  ///
+  /// Can be used more than once, and "#" returns the element itself.
+  pub fn get(mut self, pattern: &str) -> Self {
+    self.get.push(pattern.to_owned());
+    self
+  }
+
+  pub fn asc(mut self) -> Self {
+    self.descending = false;
+    self
+  }
+
+  pub fn desc(mut self) -> Self {
+    self.descending = true;
+    self
+  }
+
+  /// Sorts elements lexicographically instead of as numbers.
+  pub fn alpha(mut self) -> Self {
+    self.alpha = true;
+    self
+  }
+
+  /// Appends the options to `command`.
+  fn apply(self, mut command: Command) -> Command {
+    if let Some(pattern) = self.by {
+      command = command.arg("BY").arg(pattern);
+    }
+
+    if let Some((offset, count)) = self.limit {
+      command = command.arg("LIMIT").arg_int(offset).arg_int(count);
+    }
+
+    for pattern in self.get {
+      command = command.arg("GET").arg(pattern);
+    }
+
+    if self.descending {
+      command = command.arg("DESC");
+    }
+
+    if self.alpha {
+      command = command.arg("ALPHA");
+    }
+
+    command
+  }
+}
+
+/// A message published to a channel the client is subscribed to, see [Redis::next_message].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+  pub channel: Vec<u8>,
+  pub payload: Vec<u8>,
+}
+
+/// What GETEX does to the expiry of the key it reads, see [Redis::getex].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GetExExpiry {
+  /// Leaves the expiry as it is.
+  Keep,
+  /// Removes the expiry.
+  Persist,
+  /// Expires the key in this many seconds.
+  Ex(u64),
+  /// Expires the key in this many milliseconds.
+  Px(u64),
+  /// Expires the key at this unix time, in seconds.
+  ExAt(u64),
+  /// Expires the key at this unix time, in milliseconds.
+  PxAt(u64),
+}
+
+impl GetExExpiry {
+  /// Appends the option to `command`.
+  fn apply(self, command: Command) -> Command {
+    match self {
+      GetExExpiry::Keep => command,
+      GetExExpiry::Persist => command.arg("PERSIST"),
+      GetExExpiry::Ex(seconds) => command.arg("EX").arg_int(seconds),
+      GetExExpiry::Px(milliseconds) => command.arg("PX").arg_int(milliseconds),
+      GetExExpiry::ExAt(timestamp) => command.arg("EXAT").arg_int(timestamp),
+      GetExExpiry::PxAt(timestamp) => command.arg("PXAT").arg_int(timestamp),
+    }
+  }
+}
+
+/// The options of the LCS command, see [Redis::lcs].
+///
+/// # Examples
+///
+/// ```terminal
+/// LcsOptions::new().idx().min_match_len(4).with_match_len()
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LcsOptions {
+  len: bool,
+  idx: bool,
+  min_match_len: Option<u64>,
+  with_match_len: bool,
+}
+
+impl LcsOptions {
+  /// Returns the longest common subsequence itself.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Returns the length of the longest common subsequence instead of the subsequence.
+  pub fn len(mut self) -> Self {
+    self.len = true;
+    self
+  }
+
+  /// Returns the position of each match in both strings instead of the subsequence.
+  pub fn idx(mut self) -> Self {
+    self.idx = true;
+    self
+  }
+
+  /// Only returns matches of at least `length` characters, used with [LcsOptions::idx].
+  pub fn min_match_len(mut self, length: u64) -> Self {
+    self.min_match_len = Some(length);
+    self
+  }
+
+  /// Returns the length of each match too, used with [LcsOptions::idx].
+  pub fn with_match_len(mut self) -> Self {
+    self.with_match_len = true;
+    self
+  }
+
+  /// Appends the options to `command`.
+  fn apply(self, mut command: Command) -> Command {
+    if self.len {
+      command = command.arg("LEN");
+    }
+
+    if self.idx {
+      command = command.arg("IDX");
+    }
+
+    if let Some(length) = self.min_match_len {
+      command = command.arg("MINMATCHLEN").arg_int(length);
+    }
+
+    if self.with_match_len {
+      command = command.arg("WITHMATCHLEN");
+    }
+
+    command
+  }
+}
+
+/// The reply of LCS, which depends on its options, see [Redis::lcs].
+#[derive(Debug, Clone, PartialEq)]
+pub enum LcsResult {
+  /// The longest common subsequence.
+  String(Vec<u8>),
+  /// The length of the longest common subsequence, when [LcsOptions::len] is used.
+  Len(u64),
+  /// The matches that make up the longest common subsequence, when [LcsOptions::idx] is used.
+  Matches { matches: Vec<LcsMatch>, len: u64 },
+}
+
+/// A match of LCS, where the ranges are the positions of the match in each string, both inclusive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LcsMatch {
+  pub key1: (u64, u64),
+  pub key2: (u64, u64),
+  /// The length of the match, when [LcsOptions::with_match_len] is used.
+  pub len: Option<u64>,
+}
+
+/// The options of the ZADD command, see [Redis::zadd_opts].
+///
+/// # Examples
+///
+/// ```terminal
+/// ZAddOptions::new().xx().gt().ch()
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ZAddOptions {
+  nx: bool,
+  xx: bool,
+  gt: bool,
+  lt: bool,
+  ch: bool,
+  incr: bool,
+}
+
+impl ZAddOptions {
+  /// Adds new members and updates the scores of existing ones.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Only adds new members, never updates existing ones.
+  pub fn nx(mut self) -> Self {
+    self.nx = true;
+    self
+  }
+
+  /// Only updates existing members, never adds new ones.
+  pub fn xx(mut self) -> Self {
+    self.xx = true;
+    self
+  }
+
+  /// Only updates existing members if the new score is greater than the current one.
+  pub fn gt(mut self) -> Self {
+    self.gt = true;
+    self
+  }
+
+  /// Only updates existing members if the new score is less than the current one.
+  pub fn lt(mut self) -> Self {
+    self.lt = true;
+    self
+  }
+
+  /// Counts the members whose score changed too, instead of only the members added.
+  pub fn ch(mut self) -> Self {
+    self.ch = true;
+    self
+  }
+
+  /// Increments the score of the member instead of setting it, like ZINCRBY.
+  /// Only one member can be given.
+  pub fn incr(mut self) -> Self {
+    self.incr = true;
+    self
+  }
+
+  /// Returns an error if the options can't be used together.
+  fn validate(&self, members: usize) -> Result<(), RedisError> {
+    let error = if self.nx && self.xx {
+      "NX and XX can't be used together"
+    } else if self.gt && self.lt {
+      "GT and LT can't be used together"
+    } else if self.nx && (self.gt || self.lt) {
+      "NX can't be used with GT or LT"
+    } else if self.incr && members != 1 {
+      "INCR can only be used with one member"
+    } else {
+      return Ok(());
+    };
+
+    Err(RedisError::InvalidArgument(error.to_owned()))
+  }
+
+  /// Appends the options to `command`.
+  fn apply(self, mut command: Command) -> Command {
+    if self.nx {
+      command = command.arg("NX");
+    }
+
+    if self.xx {
+      command = command.arg("XX");
+    }
+
+    if self.gt {
+      command = command.arg("GT");
+    }
+
+    if self.lt {
+      command = command.arg("LT");
+    }
+
+    if self.ch {
+      command = command.arg("CH");
+    }
+
+    if self.incr {
+      command = command.arg("INCR");
+    }
+
+    command
+  }
+}
+
+/// The reply of ZADD, which depends on its options, see [Redis::zadd_opts].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ZAddResult {
+  /// How many members were added, or added and updated when [ZAddOptions::ch] is used.
+  Count(u64),
+  /// The new score of the member when [ZAddOptions::incr] is used,
+  /// or `None` if the member was not updated because of the other options.
+  Score(Option<f64>),
+}
+
+/// The elements of an Array reply, read from the connection one at a time, see [Redis::lrange_stream].
+#[derive(Debug)]
+pub struct ElementStream<'a, S> {
+  redis: &'a mut Redis<S>,
+  /// How many elements have not been read yet.
+  remaining: usize,
+}
+
+impl<'a, S> ElementStream<'a, S>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  /// Reads the next element, returning `None` once every element has been read.
+  pub async fn next(&mut self) -> Result<Option<Vec<u8>>> {
+    if self.remaining == 0 {
+      return Ok(None);
+    }
+
+    let element = self.redis.next_element().await?;
+
+    self.remaining -= 1;
+
+    Ok(Some(element))
+  }
+
+  /// Returns how many elements have not been read yet.
+  pub fn remaining(&self) -> usize {
+    self.remaining
+  }
+}
+
+impl<'a, S> Drop for ElementStream<'a, S> {
+  fn drop(&mut self) {
+    // The elements are still coming, they are skipped before the next reply is read.
+    self.redis.unread_elements += self.remaining;
+  }
+}
+
+/// The end of a list elements are popped from, see [Redis::lmpop].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListEnd {
+  Left,
+  Right,
+}
+
+impl ListEnd {
+  fn as_str(self) -> &'static str {
+    match self {
+      ListEnd::Left => "LEFT",
+      ListEnd::Right => "RIGHT",
+    }
+  }
+}
+
+/// A bound of a range of scores, see [Redis::zrangebyscore].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScoreBound {
+  /// Scores equal to the bound are in the range.
+  Inclusive(f64),
+  /// Scores equal to the bound are not in the range.
+  Exclusive(f64),
+  NegInf,
+  PosInf,
+}
+
+impl ScoreBound {
+  /// Formats the bound the way ZRANGEBYSCORE expects it, like "5", "(5" or "+inf".
+  fn to_arg(self) -> String {
+    match self {
+      ScoreBound::Inclusive(score) => score.to_string(),
+      ScoreBound::Exclusive(score) => format!("({}", score),
+      ScoreBound::NegInf => String::from("-inf"),
+      ScoreBound::PosInf => String::from("+inf"),
+    }
+  }
+}
+
+/// A bound of a range of members, compared byte by byte, see [Redis::zrangebylex].
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexBound {
+  /// Members equal to the bound are in the range.
+  Inclusive(Vec<u8>),
+  /// Members equal to the bound are not in the range.
+  Exclusive(Vec<u8>),
+  /// Smaller than every member.
+  Min,
+  /// Greater than every member.
+  Max,
+}
+
+impl LexBound {
+  /// Formats the bound the way ZRANGEBYLEX expects it, like "[a", "(a", "-" or "+".
+  fn to_arg(&self) -> Vec<u8> {
+    match self {
+      LexBound::Inclusive(member) => [b"[", member.as_slice()].concat(),
+      LexBound::Exclusive(member) => [b"(", member.as_slice()].concat(),
+      LexBound::Min => b"-".to_vec(),
+      LexBound::Max => b"+".to_vec(),
+    }
+  }
+}
+
+/// When a key expires, see [Redis::expiretime].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExpireTime {
+  /// The key does not exist.
+  NoKey,
+  /// The key exists but has no expiry.
+  NoExpiry,
+  /// The key expires at this time.
+  At(SystemTime),
+}
+
+/// The value of a key of any type, see [Redis::get_typed].
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypedValue {
+  String(Vec<u8>),
+  List(Vec<Vec<u8>>),
+  Set(Vec<Vec<u8>>),
+  /// The fields and values of a hash.
+  Hash(Vec<(Vec<u8>, Vec<u8>)>),
+  /// The members of a sorted set and their scores, from the lowest score to the highest.
+  SortedSet(Vec<(Vec<u8>, f64)>),
+}
+
+/// The rules of an ACL user, see [Redis::acl_getuser].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AclUser {
+  /// Flags like "on", "off" or "nopass".
+  pub flags: Vec<String>,
+  /// The SHA-256 hashes of the passwords of the user.
+  pub passwords: Vec<String>,
+  /// The commands the user can run, like "+@all -debug".
+  pub commands: String,
+  /// The key patterns the user can access, like "~*" or "%R~cache:*".
+  pub keys: Vec<String>,
+  /// The Pub/Sub channel patterns the user can access, like "&*".
+  pub channels: Vec<String>,
+}
+
+/// What the server knows about a command, see [Redis::refresh_command_table].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandInfo {
+  /// The command name in lowercase, like "get".
+  pub name: String,
+  /// How many arguments the command takes, counting its name.
+  /// A negative arity means at least `-arity` arguments.
+  pub arity: i64,
+  /// Flags like "readonly" or "fast".
+  pub flags: Vec<String>,
+}
+
+impl CommandInfo {
+  /// Returns true when the command can be called with `arg_count` arguments, counting its name.
+  pub fn accepts(&self, arg_count: usize) -> bool {
+    let arg_count = arg_count as i64;
+
+    if self.arity >= 0 {
+      arg_count == self.arity
+    } else {
+      arg_count >= -self.arity
+    }
+  }
+}
+
+/// The commands a server knows about, keyed by their lowercase name.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CommandTable {
+  commands: HashMap<String, CommandInfo>,
+}
+
+impl CommandTable {
+  /// Returns the command named `name`, in any case.
+  pub fn get(&self, name: &str) -> Option<&CommandInfo> {
+    self.commands.get(&name.to_lowercase())
+  }
+
+  pub fn len(&self) -> usize {
+    self.commands.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.commands.is_empty()
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = &CommandInfo> {
+    self.commands.values()
+  }
+
+  /// Fails when `command` is known and has the wrong number of arguments.
+  ///
+  /// Unknown commands are accepted, the server decides what to do with them.
+  pub fn check_arity(&self, command: &Command) -> Result<(), RedisError> {
+    match self.get(&command.name()) {
+      Some(info) if !info.accepts(command.len()) => Err(RedisError::WrongArity(info.name.clone())),
+      _ => Ok(()),
+    }
+  }
+}
+
+/// A range of hash slots, both inclusive, and the nodes that serve it, see [Redis::cluster_slots].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlotRange {
+  pub start: u16,
+  pub end: u16,
+  pub master: NodeAddr,
+  pub replicas: Vec<NodeAddr>,
+}
+
+/// Where a cluster node can be reached.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeAddr {
+  pub host: String,
+  pub port: u16,
+  /// The id of the node, which Redis sends since version 4.
+  pub id: Option<String>,
+}
+
+impl Redis {
+  pub async fn connect(ip: &str) -> Result<Self> {
+    info!(ip, "connecting");
+
+    let stream = TcpStream::connect(ip).await.into_diagnostic()?;
+
+    info!(ip, "connected");
+
+    Ok(Self {
+      addr: Some(ip.to_owned()),
+      ..Self::from_stream(stream)
+    })
+  }
+
+  /// Connects to the first address in `addrs` that accepts the connection, trying them in order.
+  ///
+  /// Returns [RedisError::NoAddressConnected] with why each address failed if none of them did.
+  pub async fn connect_any(addrs: &[&str]) -> Result<Self> {
+    let mut failures = Vec::with_capacity(addrs.len());
+
+    for addr in addrs {
+      match Self::connect(addr).await {
+        Ok(redis) => return Ok(redis),
+        Err(err) => {
+          info!(addr, %err, "could not connect");
+          failures.push(format!("{}: {}", addr, err));
+        }
+      }
+    }
+
+    Err(RedisError::NoAddressConnected(failures).into())
+  }
+
+  /// Returns the address the client connected to.
+  pub fn addr(&self) -> Option<&str> {
+    self.addr.as_deref()
+  }
+
+  /// Replaces the connection with a new one to the same address, after it was lost.
+  ///
+  /// The new connection is set up like the old one was: it's authenticated with the credentials
+  /// given to [Redis::auth], the database given to [Redis::select] is selected and the channels
+  /// the client was subscribed to are subscribed to again, in that order.
+  /// State changed by sending commands directly, like with `send("SELECT 1")`, is not restored.
+  ///
+  /// Replies that were not read from the old connection are lost.
+  pub async fn reconnect(&mut self) -> Result<()> {
+    let addr = self.addr.clone().ok_or(RedisError::NoAddressToReconnect)?;
+
+    info!(%addr, "reconnecting");
+
+    self.stream = TcpStream::connect(&addr).await.into_diagnostic()?;
+    self.buffer.clear();
+    self.subscriptions = 0;
+    self.unread_elements = 0;
+    // The server may have been upgraded while the client was disconnected.
+    self.server_version = None;
+
+    // Other clients may have changed the keys while the client was disconnected.
+    if let Some(cache) = &mut self.type_cache {
+      cache.types.clear();
+    }
+
+    match (self.hello_protocol, self.credentials.clone()) {
+      // A new connection speaks RESP2 until it's switched with HELLO again,
+      // which authenticates it in the same round trip.
+      (Some(protocol), credentials) => {
+        let auth = credentials.as_ref().map(|(username, password)| {
+          (username.as_deref().unwrap_or("default"), password.as_str())
+        });
+
+        self.hello(protocol, auth).await?;
+      }
+      (None, Some((username, password))) => {
+        self.auth(username.as_deref(), &password).await?;
+      }
+      (None, None) => {}
+    }
+
+    if self.db != 0 {
+      self.select(self.db).await?;
+    }
+
+    let channels = std::mem::take(&mut self.channels);
+
+    if !channels.is_empty() {
+      let channels: Vec<&str> = channels.iter().map(String::as_str).collect();
+      self.subscribe(&channels).await?;
+    }
+
+    info!(%addr, "reconnected");
+
+    Ok(())
+  }
+}
+
+impl<S> Redis<S>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  /// Creates a client that talks to the server through `stream`.
+  pub fn from_stream(stream: S) -> Self {
+    Self {
+      stream,
+      addr: None,
+      buffer: Vec::new(),
+      hooks: Vec::new(),
+      push_handlers: Vec::new(),
+      protocol: Protocol::Resp3,
+      detect_protocol: false,
+      hello_protocol: None,
+      subscriptions: 0,
+      channels: Vec::new(),
+      credentials: None,
+      db: 0,
+      messages: VecDeque::new(),
+      command_table: None,
+      parse_stats: None,
+      type_cache: None,
+      unread_elements: 0,
+      loading_timeout: None,
+      server_version: None,
+      max_string_len: Some(MAX_STRING_LEN),
+      #[cfg(feature = "compression")]
+      compression: None,
+    }
+  }
+
+  /// Returns the protocol replies are parsed with.
+  pub fn protocol(&self) -> Protocol {
+    self.protocol
+  }
+
+  /// Sets the protocol replies are parsed with.
+  ///
+  /// Replies are parsed as RESP3 by default, which accepts every RESP2 reply too.
+  /// With [Protocol::Resp2], a reply using a RESP3 type fails with [resp::ParserError::ProtocolMismatch].
+  pub fn set_protocol(&mut self, protocol: Protocol) {
+    self.protocol = protocol;
+    self.detect_protocol = false;
+  }
+
+  /// Parses replies as RESP2 until a reply uses a RESP3 type, like a Map or a Double,
+  /// and as RESP3 from then on.
+  ///
+  /// Useful when the protocol was not negotiated with HELLO.
+  ///
+  /// Replies that only use the types RESP2 and RESP3 share, like Simple Strings, Bulk Strings
+  /// and Arrays, look the same in both protocols, so the client keeps expecting RESP2
+  /// after them even when the server speaks RESP3. Only a RESP3 type settles the protocol.
+  pub fn auto_detect_protocol(&mut self) {
+    self.protocol = Protocol::Resp2;
+    self.detect_protocol = true;
+  }
+
+  /// Retries commands that fail with [RedisError::Loading], because the server is still loading
+  /// its dataset after starting, until they succeed or `timeout` passes since they were first sent.
+  ///
+  /// The client waits before each retry, twice as long as the previous time, starting at 10ms
+  /// and never more than 1s. Once `timeout` passes the LOADING error is returned.
+  pub fn retry_while_loading(&mut self, timeout: Duration) {
+    self.loading_timeout = Some(timeout);
+  }
+
+  /// Sets the longest string [Redis::setrange] can make without sending the command, 512 MB by default,
+  /// for servers whose proto-max-bulk-len was changed.
+  ///
+  /// With `None` the length is not checked and the server rejects strings that are too long.
+  pub fn set_max_string_len(&mut self, max_string_len: Option<u64>) {
+    self.max_string_len = max_string_len;
+  }
+
+  /// Registers a hook that is called after each command with its [CommandMetrics].
+  ///
+  /// # Examples
+  ///
+  /// ```terminal
+  /// redis.on_command(|metrics| {
+  ///   histogram.with_label_values(&[&metrics.command]).observe(metrics.duration.as_secs_f64())
+  /// });
+  /// ```
+  pub fn on_command(&mut self, hook: impl Fn(&CommandMetrics) + Send + Sync + 'static) {
+    self.hooks.push(Box::new(hook));
+  }
+
+  /// Registers a handler that is called with the elements of every Push the server sends,
+  /// like the invalidation messages of client side caching.
+  ///
+  /// Pushes are sent by RESP3 servers whenever they want, even while the client waits
+  /// for the reply to a command, so they are handed to the handlers as they are read
+  /// and the client keeps reading until it finds the reply.
+  /// Without handlers, a Push is returned as if it were the reply.
+  ///
+  /// # Examples
+  ///
+  /// ```terminal
+  /// redis.on_push(|push| println!("push: {:?}", push));
+  /// ```
+  pub fn on_push(&mut self, handler: impl Fn(Vec<DataType>) + Send + 'static) {
+    self.push_handlers.push(Box::new(handler));
+  }
+
+  /// Writes every byte in `bytes` to the stream.
+  ///
+  /// A single write may accept only part of the bytes,
+  /// so we keep writing until the whole command has been written.
+  async fn write_command(&mut self, bytes: &[u8]) -> Result<()> {
+    let mut bytes_written = 0;
+
+    while bytes_written < bytes.len() {
+      match self.stream.write(&bytes[bytes_written..]).await {
+        Ok(0) => {
+          return Err(std::io::Error::from(std::io::ErrorKind::WriteZero)).into_diagnostic();
+        }
+        Ok(n) => bytes_written += n,
+        Err(err)
+          if matches!(
+            err.kind(),
+            std::io::ErrorKind::Interrupted | std::io::ErrorKind::WouldBlock
+          ) =>
+        {
+          tokio::task::yield_now().await;
+        }
+        Err(err) => return Err(err).into_diagnostic(),
+      }
+    }
+
+    self.stream.flush().await.into_diagnostic()
+  }
+
+  /// Reads the next reply sent by the server,
+  /// handing the Pushes read before it to the push handlers, see [Redis::on_push].
+  ///
+  /// Returns the reply and how many bytes it took.
+  async fn read_reply(&mut self) -> Result<(DataType, usize)> {
+    self.read_reply_with(&mut Vec::new()).await
+  }
+
+  /// Reads the next reply sent by the server like [Redis::send] does, without sending a command,
+  /// reusing `buf` to hold the bytes of the reply instead of allocating a buffer for every reply.
+  ///
+  /// `buf` is cleared before the reply is read into it, its capacity is kept,
+  /// so after the first few replies it's big enough and reading a reply does not allocate
+  /// besides what the returned value needs.
+  ///
+  /// Useful in loops that read many replies, like the replies of pipelined commands
+  /// or what the server sends after MONITOR.
+  pub async fn read_reply_into(&mut self, buf: &mut Vec<u8>) -> Result<DataType> {
+    let (reply, _) = self.read_reply_with(buf).await?;
+
+    Ok(reply)
+  }
+
+  /// Reads the next reply like [Redis::read_reply] does, reading its bytes into `buf`.
+  async fn read_reply_with(&mut self, buf: &mut Vec<u8>) -> Result<(DataType, usize)> {
+    loop {
+      let (value, len) = self.read_value_into(buf).await?;
+
+      if let Some(reply) = self.out_of_band(value) {
+        return Ok((reply, len));
+      }
+    }
+  }
+
+  /// Queues `value` if it's a message and hands it to the push handlers if it's a Push.
+  ///
+  /// Returns `value` if it's neither, since then it's a reply.
+  fn out_of_band(&mut self, value: DataType) -> Option<DataType> {
+    if let Some(message) = self.pubsub_message(&value) {
+      self.messages.push_back(message);
+      return None;
+    }
+
+    match value {
+      DataType::Push(elements) if !self.push_handlers.is_empty() => {
+        for handler in &self.push_handlers {
+          handler(elements.clone());
+        }
+
+        None
+      }
+      reply => Some(reply),
+    }
+  }
+
+  /// Returns the message in `value` if it's a message published to a channel the client is subscribed to.
+  ///
+  /// Messages are sent as Pushes in RESP3 and as Arrays in RESP2,
+  /// which can only be told apart from replies because a subscribed RESP2 connection
+  /// can only receive replies to the few commands it's allowed to send.
+  fn pubsub_message(&self, value: &DataType) -> Option<Message> {
+    let elements = match value {
+      DataType::Push(elements) => elements,
+      DataType::Array(elements) if self.subscriptions > 0 && self.protocol == Protocol::Resp2 => {
+        elements
+      }
+      _ => return None,
+    };
+
+    match elements.as_slice() {
+      [DataType::BulkString(kind), DataType::BulkString(channel), DataType::BulkString(payload)]
+        if kind == b"message" =>
+      {
+        Some(Message {
+          channel: channel.clone(),
+          payload: payload.clone(),
+        })
+      }
+      _ => None,
+    }
+  }
+
+  /// Reads the next value sent by the server.
+  ///
+  /// A read may return part of a reply or more than one reply,
+  /// so we keep reading until there's a complete reply in the buffer
+  /// and leave the bytes after it in the buffer for the next call.
+  ///
+  /// Returns the value and how many bytes it took.
+  async fn read_value(&mut self) -> Result<(DataType, usize)> {
+    self.read_value_into(&mut Vec::new()).await
+  }
+
+  /// Reads the next value like [Redis::read_value] does, copying its bytes into `reply`.
+  async fn read_value_into(&mut self, reply: &mut Vec<u8>) -> Result<(DataType, usize)> {
+    self.skip_unread_elements().await?;
+
+    self.read_buffered_value(reply).await
+  }
+
+  /// Reads the next value like [Redis::read_value_into] does,
+  /// without skipping the elements left unread by an [ElementStream] first.
+  async fn read_buffered_value(&mut self, reply: &mut Vec<u8>) -> Result<(DataType, usize)> {
+    let reply_len = loop {
+      if let Some(reply_len) = resp::complete_len(&self.buffer)? {
+        break reply_len;
+      }
+
+      self.fill_buffer().await?;
+    };
+
+    // Draining keeps the capacity of the buffer instead of allocating a new one for the bytes left.
+    reply.clear();
+    reply.extend(self.buffer.drain(..reply_len));
+
+    info!("reply: {}", String::from_utf8_lossy(reply));
+
+    let reply = self.parse_reply(reply)?;
+
+    if let Some(stats) = &mut self.parse_stats {
+      stats.record(&reply, reply_len);
+    }
+
+    Ok((reply, reply_len))
+  }
+
+  /// Reads the bytes the server has sent, or waits until it sends some, into the buffer.
+  ///
+  /// Cancelling the future before it completes loses no bytes.
+  pub(crate) async fn fill_buffer(&mut self) -> Result<()> {
+    let mut chunk = [0; 4096];
+
+    let bytes_read = self.stream.read(&mut chunk).await.into_diagnostic()?;
+
+    if bytes_read == 0 {
+      return Err(RedisError::ConnectionClosed.into());
+    }
+
+    self.buffer.extend_from_slice(&chunk[..bytes_read]);
+
+    Ok(())
+  }
+
+  /// Reads and discards the elements of an [ElementStream] that was dropped before the end.
+  async fn skip_unread_elements(&mut self) -> Result<()> {
+    while self.unread_elements > 0 {
+      self.next_element().await?;
+      self.unread_elements -= 1;
+    }
+
+    Ok(())
+  }
+
+  /// Reads the header of an Array reply, leaving its elements to be read with [Redis::next_element].
+  ///
+  /// Returns how many elements the Array has.
+  async fn read_array_header(&mut self) -> Result<usize> {
+    self.skip_unread_elements().await?;
+
+    loop {
+      let mut events = resp::parse_iter(&self.buffer);
+
+      match events.next() {
+        Some(Ok(resp::Event::ArrayStart(len))) => {
+          let header_len = events.position();
+          self.buffer.drain(..header_len);
+          return Ok(len);
+        }
+        None | Some(Err(resp::ParserError::UnexpectedEndOfInput { .. })) => {
+          self.fill_buffer().await?
+        }
+        Some(Err(err)) => return Err(err.into()),
+        // Errors, Pushes sent before the reply and anything else are read whole.
+        Some(Ok(_)) => {
+          let (value, _) = self.read_buffered_value(&mut Vec::new()).await?;
+
+          match self.out_of_band(value) {
+            None => {}
+            Some(DataType::Error(message)) => return Err(server_error(message).into()),
+            Some(data_type) => return Err(RedisError::UnexpectedReply(data_type).into()),
+          }
+        }
+      }
+    }
+  }
+
+  /// Reads the next element of the Array whose header was read by [Redis::read_array_header],
+  /// which must be a Bulk String.
+  async fn next_element(&mut self) -> Result<Vec<u8>> {
+    loop {
+      let mut events = resp::parse_iter(&self.buffer);
+
+      match events.next() {
+        Some(Ok(resp::Event::BulkStringRef(element))) => {
+          let element = element.to_vec();
+          let element_len = events.position();
+          self.buffer.drain(..element_len);
+          return Ok(element);
+        }
+        None | Some(Err(resp::ParserError::UnexpectedEndOfInput { .. })) => {
+          self.fill_buffer().await?
+        }
+        Some(Err(err)) => return Err(err.into()),
+        Some(Ok(_)) => {
+          let (value, _) = self.read_buffered_value(&mut Vec::new()).await?;
+          return Err(RedisError::UnexpectedReply(value).into());
+        }
+      }
+    }
+  }
+
+  /// Starts tallying the replies the client parses, see [Redis::parse_stats].
+  ///
+  /// Disabled by default since every reply has to be walked to count the values nested in it.
+  pub fn enable_parse_stats(&mut self) {
+    self
+      .parse_stats
+      .get_or_insert_with(resp::ParseStats::default);
+  }
+
+  /// Returns what the replies parsed since [Redis::enable_parse_stats] was called were.
+  pub fn parse_stats(&self) -> Option<&resp::ParseStats> {
+    self.parse_stats.as_ref()
+  }
+
+  /// Parses `reply` with the protocol the client expects,
+  /// switching to RESP3 if it's being detected and `reply` uses a RESP3 type.
+  fn parse_reply(&mut self, reply: &[u8]) -> Result<DataType, resp::ParserError> {
+    if !self.detect_protocol {
+      return resp::parse_with_protocol(reply, self.protocol);
+    }
+
+    match resp::parse_with_protocol(reply, Protocol::Resp2) {
+      Err(resp::ParserError::ProtocolMismatch { .. }) => {
+        info!("server sent a RESP3 reply, switching to RESP3");
+
+        self.set_protocol(Protocol::Resp3);
+
+        resp::parse_with_protocol(reply, Protocol::Resp3)
+      }
+      result => result,
+    }
+  }
+
+  /// Reads and discards the next `count` replies.
+  ///
+  /// Used to get back in sync with the server when replies were left unread,
+  /// like after sending commands whose replies were not read because of an error,
+  /// since otherwise the next command would get the reply of a previous one.
+  pub async fn drain_pending(&mut self, count: usize) -> Result<()> {
+    for _ in 0..count {
+      self.read_reply().await?;
+    }
+
+    Ok(())
+  }
+
+  /// Checks that the encoded `command` named `name` can be sent
+  /// and forgets what it may change, before it's sent.
+  fn prepare_request(&mut self, name: &str, command: &[u8]) -> Result<(), RedisError> {
+    if self.subscriptions > 0
+      && self.protocol == Protocol::Resp2
+      && !ALLOWED_WHILE_SUBSCRIBED.contains(&name.to_uppercase().as_str())
+    {
+      return Err(RedisError::NotAllowedWhileSubscribed(name.to_uppercase()));
+    }
+
+    if let Some(cache) = &mut self.type_cache {
+      cache.invalidate(name, command);
+    }
+
+    Ok(())
+  }
+
+  /// Sends the encoded `command` named `name` and reads its reply.
+  async fn send_request(&mut self, name: &str, command: &[u8]) -> Result<Reply> {
+    self.prepare_request(name, command)?;
+
+    info!(command = %String::from_utf8_lossy(command), "sending command");
+
+    let started_at = Instant::now();
+
+    let mut backoff = INITIAL_LOADING_BACKOFF;
+
+    let (reply, bytes_read) = loop {
+      self.write_command(command).await?;
+
+      let (reply, bytes_read) = self.read_reply().await?;
+
+      match (&reply, self.loading_timeout) {
+        (DataType::Error(message), Some(timeout))
+          if message.starts_with("LOADING ") && started_at.elapsed() + backoff < timeout =>
+        {
+          info!(?backoff, "server is loading the dataset, retrying");
+
+          tokio::time::sleep(backoff).await;
+
+          backoff = (backoff * 2).min(MAX_LOADING_BACKOFF);
+        }
+        _ => break (reply, bytes_read),
+      }
+    };
+
+    let reply = match reply {
+      DataType::Error(message) => Reply::Error(message),
+      data_type => Reply::Ok(data_type),
+    };
+
+    if !self.hooks.is_empty() {
+      let metrics = CommandMetrics {
+        command: name.to_uppercase(),
+        bytes_sent: command.len(),
+        bytes_received: bytes_read,
+        duration: started_at.elapsed(),
+      };
+
+      for hook in &self.hooks {
+        hook(&metrics);
+      }
+    }
+
+    Ok(reply)
+  }
+
+  pub async fn send(&mut self, command: &str) -> Result<Reply> {
+    info!(command, "sending command");
+
+    let encoded_command = resp::encode(command)?;
+
+    let name = command
+      .split(' ')
+      .find(|piece| !piece.is_empty())
+      .unwrap_or_default();
+
+    self.send_request(name, encoded_command.as_bytes()).await
+  }
+
+  /// Sends a command whose reply is an Integer, like INCR or LLEN, and returns the Integer.
+  ///
+  /// The Integer is parsed straight from the bytes the server sent, without building a [DataType],
+  /// see `cargo run --release --example int_reply_bench`. Any other reply is parsed as usual,
+  /// an error reply is returned as an error and anything else as [RedisError::UnexpectedReply].
+  ///
+  /// Clients with command hooks, parse stats or retries while loading use the usual path,
+  /// since those need the parsed reply.
+  pub async fn send_expect_int(&mut self, command: &str) -> Result<i64> {
+    if !self.hooks.is_empty() || self.parse_stats.is_some() || self.loading_timeout.is_some() {
+      return match self.send(command).await?.into_data()? {
+        DataType::Int(n) => Ok(n),
+        data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+      };
+    }
+
+    let encoded_command = resp::encode(command)?;
+
+    let name = command
+      .split(' ')
+      .find(|piece| !piece.is_empty())
+      .unwrap_or_default();
+
+    self.prepare_request(name, encoded_command.as_bytes())?;
+
+    self.write_command(encoded_command.as_bytes()).await?;
+
+    self.skip_unread_elements().await?;
+
+    loop {
+      if let Some((n, len)) = int_frame(&self.buffer) {
+        self.buffer.drain(..len);
+        return Ok(n);
+      }
+
+      // Anything but an Integer, or an Integer that has not been received whole yet,
+      // is left to the full parser, which waits for the rest of it.
+      if !self.buffer.is_empty() {
+        break;
+      }
+
+      self.fill_buffer().await?;
+    }
+
+    match self.read_reply().await? {
+      (DataType::Int(n), _) => Ok(n),
+      (DataType::Error(message), _) => Err(server_error(message).into()),
+      (data_type, _) => Err(RedisError::UnexpectedReply(data_type).into()),
+    }
+  }
+
+  /// Sends a command built with [Command].
+  ///
+  /// Once the command table has been fetched with [Redis::refresh_command_table],
+  /// commands with the wrong number of arguments fail without being sent.
+  pub async fn send_command(&mut self, command: Command) -> Result<Reply> {
+    if let Some(table) = &self.command_table {
+      table.check_arity(&command)?;
+    }
+
+    self.send_request(&command.name(), &command.encode()).await
+  }
+
+  pub async fn flushall(&mut self) -> Result<Reply> {
+    self.send_request("FLUSHALL", b"FLUSHALL\r\n").await
+  }
+
+  /// Saves the dataset to the RDB file and loads it back with DEBUG RELOAD,
+  /// useful in tests to check that values survive being serialized.
+  ///
+  /// Since Redis 7.0 the DEBUG command is only available when the server is started
+  /// with `enable-debug-command` set to `yes` or `local`.
+  pub async fn reload(&mut self) -> Result<()> {
+    let command = Command::new("DEBUG").arg("RELOAD");
+
+    Ok(expect_ok(self.send_command(command).await?.into_data()?)?)
+  }
+
+  /// Sets `key` to the string `value`, compressing it first if compression is enabled,
+  /// see [Redis::enable_compression].
+  ///
+  /// With the compression feature, values that look compressed are escaped
+  /// even when compression is not enabled, so [Redis::get] returns them as they were.
+  pub async fn set(&mut self, key: &str, value: &[u8]) -> Result<()> {
+    #[cfg(feature = "compression")]
+    let value = match &self.compression {
+      Some(compression) => compression.compress(value),
+      None => compression::escape(value),
+    };
+
+    let command = Command::new("SET").arg(key).arg(value);
+
+    Ok(expect_ok(self.send_command(command).await?.into_data()?)?)
+  }
+
+  /// Returns the string stored at `key`, or `None` if the key does not exist.
+  ///
+  /// With the compression feature, values written compressed by [Redis::set] are decompressed.
+  pub async fn get(&mut self, key: &str) -> Result<Option<Vec<u8>>> {
+    let command = Command::new("GET").arg(key);
+
+    let value = match self.send_command(command).await?.into_data()? {
+      DataType::Null => return Ok(None),
+      DataType::BulkString(value) => value,
+      data_type => return Err(RedisError::UnexpectedReply(data_type).into()),
+    };
+
+    #[cfg(feature = "compression")]
+    let value = compression::decompress(value)?;
+
+    Ok(Some(value))
+  }
+
+  /// Appends `value` at the end of the string stored at `key`.
+  ///
+  /// Returns the length of the string after the append operation.
+  pub async fn append(&mut self, key: &str, value: &[u8]) -> Result<u64> {
+    let command = Command::new("APPEND").arg(key).arg(value);
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::Int(length) => Ok(length as u64),
+      data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+    }
+  }
+
+  /// Returns the bytes between `start` and `end`, both inclusive, of the string stored at `key`.
+  ///
+  /// Negative offsets count from the end of the string, where -1 is the last byte.
+  /// Like Redis, a range outside of the string, or a `key` that does not exist,
+  /// returns no bytes instead of an error.
+  pub async fn getrange(&mut self, key: &str, start: i64, end: i64) -> Result<Vec<u8>> {
+    let command = Command::new("GETRANGE")
+      .arg(key)
+      .arg_int(start)
+      .arg_int(end);
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::BulkString(bytes) => Ok(bytes),
+      DataType::Null => Ok(Vec::new()),
+      data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+    }
+  }
+
+  /// Overwrites the string stored at `key` with `value`, starting at `offset`.
+  ///
+  /// A string shorter than `offset`, or a `key` that does not exist, is padded with zero bytes first.
+  /// Strings are limited to 512 MB by default, so an `offset` that would make the string longer
+  /// is rejected without sending the command, see [Redis::set_max_string_len].
+  ///
+  /// Returns the length of the string after it was modified.
+  pub async fn setrange(&mut self, key: &str, offset: u64, value: &[u8]) -> Result<u64> {
+    if let Some(max_string_len) = self.max_string_len {
+      if offset.saturating_add(value.len() as u64) > max_string_len {
+        return Err(
+          RedisError::InvalidArgument(format!(
+            "the string would be longer than the maximum of {} bytes",
+            max_string_len
+          ))
+          .into(),
+        );
+      }
+    }
+
+    let command = Command::new("SETRANGE").arg(key).arg_int(offset).arg(value);
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::Int(length) => Ok(length as u64),
+      data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+    }
+  }
+
+  /// Returns the number of bytes that `key` and its value require to be stored in RAM,
+  /// or `None` if `key` does not exist.
+  ///
+  /// For nested data types, `samples` is the number of nested values sampled
+  /// to estimate the total size. The server default is used when `samples` is `None`.
+  pub async fn memory_usage(&mut self, key: &str, samples: Option<u64>) -> Result<Option<u64>> {
+    let mut command = Command::new("MEMORY").arg("USAGE").arg(key);
+
+    if let Some(samples) = samples {
+      command = command.arg("SAMPLES").arg_int(samples);
+    }
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::Int(bytes) => Ok(Some(bytes as u64)),
+      DataType::Null => Ok(None),
+      data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+    }
+  }
+
+  /// Returns random members from the set stored at `key`.
+  ///
+  /// Without `count` a single member is returned, or none if the set is empty.
+  /// A positive `count` returns up to `count` distinct members and
+  /// a negative `count` returns exactly `-count` members that may repeat.
+  pub async fn srandmember(&mut self, key: &str, count: Option<i64>) -> Result<Vec<Vec<u8>>> {
+    let mut command = Command::new("SRANDMEMBER").arg(key);
+
+    if let Some(count) = count {
+      command = command.arg_int(count);
+    }
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::BulkString(member) => Ok(vec![member]),
+      DataType::Null => Ok(vec![]),
+      data_type => Ok(bulk_strings(data_type)?),
+    }
+  }
+
+  /// Removes and returns random members from the set stored at `key`.
+  ///
+  /// Without `count` a single member is removed, or none if the set is empty.
+  /// With `count` up to `count` distinct members are removed.
+  pub async fn spop(&mut self, key: &str, count: Option<u64>) -> Result<Vec<Vec<u8>>> {
+    let mut command = Command::new("SPOP").arg(key);
+
+    if let Some(count) = count {
+      command = command.arg_int(count);
+    }
+
+    // Without a count the reply is a single member instead of an array of members.
+    match self.send_command(command).await?.into_data()? {
+      DataType::BulkString(member) => Ok(vec![member]),
+      DataType::Null => Ok(vec![]),
+      data_type => Ok(bulk_strings(data_type)?),
+    }
+  }
+
+  /// Returns up to `count` random fields with their values from the hash stored at `key`.
+  ///
+  /// Like [Redis::srandmember], a negative `count` allows the same field to be returned more than once.
+  pub async fn hrandfield_withvalues(
+    &mut self,
+    key: &str,
+    count: i64,
+  ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let command = Command::new("HRANDFIELD")
+      .arg(key)
+      .arg_int(count)
+      .arg("WITHVALUES");
+
+    let reply = self.send_command(command).await?.into_data()?;
+
+    Ok(pairs(reply)?)
+  }
+
+  /// Returns the number of members in the intersection of the sets stored at `keys`.
+  ///
+  /// When `limit` is given, the server stops counting once the cardinality reaches `limit`.
+  pub async fn sintercard(&mut self, keys: &[&str], limit: Option<u64>) -> Result<u64> {
+    let mut command = Command::new("SINTERCARD").args_with_numkeys(keys);
+
+    if let Some(limit) = limit {
+      command = command.arg("LIMIT").arg_int(limit);
+    }
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::Int(cardinality) => Ok(cardinality as u64),
+      data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+    }
+  }
+
+  /// Returns the help lines of a command with subcommands, like OBJECT or CONFIG.
+  ///
+  /// # Examples
+  ///
+  /// ```terminal
+  /// redis.help("OBJECT").await? -> ["OBJECT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:", ...]
+  /// ```
+  pub async fn help(&mut self, command: &str) -> Result<Vec<String>> {
+    let command = Command::new(command).arg("HELP");
+
+    Ok(help_lines(self.send_command(command).await?.into_data()?)?)
+  }
+
+  /// Counts the number of set bits in the string stored at `key`.
+  ///
+  /// When `range` is given, only the bits between `start` and `end` (both inclusive)
+  /// are counted, where `start` and `end` are byte or bit indexes depending on the [BitUnit].
+  pub async fn bitcount(&mut self, key: &str, range: Option<(i64, i64, BitUnit)>) -> Result<u64> {
+    let mut command = Command::new("BITCOUNT").arg(key);
+
+    if let Some((start, end, unit)) = range {
+      command = command.arg_int(start).arg_int(end).arg(unit.as_str());
+    }
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::Int(count) => Ok(count as u64),
+      data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+    }
+  }
+
+  /// Returns whether each of `members` is a member of the set stored at `key`.
+  pub async fn smismember(&mut self, key: &str, members: &[&[u8]]) -> Result<Vec<bool>> {
+    let mut command = Command::new("SMISMEMBER").arg(key);
+
+    for member in members {
+      command = command.arg(member);
+    }
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::Array(elements) => elements
+        .into_iter()
+        .map(|element| match element {
+          DataType::Int(is_member) => Ok(is_member == 1),
+          data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+        })
+        .collect(),
+      data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+    }
+  }
+
+  /// Sets the bit at `offset` in the string stored at `key` when `value` is true, clears it otherwise.
+  ///
+  /// `offset` must be less than 2^32, other offsets are rejected without sending the command.
+  ///
+  /// Returns the bit that was stored at `offset`.
+  pub async fn setbit(&mut self, key: &str, offset: u64, value: bool) -> Result<bool> {
+    check_bit_offset(offset)?;
+
+    let command = Command::new("SETBIT")
+      .arg(key)
+      .arg_int(offset)
+      .arg_int(u8::from(value));
+
+    Ok(bit(self.send_command(command).await?.into_data()?)?)
+  }
+
+  /// Returns the bit at `offset` in the string stored at `key`.
+  ///
+  /// Bits past the end of the string, and the bits of a key that does not exist, are false.
+  /// Like [Redis::setbit], `offset` must be less than 2^32.
+  pub async fn getbit(&mut self, key: &str, offset: u64) -> Result<bool> {
+    check_bit_offset(offset)?;
+
+    let command = Command::new("GETBIT").arg(key).arg_int(offset);
+
+    Ok(bit(self.send_command(command).await?.into_data()?)?)
+  }
+
+  /// Sets a timeout of `seconds` on `key`, after which the key is deleted.
+  ///
+  /// `seconds` can't be negative, negative values are rejected without sending the command.
+  ///
+  /// Returns false if `key` does not exist.
+  pub async fn expire(&mut self, key: &str, seconds: i64) -> Result<bool> {
+    if seconds < 0 {
+      return Err(
+        RedisError::InvalidArgument(format!("expire time must not be negative, got {}", seconds))
+          .into(),
+      );
+    }
+
+    let command = Command::new("EXPIRE").arg(key).arg_int(seconds);
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::Int(timeout_was_set) => Ok(timeout_was_set == 1),
+      data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+    }
+  }
+
+  /// Renames `src` to `dst`, overwriting `dst` if it exists.
+  ///
+  /// Returns [RedisError::NoSuchKey] if `src` does not exist.
+  pub async fn rename(&mut self, src: &str, dst: &str) -> Result<()> {
+    let command = Command::new("RENAME").arg(src).arg(dst);
+
+    match self.send_command(command).await? {
+      Reply::Error(message) if message == "ERR no such key" => {
+        Err(RedisError::NoSuchKey(src.to_owned()).into())
+      }
+      reply => Ok(expect_ok(reply.into_data()?)?),
+    }
+  }
+
+  /// Renames `src` to `dst` if `dst` does not exist.
+  ///
+  /// Returns false if `dst` already exists, and [RedisError::NoSuchKey] if `src` does not exist.
+  pub async fn renamenx(&mut self, src: &str, dst: &str) -> Result<bool> {
+    let command = Command::new("RENAMENX").arg(src).arg(dst);
+
+    match self.send_command(command).await? {
+      Reply::Error(message) if message == "ERR no such key" => {
+        Err(RedisError::NoSuchKey(src.to_owned()).into())
+      }
+      reply => match reply.into_data()? {
+        DataType::Int(renamed) => Ok(renamed == 1),
+        data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+      },
+    }
+  }
+
+  /// Removes the timeout of `key`, so it's not deleted when the timeout expires.
+  ///
+  /// Returns true if a timeout was removed, and false if `key` does not exist or has no timeout.
+  pub async fn persist(&mut self, key: &str) -> Result<bool> {
+    let command = Command::new("PERSIST").arg(key);
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::Int(timeout_was_removed) => Ok(timeout_was_removed == 1),
+      data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+    }
+  }
+
+  /// Turns the no-evict mode on or off for the current connection.
+  ///
+  /// When on, the connection is excluded from client eviction.
+  pub async fn client_no_evict(&mut self, on: bool) -> Result<()> {
+    let command = Command::new("CLIENT").arg("NO-EVICT").arg(on_off(on));
+
+    Ok(expect_ok(self.send_command(command).await?.into_data()?)?)
+  }
+
+  /// Turns the no-touch mode on or off for the current connection.
+  ///
+  /// When on, commands sent by the connection don't change the LRU/LFU of the keys they access.
+  pub async fn client_no_touch(&mut self, on: bool) -> Result<()> {
+    let command = Command::new("CLIENT").arg("NO-TOUCH").arg(on_off(on));
+
+    Ok(expect_ok(self.send_command(command).await?.into_data()?)?)
+  }
+
+  /// Returns the client side caching state of the current connection.
+  pub async fn client_trackinginfo(&mut self) -> Result<TrackingInfo> {
+    let command = Command::new("CLIENT").arg("TRACKINGINFO");
+
+    Ok(tracking_info(
+      self.send_command(command).await?.into_data()?,
+    )?)
+  }
+
+  /// Returns information about the stream stored at `key`.
+  pub async fn xinfo_stream(&mut self, key: &str) -> Result<StreamInfo> {
+    let command = Command::new("XINFO").arg("STREAM").arg(key);
+
+    Ok(stream_info(self.send_command(command).await?.into_data()?)?)
+  }
+
+  /// Returns how many messages of the consumer `group` of the stream stored at `key`
+  /// are pending, the smallest and greatest of their ids and how many each consumer has.
+  pub async fn xpending_summary(&mut self, key: &str, group: &str) -> Result<PendingSummary> {
+    let command = Command::new("XPENDING").arg(key).arg(group);
+
+    Ok(pending_summary(
+      self.send_command(command).await?.into_data()?,
+    )?)
+  }
+
+  /// Returns up to `count` pending messages of the consumer `group` of the stream stored at `key`,
+  /// with ids between `start` and `end`, both inclusive, where "-" and "+" are the smallest
+  /// and greatest ids. Only the messages of `consumer` are returned if it's given.
+  pub async fn xpending_extended(
+    &mut self,
+    key: &str,
+    group: &str,
+    start: &str,
+    end: &str,
+    count: u64,
+    consumer: Option<&str>,
+  ) -> Result<Vec<PendingMessage>> {
+    let mut command = Command::new("XPENDING")
+      .arg(key)
+      .arg(group)
+      .arg(start)
+      .arg(end)
+      .arg_int(count);
+
+    if let Some(consumer) = consumer {
+      command = command.arg(consumer);
+    }
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::Array(messages) => Ok(
+        messages
+          .into_iter()
+          .map(pending_message)
+          .collect::<Result<_, _>>()?,
+      ),
+      data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+    }
+  }
+
+  /// Returns the elements of the list stored at `key` between `start` and `stop`, both inclusive.
+  ///
+  /// Negative indexes count from the end of the list, where -1 is the last element.
+  pub async fn lrange(&mut self, key: &str, start: i64, stop: i64) -> Result<Vec<Vec<u8>>> {
+    let command = Command::new("LRANGE").arg(key).arg_int(start).arg_int(stop);
+
+    Ok(bulk_strings(
+      self.send_command(command).await?.into_data()?,
+    )?)
+  }
+
+  /// Returns the elements of the list stored at `key` like [Redis::lrange] does,
+  /// except that they are read from the connection one at a time as the stream is advanced
+  /// instead of the whole reply being read before returning.
+  ///
+  /// Useful for lists too big to hold in memory at once.
+  /// Elements that are not read before the stream is dropped are skipped before the next reply is read.
+  ///
+  /// # Examples
+  ///
+  /// ```terminal
+  /// let mut elements = redis.lrange_stream("mylist", 0, -1).await?;
+  ///
+  /// while let Some(element) = elements.next().await? {
+  ///   println!("{:?}", element);
+  /// }
+  /// ```
+  pub async fn lrange_stream(
+    &mut self,
+    key: &str,
+    start: i64,
+    stop: i64,
+  ) -> Result<ElementStream<'_, S>> {
+    let command = Command::new("LRANGE").arg(key).arg_int(start).arg_int(stop);
+
+    if let Some(table) = &self.command_table {
+      table.check_arity(&command)?;
+    }
+
+    let bytes = command.encode();
+    self.prepare_request("LRANGE", &bytes)?;
+
+    info!(command = %String::from_utf8_lossy(&bytes), "sending command");
+
+    self.write_command(&bytes).await?;
+
+    let remaining = self.read_array_header().await?;
+
+    Ok(ElementStream {
+      redis: self,
+      remaining,
+    })
+  }
+
+  /// Returns the element at `index` in the list stored at `key`,
+  /// or `None` if `index` is out of range.
+  ///
+  /// Negative indexes count from the end of the list, where -1 is the last element.
+  pub async fn lindex(&mut self, key: &str, index: i64) -> Result<Option<Vec<u8>>> {
+    let command = Command::new("LINDEX").arg(key).arg_int(index);
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::BulkString(element) => Ok(Some(element)),
+      DataType::Null => Ok(None),
+      data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+    }
+  }
+
+  /// Returns the elements of the list, set or sorted set stored at `key`, sorted as described by `options`.
+  ///
+  /// Elements that don't exist, like the ones from a GET pattern that matches no key,
+  /// are returned as empty.
+  pub async fn sort(&mut self, key: &str, options: Sort) -> Result<Vec<Vec<u8>>> {
+    let command = options.apply(Command::new("SORT").arg(key));
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::Array(elements) => elements
+        .into_iter()
+        .map(|element| match element {
+          DataType::BulkString(bytes) => Ok(bytes),
+          DataType::Null => Ok(vec![]),
+          data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+        })
+        .collect(),
+      data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+    }
+  }
+
+  /// Pops an element from the first non-empty list in `keys`,
+  /// blocking for up to `timeout` until an element is available.
+  ///
+  /// A zero `timeout` blocks forever.
+  ///
+  /// Returns the key of the list the element was popped from and the element,
+  /// or `None` if the timeout expired.
+  pub async fn blpop(
+    &mut self,
+    keys: &[&str],
+    timeout: Duration,
+  ) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+    let mut command = Command::new("BLPOP");
+
+    for key in keys {
+      command = command.arg(key);
+    }
+
+    let command = command.arg_seconds(timeout);
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::Null => Ok(None),
+      data_type => Ok(pairs(data_type)?.pop()),
+    }
+  }
+
+  /// Blocks until the previous write commands are acknowledged by at least `num_replicas` replicas,
+  /// or until `timeout` expires. A zero `timeout` blocks forever.
+  ///
+  /// Returns the number of replicas that acknowledged the writes.
+  pub async fn wait(&mut self, num_replicas: u64, timeout: Duration) -> Result<u64> {
+    let command = Command::new("WAIT")
+      .arg_int(num_replicas)
+      .arg_milliseconds(timeout);
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::Int(replicas) => Ok(replicas as u64),
+      data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+    }
+  }
+
+  /// Returns the value of `key`, or `None` if the key does not exist,
+  /// changing the expiry of the key as described by `expiry`.
+  pub async fn getex(&mut self, key: &str, expiry: GetExExpiry) -> Result<Option<Vec<u8>>> {
+    let command = expiry.apply(Command::new("GETEX").arg(key));
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::BulkString(value) => Ok(Some(value)),
+      DataType::Null => Ok(None),
+      data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+    }
+  }
+
+  /// Adds `members`, pairs of a score and a member, to the sorted set stored at `key`,
+  /// or updates their scores, depending on `options`.
+  ///
+  /// Options that can't be used together and scores that are NaN
+  /// are rejected without sending the command.
+  ///
+  /// # Examples
+  ///
+  /// ```terminal
+  /// redis.zadd_opts("myzset", &[(1.0, b"one"), (2.0, b"two")], ZAddOptions::new().ch()).await? -> ZAddResult::Count(2)
+  /// redis.zadd_opts("myzset", &[(5.0, b"one")], ZAddOptions::new().incr()).await?              -> ZAddResult::Score(Some(6.0))
+  /// ```
+  pub async fn zadd_opts(
+    &mut self,
+    key: &str,
+    members: &[(f64, &[u8])],
+    options: ZAddOptions,
+  ) -> Result<ZAddResult> {
+    options.validate(members.len())?;
+
+    let incr = options.incr;
+
+    let mut command = options.apply(Command::new("ZADD").arg(key));
+
+    for (score, member) in members {
+      if score.is_nan() {
+        return Err(RedisError::InvalidArgument(String::from("score must not be NaN")).into());
+      }
+
+      command = command.arg(score.to_string()).arg(member);
+    }
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::Int(count) if !incr => Ok(ZAddResult::Count(count as u64)),
+      data_type if incr => Ok(ZAddResult::Score(score_or_nil(data_type)?)),
+      data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+    }
+  }
+
+  /// Returns the longest common subsequence of the strings stored at `key1` and `key2`,
+  /// or its length or matches depending on `options`.
+  pub async fn lcs(&mut self, key1: &str, key2: &str, options: LcsOptions) -> Result<LcsResult> {
+    let command = options.apply(Command::new("LCS").arg(key1).arg(key2));
+
+    Ok(lcs_result(self.send_command(command).await?.into_data()?)?)
+  }
+
+  /// Returns when `key` expires, with a precision of seconds.
+  pub async fn expiretime(&mut self, key: &str) -> Result<ExpireTime> {
+    let command = Command::new("EXPIRETIME").arg(key);
+
+    Ok(expire_time(
+      self.send_command(command).await?.into_data()?,
+      Duration::from_secs,
+    )?)
+  }
+
+  /// Returns when `key` expires, with a precision of milliseconds.
+  pub async fn pexpiretime(&mut self, key: &str) -> Result<ExpireTime> {
+    let command = Command::new("PEXPIRETIME").arg(key);
+
+    Ok(expire_time(
+      self.send_command(command).await?.into_data()?,
+      Duration::from_millis,
+    )?)
+  }
+
+  /// Returns how the value stored at `key` is encoded internally, like "listpack" or "quicklist",
+  /// or `None` if `key` does not exist.
+  pub async fn object_encoding(&mut self, key: &str) -> Result<Option<String>> {
+    let command = Command::new("OBJECT").arg("ENCODING").arg(key);
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::BulkString(encoding) => Ok(Some(String::from_utf8_lossy(&encoding).to_string())),
+      DataType::Null => Ok(None),
+      data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+    }
+  }
+
+  /// Runs `ops` with the client and returns the encoding of `key` afterwards, see [Redis::object_encoding].
+  ///
+  /// Returns an error if `key` does not exist after running `ops`.
+  ///
+  /// # Examples
+  ///
+  /// ```terminal
+  /// let encoding = redis
+  ///   .encoding_after("mylist", async |redis| {
+  ///     redis.send("RPUSH mylist a b c").await?;
+  ///     Ok(())
+  ///   })
+  ///   .await?;
+  ///
+  /// assert_eq!("listpack", encoding);
+  /// ```
+  pub async fn encoding_after(
+    &mut self,
+    key: &str,
+    ops: impl AsyncFnOnce(&mut Self) -> Result<()>,
+  ) -> Result<String> {
+    ops(self).await?;
+
+    match self.object_encoding(key).await? {
+      Some(encoding) => Ok(encoding),
+      None => Err(RedisError::UnexpectedReply(DataType::Null).into()),
+    }
+  }
+
+  /// Returns the arguments of the command made of `command_args` that are keys,
+  /// like "a" and "b" for MSET a 1 b 2.
+  ///
+  /// The command is not executed.
+  pub async fn command_getkeys(&mut self, command_args: &[&[u8]]) -> Result<Vec<Vec<u8>>> {
+    let mut command = Command::new("COMMAND").arg("GETKEYS");
+
+    for arg in command_args {
+      command = command.arg(arg);
+    }
+
+    Ok(bulk_strings(
+      self.send_command(command).await?.into_data()?,
+    )?)
+  }
+
+  /// Deletes every key that matches the glob-style `pattern`, returning how many keys were deleted.
+  ///
+  /// Keys are found with SCAN, asking for about `batch` keys at a time, and deleted with UNLINK
+  /// in groups of at most `batch` keys, so the server is never blocked for long
+  /// like it would be with KEYS. Keys that match `pattern` and are created while
+  /// the keys are being deleted may not be deleted.
+  pub async fn del_matching(&mut self, pattern: &str, batch: usize) -> Result<u64> {
+    if batch == 0 {
+      return Err(RedisError::InvalidArgument(String::from("batch must be greater than 0")).into());
+    }
+
+    let mut cursor = b"0".to_vec();
+    let mut deleted = 0;
+
+    loop {
+      let command = Command::new("SCAN")
+        .arg(&cursor)
+        .arg("MATCH")
+        .arg(pattern)
+        .arg("COUNT")
+        .arg_int(batch as u64);
+
+      let (next_cursor, keys): (Vec<u8>, DataType) = self
+        .send_command(command)
+        .await?
+        .into_data()?
+        .try_into()
+        .map_err(RedisError::from)?;
+
+      let keys = bulk_strings(keys)?;
+
+      for keys in keys.chunks(batch) {
+        let mut command = Command::new("UNLINK");
+
+        for key in keys {
+          command = command.arg(key);
+        }
+
+        match self.send_command(command).await?.into_data()? {
+          DataType::Int(unlinked) => deleted += unlinked as u64,
+          data_type => return Err(RedisError::UnexpectedReply(data_type).into()),
+        }
+      }
+
+      // The iteration is over when the server returns the cursor 0.
+      if next_cursor == b"0" {
+        return Ok(deleted);
+      }
+
+      cursor = next_cursor;
+    }
+  }
+
+  /// Blocks until the previous write commands are fsynced to the append only file
+  /// of the server and of at least `num_replicas` replicas, or until `timeout` expires.
+  /// A zero `timeout` blocks forever.
+  ///
+  /// `num_local` is 0 or 1, and 1 requires the append only file to be enabled on the server.
+  ///
+  /// Returns how many servers, the local one and replicas, fsynced the writes.
+  pub async fn waitaof(
+    &mut self,
+    num_local: u64,
+    num_replicas: u64,
+    timeout: Duration,
+  ) -> Result<(u64, u64)> {
+    let command = Command::new("WAITAOF")
+      .arg_int(num_local)
+      .arg_int(num_replicas)
+      .arg_milliseconds(timeout);
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::Array(elements) => match elements.as_slice() {
+        [DataType::Int(local), DataType::Int(replicas)] => Ok((*local as u64, *replicas as u64)),
+        _ => Err(RedisError::UnexpectedReply(DataType::Array(elements)).into()),
+      },
+      data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+    }
+  }
+
+  /// Sends DEBUG followed by `args`, like ["SET-ACTIVE-EXPIRE", "0"], and returns the reply as is.
+  ///
+  /// DEBUG subcommands are meant for testing and change between versions,
+  /// so the reply is not interpreted.
+  ///
+  /// The server rejects DEBUG unless it's enabled with the enable-debug-command option.
+  pub async fn debug(&mut self, args: &[&str]) -> Result<Reply> {
+    let mut command = Command::new("DEBUG");
+
+    for arg in args {
+      command = command.arg(arg);
+    }
+
+    self.send_command(command).await
+  }
+
+  /// Runs `commands` in a transaction, between MULTI and EXEC, and returns their replies.
+  ///
+  /// If the server does not queue one of the commands, like when a command does not exist,
+  /// the transaction is discarded and the reply the command got is returned as an error.
+  pub async fn transaction(&mut self, commands: Vec<Command>) -> Result<Vec<Reply>> {
+    expect_ok(
+      self
+        .send_command(Command::new("MULTI"))
+        .await?
+        .into_data()?,
+    )?;
+
+    for command in commands {
+      let reply = self.send_command(command).await?;
+
+      if !reply.is_queued() {
+        self.send_command(Command::new("DISCARD")).await?;
+
+        return Err(
+          match reply {
+            Reply::Error(message) => server_error(message),
+            Reply::Ok(data_type) => RedisError::UnexpectedReply(data_type),
+          }
+          .into(),
+        );
+      }
+    }
+
+    match self.send_command(Command::new("EXEC")).await?.into_data()? {
+      DataType::Array(replies) => Ok(
+        replies
+          .into_iter()
+          .map(|reply| match reply {
+            DataType::Error(message) => Reply::Error(message),
+            data_type => Reply::Ok(data_type),
+          })
+          .collect(),
+      ),
+      data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+    }
+  }
+
+  /// Subscribes the client to `channels`, see [Redis::next_message].
+  ///
+  /// A RESP2 connection that is subscribed to a channel can only send (UN)SUBSCRIBE and a few other
+  /// commands, sending any other command fails with [RedisError::NotAllowedWhileSubscribed].
+  /// A RESP3 connection can send any command, and the messages received while waiting
+  /// for its reply are kept for [Redis::next_message].
+  ///
+  /// The protocol the server speaks is learned from how it confirms the subscriptions.
+  pub async fn subscribe(&mut self, channels: &[&str]) -> Result<()> {
+    self.change_subscriptions("SUBSCRIBE", channels).await
+  }
+
+  /// Unsubscribes the client from `channels`.
+  pub async fn unsubscribe(&mut self, channels: &[&str]) -> Result<()> {
+    if channels.is_empty() {
+      return Err(RedisError::InvalidArgument(String::from("channels must not be empty")).into());
+    }
+
+    self.change_subscriptions("UNSUBSCRIBE", channels).await
+  }
+
+  /// Unsubscribes the client from every channel it's subscribed to.
+  pub async fn unsubscribe_all(&mut self) -> Result<()> {
+    self.change_subscriptions("UNSUBSCRIBE", &[]).await
+  }
+
+  /// Returns how many channels the client is subscribed to.
+  pub fn subscriptions(&self) -> usize {
+    self.subscriptions
+  }
+
+  /// Sends SUBSCRIBE or UNSUBSCRIBE, named `name`, with `channels`
+  /// and reads the confirmation the server sends for each channel.
+  ///
+  /// UNSUBSCRIBE without channels unsubscribes from every channel, the server sends
+  /// a confirmation for each one until no subscriptions are left,
+  /// or a single confirmation if there were none.
+  async fn change_subscriptions(&mut self, name: &str, channels: &[&str]) -> Result<()> {
+    let mut command = Command::new(name);
+
+    for channel in channels {
+      command = command.arg(channel);
+    }
+
+    info!(command = %name, ?channels, "sending command");
+
+    self.write_command(&command.encode()).await?;
+
+    let mut confirmations = 0;
+
+    let confirmed = |confirmations: usize, subscriptions: usize| {
+      if channels.is_empty() {
+        confirmations > 0 && subscriptions == 0
+      } else {
+        confirmations == channels.len()
+      }
+    };
+
+    while !confirmed(confirmations, self.subscriptions) {
+      let (value, _) = self.read_value().await?;
+
+      if let Some(message) = self.pubsub_message(&value) {
+        self.messages.push_back(message);
+        continue;
+      }
+
+      let (elements, protocol) = match value {
+        DataType::Push(elements) => (elements, Protocol::Resp3),
+        DataType::Array(elements) => (elements, Protocol::Resp2),
+        DataType::Error(message) => return Err(server_error(message).into()),
+        data_type => return Err(RedisError::UnexpectedReply(data_type).into()),
+      };
+
+      match elements.as_slice() {
+        [DataType::BulkString(kind), channel, DataType::Int(subscriptions)]
+          if kind.eq_ignore_ascii_case(name.as_bytes()) =>
+        {
+          if let DataType::BulkString(channel) = channel {
+            let channel = String::from_utf8_lossy(channel).to_string();
+
+            self.channels.retain(|subscribed| *subscribed != channel);
+
+            if name == "SUBSCRIBE" {
+              self.channels.push(channel);
+            }
+          }
+
+          self.subscriptions = *subscriptions as usize;
+          self.set_protocol(protocol);
+          confirmations += 1;
+        }
+        _ => return Err(RedisError::UnexpectedReply(DataType::Array(elements)).into()),
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Returns the next message published to one of the channels the client is subscribed to,
+  /// waiting for one if none has been received yet.
+  ///
+  /// Returns [RedisError::NotSubscribed] if the client is not subscribed to any channel.
+  pub async fn next_message(&mut self) -> Result<Message> {
+    loop {
+      if let Some(message) = self.messages.pop_front() {
+        return Ok(message);
+      }
+
+      if self.subscriptions == 0 {
+        return Err(RedisError::NotSubscribed.into());
+      }
+
+      let (value, _) = self.read_value().await?;
+
+      match self.pubsub_message(&value) {
+        Some(message) => return Ok(message),
+        None => match value {
+          DataType::Push(elements) if !self.push_handlers.is_empty() => {
+            for handler in &self.push_handlers {
+              handler(elements.clone());
+            }
+          }
+          data_type => return Err(RedisError::UnexpectedReply(data_type).into()),
+        },
+      }
+    }
+  }
+
+  /// Returns the slot ranges of the cluster and the nodes that serve each one.
+  pub async fn cluster_slots(&mut self) -> Result<Vec<SlotRange>> {
+    let command = Command::new("CLUSTER").arg("SLOTS");
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::Array(ranges) => Ok(
+        ranges
+          .into_iter()
+          .map(slot_range)
+          .collect::<Result<_, _>>()?,
+      ),
+      data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+    }
+  }
+
+  /// Fetches the commands the server knows about with COMMAND, replacing the ones fetched before.
+  ///
+  /// Call it again after loading a module to learn about the commands it adds.
+  pub async fn refresh_command_table(&mut self) -> Result<&CommandTable> {
+    let reply = self
+      .send_command(Command::new("COMMAND"))
+      .await?
+      .into_data()?;
+
+    Ok(self.command_table.insert(command_table(reply)?))
+  }
+
+  /// Returns the commands fetched by [Redis::refresh_command_table], if it has been called.
+  pub fn command_table(&self) -> Option<&CommandTable> {
+    self.command_table.as_ref()
+  }
+
+  /// Returns the name of the user the connection is authenticated as.
+  pub async fn acl_whoami(&mut self) -> Result<String> {
+    let command = Command::new("ACL").arg("WHOAMI");
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::BulkString(name) => Ok(String::from_utf8_lossy(&name).to_string()),
+      DataType::SimpleString(name) => Ok(name),
+      data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+    }
+  }
+
+  /// Returns the rules of the user named `username`, or `None` if the user does not exist.
+  pub async fn acl_getuser(&mut self, username: &str) -> Result<Option<AclUser>> {
+    let command = Command::new("ACL").arg("GETUSER").arg(username);
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::Null => Ok(None),
+      data_type => Ok(Some(acl_user(data_type)?)),
+    }
+  }
+
+  /// Updates the last access time of `keys`, returning how many of them exist.
+  ///
+  /// Keys that are touched are less likely to be evicted by an LRU or LFU eviction policy.
+  pub async fn touch(&mut self, keys: &[&str]) -> Result<u64> {
+    let mut command = Command::new("TOUCH");
+
+    for key in keys {
+      command = command.arg(key);
+    }
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::Int(touched) => Ok(touched as u64),
+      data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+    }
+  }
+
+  /// Caches the types [Redis::get_typed] finds for `ttl`, so reading a key again doesn't need a TYPE round trip.
+  ///
+  /// The type of a key is forgotten when this client sends a command that may write to the key.
+  /// Writes by other clients are not seen: until `ttl` passes, a key deleted and created again
+  /// with another type by someone else fails with a WRONGTYPE error, which also forgets its type.
+  pub fn enable_type_cache(&mut self, ttl: Duration) {
+    self.type_cache = Some(TypeCache {
+      ttl,
+      types: HashMap::new(),
+    });
+  }
+
+  /// Returns the value of `key` whatever its type is, or `None` if the key does not exist.
+  ///
+  /// The type of the key is found with TYPE before reading it,
+  /// unless it's cached, see [Redis::enable_type_cache].
+  pub async fn get_typed(&mut self, key: &str) -> Result<Option<TypedValue>> {
+    let cached_type = self.type_cache.as_ref().and_then(|cache| cache.get(key));
+
+    let key_type = match cached_type {
+      Some(key_type) => key_type,
+      None => {
+        let key_type = match self
+          .send_command(Command::new("TYPE").arg(key))
+          .await?
+          .into_data()?
+        {
+          DataType::SimpleString(key_type) => key_type,
+          data_type => return Err(RedisError::UnexpectedReply(data_type).into()),
+        };
+
+        if let Some(cache) = &mut self.type_cache {
+          if key_type != "none" {
+            cache
+              .types
+              .insert(key.as_bytes().to_vec(), (key_type.clone(), Instant::now()));
+          }
+        }
+
+        key_type
+      }
+    };
+
+    let command = match key_type.as_str() {
+      "none" => return Ok(None),
+      "string" => Command::new("GET").arg(key),
+      "list" => Command::new("LRANGE").arg(key).arg("0").arg("-1"),
+      "set" => Command::new("SMEMBERS").arg(key),
+      "hash" => Command::new("HGETALL").arg(key),
+      "zset" => Command::new("ZRANGE")
+        .arg(key)
+        .arg("0")
+        .arg("-1")
+        .arg("WITHSCORES"),
+      _ => return Err(RedisError::UnexpectedReply(DataType::SimpleString(key_type)).into()),
+    };
+
+    let value = match self.send_command(command).await?.into_data() {
+      Ok(data_type) => typed_value(&key_type, data_type),
+      Err(err) => Err(err),
+    };
+
+    // The cached type was wrong or the key expired, since Redis deletes
+    // lists, sets, hashes and sorted sets when they become empty.
+    if !matches!(value, Ok(Some(_))) {
+      if let Some(cache) = &mut self.type_cache {
+        cache.types.remove(key.as_bytes());
+      }
+    }
+
+    Ok(value?)
+  }
+
+  /// Appends an entry with `fields` to the stream stored at `key`, creating the stream if needed.
+  ///
+  /// Returns the id of the entry, which is the generated one when `id` is [XId::Auto].
+  pub async fn xadd(&mut self, key: &str, id: XId, fields: &[(&[u8], &[u8])]) -> Result<String> {
+    let mut command = Command::new("XADD").arg(key).arg(id.as_str());
+
+    for (field, value) in fields {
+      command = command.arg(field).arg(value);
+    }
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::BulkString(id) => Ok(String::from_utf8_lossy(&id).to_string()),
+      data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+    }
+  }
+
+  /// Sends a command built with [Command] like [Redis::send_command] does, tagging the events
+  /// emitted while it's written and its reply is read with `correlation_id`,
+  /// like the id of the request being served, so they can be told apart from other requests.
+  ///
+  /// The command sent to the server is not changed.
+  pub async fn send_traced(&mut self, command: Command, correlation_id: &str) -> Result<Reply> {
+    let span = info_span!("command", name = %command.name(), correlation_id = %correlation_id);
+
+    self.send_command(command).instrument(span).await
+  }
+
+  /// Sends every command in `commands` at once and then reads their replies,
+  /// saving a round trip per command compared to sending them one by one.
+  ///
+  /// Returns the reply of each command at the same index as the command,
+  /// where a command that failed has its error message instead of its reply.
+  /// Unlike [Redis::transaction], the commands that don't fail still run.
+  pub async fn pipeline(
+    &mut self,
+    commands: Vec<Command>,
+  ) -> Result<Vec<std::result::Result<DataType, String>>> {
+    let mut encoded = Vec::new();
+
+    for command in &commands {
+      if let Some(table) = &self.command_table {
+        table.check_arity(command)?;
+      }
+
+      let bytes = command.encode();
+      self.prepare_request(&command.name(), &bytes)?;
+      encoded.extend_from_slice(&bytes);
+    }
+
+    info!(commands = commands.len(), "sending pipeline");
+
+    self.write_command(&encoded).await?;
+
+    let mut replies = Vec::with_capacity(commands.len());
+
+    for _ in 0..commands.len() {
+      let (reply, _) = self.read_reply().await?;
+
+      replies.push(match reply {
+        DataType::Error(message) => Err(message),
+        data_type => Ok(data_type),
+      });
+    }
+
+    Ok(replies)
+  }
+
+  /// Authenticates the connection as `username`, or as the default user if it's `None`.
+  ///
+  /// The credentials are kept to authenticate again after [Redis::reconnect].
+  pub async fn auth(&mut self, username: Option<&str>, password: &str) -> Result<()> {
+    let mut command = Command::new("AUTH");
+
+    if let Some(username) = username {
+      command = command.arg(username);
+    }
+
+    expect_ok(
+      self
+        .send_command(command.arg(password))
+        .await?
+        .into_data()?,
+    )?;
+
+    self.credentials = Some((username.map(String::from), password.to_owned()));
+
+    Ok(())
+  }
+
+  /// Selects the database with index `db`, which is kept to select it again after [Redis::reconnect].
+  pub async fn select(&mut self, db: u64) -> Result<()> {
+    let command = Command::new("SELECT").arg_int(db);
+
+    expect_ok(self.send_command(command).await?.into_data()?)?;
+
+    self.db = db;
+
+    Ok(())
+  }
+
+  /// Returns the channels the client is subscribed to, in the order they were subscribed to.
+  pub fn channels(&self) -> &[String] {
+    &self.channels
+  }
+
+  /// Switches the connection to `protocol` with HELLO, authenticating it in the same round trip
+  /// when `auth` has a username and a password.
+  ///
+  /// Fails with [RedisError::AuthFailed] if the server rejects the credentials, or if it requires
+  /// them and none were given, and with [RedisError::UnsupportedProtocol] if it can't speak `protocol`.
+  /// The protocol and the credentials are kept to switch and authenticate again after [Redis::reconnect].
+  ///
+  /// # Examples
+  ///
+  /// ```terminal
+  /// redis.hello(Protocol::Resp3, Some(("default", "secret"))).await? -> ServerHello { protocol: Resp3, .. }
+  /// redis.hello(Protocol::Resp3, Some(("default", "wrong"))).await?  -> Err(AuthFailed("WRONGPASS invalid username-password pair ..."))
+  /// ```
+  pub async fn hello(
+    &mut self,
+    protocol: Protocol,
+    auth: Option<(&str, &str)>,
+  ) -> Result<ServerHello> {
+    let version = match protocol {
+      Protocol::Resp2 => 2,
+      Protocol::Resp3 => 3,
+    };
+
+    let mut command = Command::new("HELLO").arg_int(version);
+
+    if let Some((username, password)) = auth {
+      command = command.arg("AUTH").arg(username).arg(password);
+    }
+
+    // The reply is a Map when switching to RESP3, which can't be parsed as RESP2.
+    let (previous_protocol, previous_detect_protocol) = (self.protocol, self.detect_protocol);
+    self.protocol = Protocol::Resp3;
+    self.detect_protocol = false;
+
+    let hello = match self.send_command(command).await {
+      Ok(Reply::Ok(data_type)) => server_hello(data_type).map_err(Into::into),
+      Ok(Reply::Error(message)) => Err(hello_error(message).into()),
+      Err(err) => Err(err),
+    };
+
+    let hello = match hello {
+      Ok(hello) => hello,
+      Err(err) => {
+        self.protocol = previous_protocol;
+        self.detect_protocol = previous_detect_protocol;
+        return Err(err);
+      }
+    };
+
+    self.set_protocol(hello.protocol);
+    self.hello_protocol = Some(hello.protocol);
+    self.server_version = parse_version(&hello.version);
+
+    if let Some((username, password)) = auth {
+      self.credentials = Some((Some(username.to_owned()), password.to_owned()));
+    }
+
+    Ok(hello)
+  }
+
+  /// Returns the latest `count` entries of the slowlog, newest first,
+  /// or the server's default number of entries if `count` is `None`.
+  pub async fn slowlog_get(&mut self, count: Option<u64>) -> Result<Vec<SlowlogEntry>> {
+    let mut command = Command::new("SLOWLOG").arg("GET");
+
+    if let Some(count) = count {
+      command = command.arg_int(count);
+    }
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::Array(entries) => Ok(
+        entries
+          .into_iter()
+          .map(slowlog_entry)
+          .collect::<Result<_, _>>()?,
+      ),
+      data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+    }
+  }
+
+  /// Sends every command in `commands` at once, like [Redis::pipeline] does, and discards their replies,
+  /// for when the replies don't matter, like when bulk loading keys.
+  ///
+  /// The replies still have to be read to stay in sync with the server, but they are read
+  /// in one batch into the same buffer without being returned, so error replies are discarded too.
+  ///
+  /// # Examples
+  ///
+  /// ```terminal
+  /// redis.pipeline_ignore(vec![
+  ///   Command::new("SET").arg("key:1").arg("a"),
+  ///   Command::new("SET").arg("key:2").arg("b"),
+  ///   Command::new("INCR").arg("counter"),
+  /// ]).await?;
+  /// ```
+  pub async fn pipeline_ignore(&mut self, commands: Vec<Command>) -> Result<()> {
+    let mut encoded = Vec::new();
+
+    for command in &commands {
+      if let Some(table) = &self.command_table {
+        table.check_arity(command)?;
+      }
+
+      let bytes = command.encode();
+      self.prepare_request(&command.name(), &bytes)?;
+      encoded.extend_from_slice(&bytes);
+    }
+
+    info!(
+      commands = commands.len(),
+      "sending pipeline, ignoring replies"
+    );
+
+    self.write_command(&encoded).await?;
+
+    let mut buf = Vec::new();
+
+    for _ in 0..commands.len() {
+      self.read_reply_with(&mut buf).await?;
+    }
+
+    Ok(())
+  }
+
+  /// Returns the version of the server as (major, minor, patch), like (7, 0, 0).
+  ///
+  /// The version is learned from the reply to [Redis::hello] or asked for with INFO
+  /// the first time it's needed, and kept until [Redis::reconnect].
+  pub async fn server_version(&mut self) -> Result<(u64, u64, u64)> {
+    if let Some(version) = self.server_version {
+      return Ok(version);
+    }
+
+    let command = Command::new("INFO").arg("server");
+
+    let info = match self.send_command(command).await?.into_data()? {
+      // A Verbatim String in RESP3, which is parsed as a Bulk String.
+      DataType::BulkString(info) => String::from_utf8_lossy(&info).to_string(),
+      data_type => return Err(RedisError::UnexpectedReply(data_type).into()),
+    };
+
+    let version = info
+      .lines()
+      .find_map(|line| line.strip_prefix("redis_version:"))
+      .and_then(parse_version)
+      .ok_or_else(|| {
+        RedisError::UnexpectedReply(DataType::BulkString(info.clone().into_bytes()))
+      })?;
+
+    self.server_version = Some(version);
+
+    Ok(version)
+  }
+
+  /// Sets the string stored at `key` to `value`, returning the string it replaced,
+  /// or `None` if `key` did not exist.
+  ///
+  /// GETSET is deprecated since Redis 6.2, so SET with the GET option is sent instead
+  /// to servers that support it, see [Redis::server_version].
+  pub async fn getset(&mut self, key: &str, value: &[u8]) -> Result<Option<Vec<u8>>> {
+    let command = if self.server_version().await? >= (6, 2, 0) {
+      Command::new("SET").arg(key).arg(value).arg("GET")
+    } else {
+      Command::new("GETSET").arg(key).arg(value)
+    };
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::BulkString(value) => Ok(Some(value)),
+      DataType::Null => Ok(None),
+      data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+    }
+  }
+
+  /// Returns the fields and values of the hash stored at `key`, or no fields if `key` does not exist.
+  ///
+  /// Small hashes are encoded as listpacks and read with a single HGETALL, while big hashes
+  /// are encoded as hashtables and read with HSCAN, some fields at a time, so the server
+  /// doesn't have to build a huge reply and block other clients while doing it.
+  /// A hash changed while it's being scanned may be returned with only some of the changes.
+  pub async fn hgetall_smart(&mut self, key: &str) -> Result<HashMap<Vec<u8>, Vec<u8>>> {
+    match self.object_encoding(key).await?.as_deref() {
+      None => Ok(HashMap::new()),
+      Some("hashtable") => {
+        let mut entries = HashMap::new();
+        let mut cursor = String::from("0");
+
+        loop {
+          let command = Command::new("HSCAN")
+            .arg(key)
+            .arg(&cursor)
+            .arg("COUNT")
+            .arg_int(HSCAN_COUNT);
+
+          let reply = self.send_command(command).await?.into_data()?;
+
+          let (next_cursor, fields): (String, DataType) =
+            reply.try_into().map_err(RedisError::from)?;
+
+          // A field may be returned more than once, the last value is kept.
+          entries.extend(pairs(fields)?);
+
+          cursor = next_cursor;
+
+          if cursor == "0" {
+            return Ok(entries);
+          }
+        }
+      }
+      Some(_) => {
+        let command = Command::new("HGETALL").arg(key);
+
+        let entries = hash_entries(self.send_command(command).await?.into_data()?)?;
+
+        Ok(entries.into_iter().collect())
+      }
+    }
+  }
+
+  /// Returns the members of the sorted set stored at `key` with scores between `min` and `max`,
+  /// from the lowest score to the highest, with their scores if `withscores` is true.
+  ///
+  /// `limit` skips the first `offset` members and returns at most `count` members.
+  ///
+  /// # Examples
+  ///
+  /// ```terminal
+  /// redis.zrangebyscore("myzset", ScoreBound::Exclusive(1.0), ScoreBound::PosInf, true, None).await?
+  ///   -> [("two", Some(2.0)), ("three", Some(3.0))]
+  /// ```
+  pub async fn zrangebyscore(
+    &mut self,
+    key: &str,
+    min: ScoreBound,
+    max: ScoreBound,
+    withscores: bool,
+    limit: Option<(i64, u64)>,
+  ) -> Result<Vec<(Vec<u8>, Option<f64>)>> {
+    let mut command = Command::new("ZRANGEBYSCORE")
+      .arg(key)
+      .arg(min.to_arg())
+      .arg(max.to_arg());
+
+    if withscores {
+      command = command.arg("WITHSCORES");
+    }
+
+    if let Some((offset, count)) = limit {
+      command = command.arg("LIMIT").arg_int(offset).arg_int(count);
+    }
+
+    let reply = self.send_command(command).await?.into_data()?;
+
+    if !withscores {
+      return Ok(
+        bulk_strings(reply)?
+          .into_iter()
+          .map(|member| (member, None))
+          .collect(),
+      );
+    }
+
+    match reply {
+      DataType::Array(elements) => Ok(
+        scored_members(elements)?
+          .into_iter()
+          .map(|(member, score)| (member, Some(score)))
+          .collect(),
+      ),
+      data_type => Err(RedisError::UnexpectedReply(data_type).into()),
+    }
+  }
+
+  /// Returns the members of the sorted set stored at `key` between `min` and `max`,
+  /// compared byte by byte, which only makes sense when every member has the same score.
+  ///
+  /// `limit` skips the first `offset` members and returns at most `count` members.
+  pub async fn zrangebylex(
+    &mut self,
+    key: &str,
+    min: LexBound,
+    max: LexBound,
+    limit: Option<(i64, u64)>,
+  ) -> Result<Vec<Vec<u8>>> {
+    let mut command = Command::new("ZRANGEBYLEX")
+      .arg(key)
+      .arg(min.to_arg())
+      .arg(max.to_arg());
+
+    if let Some((offset, count)) = limit {
+      command = command.arg("LIMIT").arg_int(offset).arg_int(count);
+    }
+
+    Ok(bulk_strings(
+      self.send_command(command).await?.into_data()?,
+    )?)
+  }
+
+  /// Returns the members of the union of the sorted sets stored at `keys`,
+  /// ordered by the sum of their scores.
+  pub async fn zunion(&mut self, keys: &[&str]) -> Result<Vec<Vec<u8>>> {
+    let command = Command::new("ZUNION").args_with_numkeys(keys);
+
+    Ok(bulk_strings(
+      self.send_command(command).await?.into_data()?,
+    )?)
+  }
+
+  /// Returns the members of the intersection of the sorted sets stored at `keys`,
+  /// ordered by the sum of their scores.
+  pub async fn zinter(&mut self, keys: &[&str]) -> Result<Vec<Vec<u8>>> {
+    let command = Command::new("ZINTER").args_with_numkeys(keys);
+
+    Ok(bulk_strings(
+      self.send_command(command).await?.into_data()?,
+    )?)
+  }
+
+  /// Returns the members of the first sorted set stored at `keys` that are not in any of the others.
+  pub async fn zdiff(&mut self, keys: &[&str]) -> Result<Vec<Vec<u8>>> {
+    let command = Command::new("ZDIFF").args_with_numkeys(keys);
+
+    Ok(bulk_strings(
+      self.send_command(command).await?.into_data()?,
+    )?)
+  }
+
+  /// Pops up to `count` elements from `end` of the first non-empty list stored at `keys`.
+  ///
+  /// Returns the key of the list and the popped elements, or `None` if every list is empty.
+  pub async fn lmpop(
+    &mut self,
+    keys: &[&str],
+    end: ListEnd,
+    count: u64,
+  ) -> Result<Option<(String, Vec<Vec<u8>>)>> {
+    let command = Command::new("LMPOP")
+      .args_with_numkeys(keys)
+      .arg(end.as_str())
+      .arg("COUNT")
+      .arg_int(count);
+
+    match self.send_command(command).await?.into_data()? {
+      DataType::Null => Ok(None),
+      reply => {
+        let (key, elements): (Vec<u8>, DataType) = reply.try_into().map_err(RedisError::from)?;
+
+        Ok(Some((
+          String::from_utf8_lossy(&key).to_string(),
+          bulk_strings(elements)?,
+        )))
+      }
+    }
+  }
+}
+
+/// Converts an Array of Bulk Strings into the bytes of each string.
+fn bulk_strings(data_type: DataType) -> Result<Vec<Vec<u8>>, RedisError> {
+  match data_type {
+    DataType::Array(elements) => elements
+      .into_iter()
+      .map(|element| match element {
+        DataType::BulkString(bytes) => Ok(bytes),
+        data_type => Err(RedisError::UnexpectedReply(data_type)),
+      })
+      .collect(),
+    data_type => Err(RedisError::UnexpectedReply(data_type)),
+  }
+}
+
+/// Groups an Array of Bulk Strings like [field1, value1, field2, value2] into pairs.
+///
+/// An Array with an odd number of elements is rejected instead of dropping the last one.
+fn pairs(data_type: DataType) -> Result<HashEntries, RedisError> {
+  if let DataType::Array(elements) = &data_type {
+    if elements.len() % 2 != 0 {
+      return Err(RedisError::UnexpectedReply(data_type));
+    }
+  }
+
+  let mut elements = bulk_strings(data_type)?.into_iter();
+
+  let mut pairs = Vec::new();
+
+  while let (Some(first), Some(second)) = (elements.next(), elements.next()) {
+    pairs.push((first, second));
+  }
+
+  Ok(pairs)
+}
+
+/// Converts the reply of a HELP subcommand into its lines.
+fn help_lines(data_type: DataType) -> Result<Vec<String>, RedisError> {
+  match data_type {
+    DataType::Array(lines) => lines
+      .into_iter()
+      .map(|line| match line {
+        DataType::SimpleString(line) => Ok(line),
+        DataType::BulkString(line) => Ok(String::from_utf8_lossy(&line).to_string()),
+        data_type => Err(RedisError::UnexpectedReply(data_type)),
+      })
+      .collect(),
+    data_type => Err(RedisError::UnexpectedReply(data_type)),
+  }
+}
+
+/// Returns the argument used by commands that turn a mode "ON" or "OFF".
+fn on_off(on: bool) -> &'static str {
+  if on {
+    "ON"
+  } else {
+    "OFF"
+  }
+}
+
+/// Returns an error unless `data_type` is the "+OK" reply.
+fn expect_ok(data_type: DataType) -> Result<(), RedisError> {
+  match data_type {
+    DataType::SimpleString(string) if string == "OK" => Ok(()),
+    data_type => Err(RedisError::UnexpectedReply(data_type)),
+  }
+}
+
+/// Converts a reply with field names followed by their values,
+/// like [name1, value1, name2, value2] or a RESP3 Map, into (name, value) pairs.
+fn map_entries(data_type: DataType) -> Result<Vec<(String, DataType)>, RedisError> {
+  let pairs = match data_type {
+    DataType::Map(pairs) => pairs,
+    DataType::Array(elements) if elements.len() % 2 == 0 => {
+      let mut elements = elements.into_iter();
+
+      let mut pairs = Vec::new();
+
+      while let (Some(name), Some(value)) = (elements.next(), elements.next()) {
+        pairs.push((name, value));
+      }
+
+      pairs
+    }
+    data_type => return Err(RedisError::UnexpectedReply(data_type)),
+  };
+
+  let mut entries = Vec::with_capacity(pairs.len());
+
+  for (name, value) in pairs {
+    let name = match name {
+      DataType::BulkString(name) => String::from_utf8_lossy(&name).to_string(),
+      DataType::SimpleString(name) => name,
+      data_type => return Err(RedisError::UnexpectedReply(data_type)),
+    };
+
+    entries.push((name, value));
+  }
+
+  Ok(entries)
+}
+
+/// Converts the reply of XINFO STREAM into [StreamInfo].
+fn stream_info(data_type: DataType) -> Result<StreamInfo, RedisError> {
+  let mut info = StreamInfo {
+    length: 0,
+    last_generated_id: String::new(),
+    groups: 0,
+    first_entry: None,
+    last_entry: None,
+  };
+
+  for (name, value) in map_entries(data_type)? {
+    match (name.as_str(), value) {
+      ("length", DataType::Int(length)) => info.length = length as u64,
+      ("groups", DataType::Int(groups)) => info.groups = groups as u64,
+      ("last-generated-id", DataType::BulkString(id)) => {
+        info.last_generated_id = String::from_utf8_lossy(&id).to_string()
+      }
+      ("first-entry", entry) => info.first_entry = stream_entry_or_nil(entry)?,
+      ("last-entry", entry) => info.last_entry = stream_entry_or_nil(entry)?,
+      // Fields we don't know about, like the ones added in newer versions.
+      _ => {}
+    }
+  }
+
+  Ok(info)
+}
+
+/// Converts the reply of CLIENT TRACKINGINFO, a Map in RESP3 and a flat list of names
+/// and values in RESP2, into [TrackingInfo]. The flags are a Set in RESP3.
+fn tracking_info(data_type: DataType) -> Result<TrackingInfo, RedisError> {
+  let mut info = TrackingInfo::default();
+
+  for (name, value) in map_entries(data_type)? {
+    match (name.as_str(), value) {
+      ("flags", DataType::Array(flags)) | ("flags", DataType::Set(flags)) => {
+        info.flags = flags
+          .into_iter()
+          .map(|flag| match flag {
+            DataType::SimpleString(flag) => Ok(flag),
+            DataType::BulkString(flag) => Ok(String::from_utf8_lossy(&flag).to_string()),
+            data_type => Err(RedisError::UnexpectedReply(data_type)),
+          })
+          .collect::<Result<_, _>>()?
+      }
+      ("redirect", DataType::Int(redirect)) => info.redirect = redirect,
+      ("prefixes", prefixes) => info.prefixes = bulk_strings(prefixes)?,
+      _ => {}
+    }
+  }
+
+  Ok(info)
+}
+
+/// Converts the reply of XPENDING without a range, [count, min-id, max-id, [[consumer, count], ...]],
+/// into [PendingSummary]. The ids and the consumers are Null when there are no pending messages.
+fn pending_summary(data_type: DataType) -> Result<PendingSummary, RedisError> {
+  let elements = match data_type {
+    DataType::Array(elements) => elements,
+    data_type => return Err(RedisError::UnexpectedReply(data_type)),
+  };
+
+  let [count, min_id, max_id, consumers] = match <[DataType; 4]>::try_from(elements) {
+    Ok(elements) => elements,
+    Err(elements) => return Err(RedisError::UnexpectedReply(DataType::Array(elements))),
+  };
+
+  let id = |data_type: DataType| match data_type {
+    DataType::Null => Ok(None),
+    DataType::BulkString(id) => Ok(Some(String::from_utf8_lossy(&id).to_string())),
+    data_type => Err(RedisError::UnexpectedReply(data_type)),
+  };
+
+  let consumers = match consumers {
+    DataType::Null => Vec::new(),
+    DataType::Array(consumers) => consumers
+      .into_iter()
+      .map(|consumer| match consumer {
+        DataType::Array(pair) => match <[DataType; 2]>::try_from(pair) {
+          Ok([DataType::BulkString(name), DataType::BulkString(count)]) => {
+            match String::from_utf8_lossy(&count).parse::<u64>() {
+              Ok(count) => Ok((String::from_utf8_lossy(&name).to_string(), count)),
+              Err(_) => Err(RedisError::UnexpectedReply(DataType::BulkString(count))),
+            }
+          }
+          Ok(pair) => Err(RedisError::UnexpectedReply(DataType::Array(pair.into()))),
+          Err(pair) => Err(RedisError::UnexpectedReply(DataType::Array(pair))),
+        },
+        data_type => Err(RedisError::UnexpectedReply(data_type)),
+      })
+      .collect::<Result<_, _>>()?,
+    data_type => return Err(RedisError::UnexpectedReply(data_type)),
+  };
+
+  Ok(PendingSummary {
+    count: match count {
+      DataType::Int(count) => count as u64,
+      data_type => return Err(RedisError::UnexpectedReply(data_type)),
+    },
+    min_id: id(min_id)?,
+    max_id: id(max_id)?,
+    consumers,
+  })
+}
+
+/// Converts a message of XPENDING with a range, [id, consumer, idle milliseconds, deliveries],
+/// into [PendingMessage].
+fn pending_message(data_type: DataType) -> Result<PendingMessage, RedisError> {
+  let elements = match data_type {
+    DataType::Array(elements) => elements,
+    data_type => return Err(RedisError::UnexpectedReply(data_type)),
+  };
+
+  match <[DataType; 4]>::try_from(elements) {
+    Ok(
+      [DataType::BulkString(id), DataType::BulkString(consumer), DataType::Int(idle), DataType::Int(deliveries)],
+    ) => Ok(PendingMessage {
+      id: String::from_utf8_lossy(&id).to_string(),
+      consumer: String::from_utf8_lossy(&consumer).to_string(),
+      idle: Duration::from_millis(idle.max(0) as u64),
+      deliveries: deliveries as u64,
+    }),
+    Ok(elements) => Err(RedisError::UnexpectedReply(DataType::Array(
+      elements.into(),
+    ))),
+    Err(elements) => Err(RedisError::UnexpectedReply(DataType::Array(elements))),
+  }
+}
+
+/// The (field, value) pairs of a hash.
+type HashEntries = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// Converts the reply of HGETALL, a Map in RESP3 and a list like [field1, value1, ...] in RESP2,
+/// into (field, value) pairs.
+fn hash_entries(data_type: DataType) -> Result<HashEntries, RedisError> {
+  match data_type {
+    DataType::Map(entries) => entries
+      .into_iter()
+      .map(|(field, value)| match (field, value) {
+        (DataType::BulkString(field), DataType::BulkString(value)) => Ok((field, value)),
+        (field, value) => Err(RedisError::UnexpectedReply(DataType::Array(vec![
+          field, value,
+        ]))),
+      })
+      .collect(),
+    data_type => pairs(data_type),
+  }
+}
+
+/// Converts a stream entry like [id, [field1, value1, ...]] into [StreamEntry].
+fn stream_entry_or_nil(data_type: DataType) -> Result<Option<StreamEntry>, RedisError> {
+  match data_type {
+    DataType::Null => Ok(None),
+    DataType::Array(elements) => match <[DataType; 2]>::try_from(elements) {
+      Ok([DataType::BulkString(id), fields]) => Ok(Some(StreamEntry {
+        id: String::from_utf8_lossy(&id).to_string(),
+        fields: pairs(fields)?,
+      })),
+      Ok(elements) => Err(RedisError::UnexpectedReply(DataType::Array(
+        elements.into(),
+      ))),
+      Err(elements) => Err(RedisError::UnexpectedReply(DataType::Array(elements))),
+    },
+    data_type => Err(RedisError::UnexpectedReply(data_type)),
+  }
+}
+
+/// Converts the reply of LCS into [LcsResult].
+///
+/// The reply is a Bulk String, an Integer when LEN is used
+/// or a map like {"matches": [...], "len": 6} when IDX is used.
+fn lcs_result(data_type: DataType) -> Result<LcsResult, RedisError> {
+  let entries = match data_type {
+    DataType::BulkString(string) => return Ok(LcsResult::String(string)),
+    DataType::Int(len) => return Ok(LcsResult::Len(len as u64)),
+    data_type => map_entries(data_type)?,
+  };
+
+  let mut matches = Vec::new();
+  let mut len = 0;
+
+  for (name, value) in entries {
+    match (name.as_str(), value) {
+      ("matches", DataType::Array(elements)) => {
+        matches = elements
+          .into_iter()
+          .map(lcs_match)
+          .collect::<Result<_, _>>()?
+      }
+      ("len", DataType::Int(length)) => len = length as u64,
+      (_, data_type) => return Err(RedisError::UnexpectedReply(data_type)),
+    }
+  }
+
+  Ok(LcsResult::Matches { matches, len })
+}
+
+/// Converts a match like [[start1, end1], [start2, end2], len] into [LcsMatch],
+/// where len is only sent when WITHMATCHLEN is used.
+fn lcs_match(data_type: DataType) -> Result<LcsMatch, RedisError> {
+  let range = |data_type: DataType| match data_type {
+    DataType::Array(elements) => match elements.as_slice() {
+      [DataType::Int(start), DataType::Int(end)] => Ok((*start as u64, *end as u64)),
+      _ => Err(RedisError::UnexpectedReply(DataType::Array(elements))),
+    },
+    data_type => Err(RedisError::UnexpectedReply(data_type)),
+  };
+
+  let mut elements = match data_type {
+    DataType::Array(elements) if elements.len() == 2 || elements.len() == 3 => elements.into_iter(),
+    data_type => return Err(RedisError::UnexpectedReply(data_type)),
+  };
+
+  let key1 = range(elements.next().unwrap_or(DataType::Null))?;
+  let key2 = range(elements.next().unwrap_or(DataType::Null))?;
+
+  let len = match elements.next() {
+    None => None,
+    Some(DataType::Int(len)) => Some(len as u64),
+    Some(data_type) => return Err(RedisError::UnexpectedReply(data_type)),
+  };
+
+  Ok(LcsMatch { key1, key2, len })
+}
+
+/// Converts the reply of EXPIRETIME or PEXPIRETIME into [ExpireTime],
+/// where `unit` converts the unix time in the reply into a [Duration].
+///
+/// Like TTL, -2 means the key does not exist and -1 that it has no expiry.
+fn expire_time(data_type: DataType, unit: fn(u64) -> Duration) -> Result<ExpireTime, RedisError> {
+  match data_type {
+    DataType::Int(-2) => Ok(ExpireTime::NoKey),
+    DataType::Int(-1) => Ok(ExpireTime::NoExpiry),
+    DataType::Int(time) if time >= 0 => Ok(ExpireTime::At(UNIX_EPOCH + unit(time as u64))),
+    data_type => Err(RedisError::UnexpectedReply(data_type)),
+  }
+}
+
+/// Converts a range like [start, end, master, replica1, replica2, ...] into [SlotRange].
+fn slot_range(data_type: DataType) -> Result<SlotRange, RedisError> {
+  let mut elements = match data_type {
+    DataType::Array(elements) if elements.len() >= 3 => elements.into_iter(),
+    data_type => return Err(RedisError::UnexpectedReply(data_type)),
+  };
+
+  let slot = |data_type: Option<DataType>| match data_type {
+    Some(DataType::Int(slot)) if (0..16384).contains(&slot) => Ok(slot as u16),
+    data_type => Err(RedisError::UnexpectedReply(
+      data_type.unwrap_or(DataType::Null),
+    )),
+  };
+
+  let start = slot(elements.next())?;
+  let end = slot(elements.next())?;
+  let master = node_addr(elements.next().unwrap_or(DataType::Null))?;
+  let replicas = elements.map(node_addr).collect::<Result<_, _>>()?;
+
+  Ok(SlotRange {
+    start,
+    end,
+    master,
+    replicas,
+  })
+}
+
+/// Converts a node like [host, port, id, metadata] into [NodeAddr],
+/// where id is only sent since Redis 4 and metadata, which is ignored, since Redis 7.
+fn node_addr(data_type: DataType) -> Result<NodeAddr, RedisError> {
+  let mut elements = match data_type {
+    DataType::Array(elements) if elements.len() >= 2 => elements.into_iter(),
+    data_type => return Err(RedisError::UnexpectedReply(data_type)),
+  };
+
+  let host = match elements.next() {
+    Some(DataType::BulkString(host)) => String::from_utf8_lossy(&host).into_owned(),
+    Some(DataType::SimpleString(host)) => host,
+    data_type => {
+      return Err(RedisError::UnexpectedReply(
+        data_type.unwrap_or(DataType::Null),
+      ))
+    }
+  };
+
+  let port = match elements.next() {
+    Some(DataType::Int(port)) if (0..=u16::MAX as i64).contains(&port) => port as u16,
+    data_type => {
+      return Err(RedisError::UnexpectedReply(
+        data_type.unwrap_or(DataType::Null),
+      ))
+    }
+  };
+
+  let id = match elements.next() {
+    None => None,
+    Some(DataType::BulkString(id)) => Some(String::from_utf8_lossy(&id).into_owned()),
+    Some(DataType::SimpleString(id)) => Some(id),
+    Some(data_type) => return Err(RedisError::UnexpectedReply(data_type)),
+  };
+
+  Ok(NodeAddr { host, port, id })
+}
+
+/// Converts the reply of COMMAND, an array of entries like [name, arity, flags, ...], into [CommandTable].
+///
+/// Entries have more elements, like the key positions, that are ignored.
+fn command_table(data_type: DataType) -> Result<CommandTable, RedisError> {
+  let entries = match data_type {
+    DataType::Array(entries) => entries,
+    data_type => return Err(RedisError::UnexpectedReply(data_type)),
+  };
+
+  let mut commands = HashMap::with_capacity(entries.len());
+
+  for entry in entries {
+    let info = command_info(entry)?;
+    commands.insert(info.name.clone(), info);
+  }
+
+  Ok(CommandTable { commands })
+}
+
+fn command_info(data_type: DataType) -> Result<CommandInfo, RedisError> {
+  let elements = match data_type {
+    DataType::Array(elements) if elements.len() >= 3 => elements,
+    data_type => return Err(RedisError::UnexpectedReply(data_type)),
+  };
+
+  match &elements[..3] {
+    [DataType::BulkString(name), DataType::Int(arity), DataType::Array(flags) | DataType::Set(flags)] =>
+    {
+      let flags = flags
+        .iter()
+        .map(|flag| match flag {
+          DataType::SimpleString(flag) => Ok(flag.clone()),
+          data_type => Err(RedisError::UnexpectedReply(data_type.clone())),
+        })
+        .collect::<Result<_, _>>()?;
+
+      Ok(CommandInfo {
+        name: String::from_utf8_lossy(name).to_lowercase(),
+        arity: *arity,
+        flags,
+      })
+    }
+    _ => Err(RedisError::UnexpectedReply(DataType::Array(elements))),
+  }
+}
+
+/// Formats `data_type` like redis-cli does, ending every line with "\n".
+///
+/// Every line but the first starts with `prefix`, which indents the elements of nested aggregates.
+fn cli_format(data_type: &DataType, prefix: &str) -> String {
+  match data_type {
+    DataType::SimpleString(string) => format!("{}\n", string),
+    DataType::Error(message) => format!("(error) {}\n", message),
+    DataType::Int(int) => format!("(integer) {}\n", int),
+    DataType::Double(double) => format!("(double) {}\n", double),
+    DataType::BulkString(bytes) => format!("{}\n", cli_quote(bytes)),
+    DataType::Boolean(boolean) => format!("({})\n", boolean),
+    DataType::BigNumber(number) => format!("(big number) {}\n", number),
+    DataType::Null => String::from("(nil)\n"),
+    DataType::Array(elements) => cli_format_aggregate(
+      elements.iter().map(|element| (element, None)).collect(),
+      ')',
+      "array",
+      prefix,
+    ),
+    DataType::Set(elements) => cli_format_aggregate(
+      elements.iter().map(|element| (element, None)).collect(),
+      '~',
+      "set",
+      prefix,
+    ),
+    DataType::Push(elements) => cli_format_aggregate(
+      elements.iter().map(|element| (element, None)).collect(),
+      ')',
+      "push",
+      prefix,
+    ),
+    DataType::Map(entries) => cli_format_aggregate(
+      entries
+        .iter()
+        .map(|(key, value)| (key, Some(value)))
+        .collect(),
+      '#',
+      "hash",
+      prefix,
+    ),
+  }
+}
+
+/// Formats the entries of an aggregate as a numbered list like "1) ...", where the numbers
+/// are right aligned and followed by `separator`. Map entries are formatted as "key => value".
+fn cli_format_aggregate(
+  entries: Vec<(&DataType, Option<&DataType>)>,
+  separator: char,
+  name: &str,
+  prefix: &str,
+) -> String {
+  if entries.is_empty() {
+    return format!("(empty {})\n", name);
+  }
+
+  let index_len = entries.len().to_string().len();
+
+  // Nested values are aligned with the value after "1) ".
+  let nested_prefix = format!("{}{}", prefix, " ".repeat(index_len + 2));
+
+  let mut output = String::new();
+
+  for (i, (element, value)) in entries.into_iter().enumerate() {
+    // The first line is already indented by whoever formats the aggregate.
+    if i > 0 {
+      output.push_str(prefix);
+    }
+
+    output.push_str(&format!(
+      "{:>width$}{} ",
+      i + 1,
+      separator,
+      width = index_len
+    ));
+    output.push_str(&cli_format(element, &nested_prefix));
+
+    if let Some(value) = value {
+      output.pop();
+      output.push_str(" => ");
+      output.push_str(&cli_format(value, &nested_prefix));
+    }
+  }
+
+  output
+}
+
+/// Quotes `bytes` like redis-cli does, escaping quotes, backslashes and bytes that are not printable.
+fn cli_quote(bytes: &[u8]) -> String {
+  let mut quoted = String::from("\"");
+
+  for byte in bytes {
+    match byte {
+      b'\\' => quoted.push_str("\\\\"),
+      b'"' => quoted.push_str("\\\""),
+      b'\n' => quoted.push_str("\\n"),
+      b'\r' => quoted.push_str("\\r"),
+      b'\t' => quoted.push_str("\\t"),
+      0x07 => quoted.push_str("\\a"),
+      0x08 => quoted.push_str("\\b"),
+      byte if byte.is_ascii_graphic() || *byte == b' ' => quoted.push(*byte as char),
+      byte => quoted.push_str(&format!("\\x{:02x}", byte)),
+    }
+  }
+
+  quoted.push('"');
+
+  quoted
+}
+
+/// Converts the reply of ACL GETUSER into [AclUser].
+///
+/// The reply is a list like [name1, value1, name2, value2, ...] in RESP2 and a map in RESP3.
+/// Keys and channels are lists of patterns before Redis 7 and a single string of
+/// space separated patterns since then.
+fn acl_user(data_type: DataType) -> Result<AclUser, RedisError> {
+  let mut user = AclUser::default();
+
+  for (name, value) in map_entries(data_type)? {
+    match (name.as_str(), value) {
+      ("flags", value) => user.flags = acl_patterns(value)?,
+      ("passwords", value) => user.passwords = acl_patterns(value)?,
+      ("commands", DataType::BulkString(commands)) => {
+        user.commands = String::from_utf8_lossy(&commands).to_string()
+      }
+      ("commands", data_type) => return Err(RedisError::UnexpectedReply(data_type)),
+      ("keys", value) => user.keys = acl_patterns(value)?,
+      ("channels", value) => user.channels = acl_patterns(value)?,
+      // Fields we don't know about, like the selectors added in Redis 7.
+      _ => {}
+    }
+  }
+
+  Ok(user)
+}
+
+/// Converts a list of strings, or a single string of space separated patterns, into the strings.
+fn acl_patterns(data_type: DataType) -> Result<Vec<String>, RedisError> {
+  let elements = match data_type {
+    DataType::Array(elements) | DataType::Set(elements) => elements,
+    DataType::BulkString(patterns) => {
+      return Ok(
+        String::from_utf8_lossy(&patterns)
+          .split_whitespace()
+          .map(String::from)
+          .collect(),
+      )
+    }
+    data_type => return Err(RedisError::UnexpectedReply(data_type)),
+  };
+
+  elements
+    .into_iter()
+    .map(|element| match element {
+      DataType::BulkString(string) => Ok(String::from_utf8_lossy(&string).to_string()),
+      DataType::SimpleString(string) => Ok(string),
+      data_type => Err(RedisError::UnexpectedReply(data_type)),
+    })
+    .collect()
+}
+
+/// Converts the reply of the command that reads a key of type `key_type` into [TypedValue],
+/// or `None` if the key does not exist.
+fn typed_value(key_type: &str, data_type: DataType) -> Result<Option<TypedValue>, RedisError> {
+  let value = match (key_type, data_type) {
+    (_, DataType::Null) => return Ok(None),
+    ("string", DataType::BulkString(value)) => TypedValue::String(value),
+    ("list", data_type) => TypedValue::List(bulk_strings(data_type)?),
+    ("set", DataType::Set(members)) => TypedValue::Set(bulk_strings(DataType::Array(members))?),
+    ("set", data_type) => TypedValue::Set(bulk_strings(data_type)?),
+    ("hash", data_type) => TypedValue::Hash(hash_entries(data_type)?),
+    ("zset", DataType::Array(elements)) => TypedValue::SortedSet(scored_members(elements)?),
+    (_, data_type) => return Err(RedisError::UnexpectedReply(data_type)),
+  };
+
+  let is_empty = match &value {
+    TypedValue::String(_) => false,
+    TypedValue::List(elements) | TypedValue::Set(elements) => elements.is_empty(),
+    TypedValue::Hash(entries) => entries.is_empty(),
+    TypedValue::SortedSet(members) => members.is_empty(),
+  };
+
+  // Reading a key that does not exist returns an empty list, set, hash or sorted set.
+  if is_empty {
+    return Ok(None);
+  }
+
+  Ok(Some(value))
+}
+
+/// Converts the reply of ZRANGE WITHSCORES into members and their scores.
+///
+/// The reply is a list like [member1, score1, member2, score2, ...] in RESP2
+/// and a list of pairs like [[member1, score1], ...] in RESP3, where scores are Doubles.
+fn scored_members(elements: Vec<DataType>) -> Result<Vec<(Vec<u8>, f64)>, RedisError> {
+  let pairs = if elements
+    .iter()
+    .all(|element| matches!(element, DataType::Array(_)))
+  {
+    elements
+      .into_iter()
+      .map(|element| match element {
+        DataType::Array(pair) if pair.len() == 2 => {
+          let mut pair = pair.into_iter();
+          Ok((pair.next().unwrap(), pair.next().unwrap()))
+        }
+        data_type => Err(RedisError::UnexpectedReply(data_type)),
+      })
+      .collect::<Result<Vec<_>, _>>()?
+  } else {
+    let mut elements = elements.into_iter();
+
+    let mut pairs = Vec::new();
+
+    while let (Some(member), Some(score)) = (elements.next(), elements.next()) {
+      pairs.push((member, score));
+    }
+
+    pairs
+  };
+
+  pairs
+    .into_iter()
+    .map(|pair| match pair {
+      (DataType::BulkString(member), DataType::Double(score)) => Ok((member, score)),
+      (DataType::BulkString(member), DataType::BulkString(score)) => {
+        match String::from_utf8_lossy(&score).parse::<f64>() {
+          Ok(score) => Ok((member, score)),
+          Err(_) => Err(RedisError::UnexpectedReply(DataType::BulkString(score))),
+        }
+      }
+      (member, score) => Err(RedisError::UnexpectedReply(DataType::Array(vec![
+        member, score,
+      ]))),
+    })
+    .collect()
+}
+
+/// Converts the 0 or 1 reply of SETBIT and GETBIT into a bool.
+fn bit(data_type: DataType) -> Result<bool, RedisError> {
+  match data_type {
+    DataType::Int(0) => Ok(false),
+    DataType::Int(1) => Ok(true),
+    data_type => Err(RedisError::UnexpectedReply(data_type)),
+  }
+}
+
+/// Rejects bit offsets of 2^32 and more, see [MAX_BIT_OFFSET].
+fn check_bit_offset(offset: u64) -> Result<(), RedisError> {
+  if offset >= MAX_BIT_OFFSET {
+    return Err(RedisError::InvalidArgument(format!(
+      "bit offset must be less than {}, got {}",
+      MAX_BIT_OFFSET, offset
+    )));
+  }
+
+  Ok(())
+}
+
+/// Converts the reply of HELLO into [ServerHello].
+fn server_hello(data_type: DataType) -> Result<ServerHello, RedisError> {
+  let mut hello = ServerHello {
+    server: String::new(),
+    version: String::new(),
+    protocol: Protocol::Resp2,
+    id: 0,
+    mode: String::new(),
+    role: String::new(),
+  };
+
+  let string = |value: DataType| match value {
+    DataType::BulkString(bytes) => Ok(String::from_utf8_lossy(&bytes).to_string()),
+    DataType::SimpleString(string) => Ok(string),
+    data_type => Err(RedisError::UnexpectedReply(data_type)),
+  };
+
+  for (name, value) in map_entries(data_type)? {
+    match (name.as_str(), value) {
+      ("server", value) => hello.server = string(value)?,
+      ("version", value) => hello.version = string(value)?,
+      ("proto", DataType::Int(2)) => hello.protocol = Protocol::Resp2,
+      ("proto", DataType::Int(3)) => hello.protocol = Protocol::Resp3,
+      ("proto", data_type) => return Err(RedisError::UnexpectedReply(data_type)),
+      ("id", DataType::Int(id)) => hello.id = id,
+      ("mode", value) => hello.mode = string(value)?,
+      ("role", value) => hello.role = string(value)?,
+      // Fields like "modules" and the ones added in newer versions.
+      _ => {}
+    }
+  }
+
+  Ok(hello)
+}
+
+/// Converts the error reply of HELLO into the error it stands for,
+/// telling rejected credentials apart from an unsupported protocol.
+fn hello_error(message: String) -> RedisError {
+  match message.split(' ').next() {
+    Some("WRONGPASS") | Some("NOAUTH") => RedisError::AuthFailed(message),
+    Some("NOPROTO") => RedisError::UnsupportedProtocol(message),
+    _ => server_error(message),
+  }
+}
+
+/// Returns the Integer at the start of `buffer` and how many bytes it takes,
+/// or `None` if `buffer` does not start with a complete Integer.
+fn int_frame(buffer: &[u8]) -> Option<(i64, usize)> {
+  let line = buffer.strip_prefix(b":")?;
+
+  let line_end = line.windows(2).position(|window| window == b"\r\n")?;
+
+  let n = std::str::from_utf8(&line[..line_end]).ok()?.parse().ok()?;
+
+  Some((n, 1 + line_end + 2))
+}
+
+/// Converts an error reply into the error it stands for, depending on its code,
+/// the first word of the message.
+fn server_error(message: String) -> RedisError {
+  match message.split(' ').next() {
+    Some("LOADING") => RedisError::Loading(message),
+    _ => RedisError::Server(message),
+  }
+}
+
+/// Converts an entry of SLOWLOG GET, [id, timestamp, microseconds, [args...], client_addr, client_name],
+/// into [SlowlogEntry]. Servers older than 4.0 don't send the last two elements.
+fn slowlog_entry(data_type: DataType) -> Result<SlowlogEntry, RedisError> {
+  let elements = match data_type {
+    DataType::Array(elements) if elements.len() == 4 || elements.len() == 6 => elements,
+    data_type => return Err(RedisError::UnexpectedReply(data_type)),
+  };
+
+  let mut elements = elements.into_iter();
+
+  let mut next_int = || match elements.next() {
+    Some(DataType::Int(n)) => Ok(n),
+    Some(data_type) => Err(RedisError::UnexpectedReply(data_type)),
+    None => unreachable!("the entry has at least 4 elements"),
+  };
+
+  let id = next_int()?;
+  let timestamp = next_int()?;
+  let microseconds = next_int()?;
+
+  let args = bulk_strings(elements.next().unwrap_or(DataType::Null))?;
+
+  let mut next_string = || match elements.next() {
+    Some(DataType::BulkString(bytes)) => Ok(String::from_utf8_lossy(&bytes).to_string()),
+    Some(data_type) => Err(RedisError::UnexpectedReply(data_type)),
+    None => Ok(String::new()),
+  };
+
+  Ok(SlowlogEntry {
+    id,
+    timestamp,
+    duration: Duration::from_micros(microseconds.max(0) as u64),
+    args,
+    client_addr: next_string()?,
+    client_name: next_string()?,
+  })
+}
+
+/// Converts a score, a Bulk String in RESP2 and a Double in RESP3, into an f64.
+fn score_or_nil(data_type: DataType) -> Result<Option<f64>, RedisError> {
+  match data_type {
+    DataType::Null => Ok(None),
+    DataType::Double(score) => Ok(Some(score)),
+    DataType::BulkString(score) => match String::from_utf8_lossy(&score).parse::<f64>() {
+      Ok(score) => Ok(Some(score)),
+      Err(_) => Err(RedisError::UnexpectedReply(DataType::BulkString(score))),
+    },
+    data_type => Err(RedisError::UnexpectedReply(data_type)),
+  }
+}
+
+/// Parses a version like "7.0.11" into (7, 0, 11).
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+  let mut parts = version
+    .trim()
+    .split('.')
+    .map(|part| part.parse::<u64>().ok());
+
+  match (parts.next(), parts.next(), parts.next()) {
+    (Some(Some(major)), Some(Some(minor)), Some(Some(patch))) => Some((major, minor, patch)),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::data_type::DataTypeKind;
+  use crate::test_server::{command_args, fake_redis, mock_server, scripted_server};
+  use tokio::io::duplex;
+
+  const TEST_REDIS_IP: &str = "127.0.0.1:6380";
+
+  #[tokio::test]
+  async fn basic_commands() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    assert_eq!(
+      Reply::Ok(DataType::SimpleString(String::from("OK"))),
+      redis.flushall().await?,
+    );
+
+    assert_reply_int!(redis.send("LLEN mylist").await?, 0);
+
+    assert_reply_int!(redis.send(r#"LPUSH mylist World"#).await?, 1);
+
+    assert_reply_int!(redis.send(r#"LPUSH mylist Hello"#).await?, 2);
+
+    assert_reply_int!(redis.send("LLEN mylist").await?, 2);
+
+    assert_reply_bulk!(redis.send("LPOP mylist").await?, "Hello");
+
+    assert_reply_bulk!(redis.send("LPOP mylist").await?, "World");
+
+    assert_reply_int!(redis.send("LLEN mylist").await?, 0);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn append() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis.send("DEL append:mykey").await?;
+
+    assert_eq!(5, redis.append("append:mykey", b"Hello").await?);
+
+    assert_eq!(11, redis.append("append:mykey", b" World").await?);
+
+    assert_reply_bulk!(redis.send("GET append:mykey").await?, "Hello World");
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn drain_pending() -> Result<()> {
+    let (client, mut server) = duplex(1024);
+
+    // Replies to commands sent before that were never read,
+    // followed by the reply to the ECHO command.
+    server
+      .write_all(b"+PONG\r\n*2\r\n$3\r\nfoo\r\n$3\r\nbar\r\n$5\r\nHello\r\n")
+      .await
+      .into_diagnostic()?;
+
+    let mut redis = Redis::from_stream(client);
+
+    redis.drain_pending(2).await?;
+
+    assert_eq!(
+      Reply::Ok(DataType::BulkString(b"Hello".to_vec())),
+      redis
+        .send_command(Command::new("ECHO").arg("Hello"))
+        .await?
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn large_command_is_written_through_small_write_windows() -> Result<()> {
+    // Each write can send at most 16 bytes before the other side reads them.
+    let (client, mut server) = duplex(16);
+
+    let command = Command::new("SET").arg("mykey").arg(vec![b'a'; 64 * 1024]);
+    let expected = command.encode();
+    let expected_len = expected.len();
+
+    let server = tokio::spawn(async move {
+      let mut received = Vec::new();
+      let mut buffer = [0; 16];
+
+      while received.len() < expected_len {
+        let bytes_read = server.read(&mut buffer).await.unwrap();
+        received.extend_from_slice(&buffer[..bytes_read]);
+      }
+
+      server.write_all(b"+OK\r\n").await.unwrap();
+
+      received
+    });
+
+    let mut redis = Redis::from_stream(client);
+
+    assert_eq!(
+      Reply::Ok(DataType::SimpleString(String::from("OK"))),
+      redis.send_command(command).await?,
+    );
+
+    assert_eq!(expected, server.await.into_diagnostic()?);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn memory_usage() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis.send("DEL memory_usage:mykey").await?;
+
+    assert_eq!(None, redis.memory_usage("memory_usage:mykey", None).await?);
+
+    redis.send("SET memory_usage:mykey Hello").await?;
+
+    assert!(
+      redis
+        .memory_usage("memory_usage:mykey", None)
+        .await?
+        .unwrap()
+        > 0
+    );
+    assert!(
+      redis
+        .memory_usage("memory_usage:mykey", Some(5))
+        .await?
+        .unwrap()
+        > 0
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn on_command() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+
+    let hook_seen = std::sync::Arc::clone(&seen);
+    redis.on_command(move |metrics| hook_seen.lock().unwrap().push(metrics.clone()));
+
+    redis.send("DEL on_command:mylist").await?;
+    redis.send("llen on_command:mylist").await?;
+    redis.append("on_command:mykey", b"Hello").await?;
+
+    let seen = seen.lock().unwrap();
+
+    assert_eq!(
+      vec!["DEL", "LLEN", "APPEND"],
+      seen
+        .iter()
+        .map(|metrics| metrics.command.as_str())
+        .collect::<Vec<_>>()
+    );
+
+    for metrics in seen.iter() {
+      assert!(metrics.bytes_sent > 0);
+      assert!(metrics.bytes_received > 0);
+      assert!(metrics.duration > Duration::ZERO);
+    }
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn srandmember() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis.send("DEL srandmember:myset").await?;
+
+    assert_eq!(
+      Vec::<Vec<u8>>::new(),
+      redis.srandmember("srandmember:myset", None).await?
+    );
+
+    redis.send("SADD srandmember:myset one two three").await?;
+
+    let members = redis.srandmember("srandmember:myset", None).await?;
+    assert_eq!(1, members.len());
+    assert!([b"one".to_vec(), b"two".to_vec(), b"three".to_vec()].contains(&members[0]));
+
+    assert_eq!(
+      2,
+      redis.srandmember("srandmember:myset", Some(2)).await?.len()
+    );
+    assert_eq!(
+      3,
+      redis
+        .srandmember("srandmember:myset", Some(10))
+        .await?
+        .len()
+    );
+    assert_eq!(
+      10,
+      redis
+        .srandmember("srandmember:myset", Some(-10))
+        .await?
+        .len()
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn spop_reply_shapes() -> Result<()> {
+    let (mut redis, server) = scripted_server(&[
+      b"$3\r\none\r\n",
+      b"*3\r\n$3\r\none\r\n$3\r\ntwo\r\n$5\r\nthree\r\n",
+      b"$-1\r\n",
+      b"*0\r\n",
+    ]);
+
+    assert_eq!(vec![b"one".to_vec()], redis.spop("myset", None).await?);
+    assert_eq!(
+      vec![b"one".to_vec(), b"two".to_vec(), b"three".to_vec()],
+      redis.spop("myset", Some(3)).await?
+    );
+    assert_eq!(Vec::<Vec<u8>>::new(), redis.spop("myset", None).await?);
+    assert_eq!(Vec::<Vec<u8>>::new(), redis.spop("myset", Some(3)).await?);
+
+    assert_eq!(
+      vec![
+        command_args(&["SPOP", "myset"]),
+        command_args(&["SPOP", "myset", "3"]),
+        command_args(&["SPOP", "myset"]),
+        command_args(&["SPOP", "myset", "3"]),
+      ],
+      server.await.unwrap()
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn spop() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis.send("DEL spop:myset").await?;
+
+    assert_eq!(Vec::<Vec<u8>>::new(), redis.spop("spop:myset", None).await?);
+    assert_eq!(
+      Vec::<Vec<u8>>::new(),
+      redis.spop("spop:myset", Some(3)).await?
+    );
+
+    redis.send("SADD spop:myset one two three four").await?;
+
+    let members = ["one", "two", "three", "four"].map(|member| member.as_bytes().to_vec());
+
+    let popped = redis.spop("spop:myset", None).await?;
+    assert_eq!(1, popped.len());
+    assert!(members.contains(&popped[0]));
+
+    let popped = redis.spop("spop:myset", Some(3)).await?;
+    assert_eq!(3, popped.len());
+    assert!(popped.iter().all(|member| members.contains(member)));
+
+    assert_eq!(Vec::<Vec<u8>>::new(), redis.spop("spop:myset", None).await?);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn hrandfield_withvalues() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis.send("DEL hrandfield:myhash").await?;
+    redis
+      .send("HSET hrandfield:myhash heads obverse tails reverse")
+      .await?;
+
+    let mut fields = redis.hrandfield_withvalues("hrandfield:myhash", 2).await?;
+    fields.sort();
+
+    assert_eq!(
+      vec![
+        (b"heads".to_vec(), b"obverse".to_vec()),
+        (b"tails".to_vec(), b"reverse".to_vec()),
+      ],
+      fields
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn hrandfield_withvalues_odd_reply() -> Result<()> {
+    let (mut redis, _server) =
+      scripted_server(&[b"*3\r\n$5\r\nheads\r\n$7\r\nobverse\r\n$5\r\ntails\r\n"]);
+
+    let err = redis.hrandfield_withvalues("myhash", 2).await.unwrap_err();
+    assert_eq!(
+      Some(&RedisError::UnexpectedReply(DataType::Array(vec![
+        DataType::BulkString(b"heads".to_vec()),
+        DataType::BulkString(b"obverse".to_vec()),
+        DataType::BulkString(b"tails".to_vec()),
+      ]))),
+      err.downcast_ref::<RedisError>()
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn sintercard() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis.send("DEL sintercard:key1 sintercard:key2").await?;
+    redis.send("SADD sintercard:key1 a b c d").await?;
+    redis.send("SADD sintercard:key2 c d e").await?;
+
+    assert_eq!(
+      2,
+      redis
+        .sintercard(&["sintercard:key1", "sintercard:key2"], None)
+        .await?
+    );
+
+    assert_eq!(
+      1,
+      redis
+        .sintercard(&["sintercard:key1", "sintercard:key2"], Some(1))
+        .await?
+    );
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_help_lines() {
+    let reply = resp::parse(
+      b"*3\r\n+OBJECT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:\r\n+ENCODING <key>\r\n+    Return the kind of internal representation used in order to store the value\r\n"
+        .to_vec(),
+    )
+    .unwrap();
+
+    assert_eq!(
+      Ok(vec![
+        String::from("OBJECT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:"),
+        String::from("ENCODING <key>"),
+        String::from(
+          "    Return the kind of internal representation used in order to store the value"
+        ),
+      ]),
+      help_lines(reply)
+    );
+
+    assert_eq!(
+      Err(RedisError::UnexpectedReply(DataType::Int(1))),
+      help_lines(DataType::Array(vec![DataType::Int(1)]))
+    );
+  }
+
+  #[tokio::test]
+  async fn help() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    let lines = redis.help("OBJECT").await?;
+
+    assert!(lines[0].starts_with("OBJECT <subcommand>"));
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn bitcount() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis.send("SET bitcount:mykey foobar").await?;
+
+    assert_eq!(26, redis.bitcount("bitcount:mykey", None).await?);
+    assert_eq!(
+      4,
+      redis
+        .bitcount("bitcount:mykey", Some((0, 0, BitUnit::Byte)))
+        .await?
+    );
+    assert_eq!(
+      6,
+      redis
+        .bitcount("bitcount:mykey", Some((1, 1, BitUnit::Byte)))
+        .await?
+    );
+    assert_eq!(
+      17,
+      redis
+        .bitcount("bitcount:mykey", Some((5, 30, BitUnit::Bit)))
+        .await?
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn smismember() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis.send("DEL smismember:myset").await?;
+    redis.send("SADD smismember:myset one two").await?;
+
+    assert_eq!(
+      vec![true, false, true, false],
+      redis
+        .smismember("smismember:myset", &[b"one", b"three", b"two", b"\x00"])
+        .await?
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn invalid_arguments_are_rejected_before_sending() -> Result<()> {
+    // The server never replies, the commands must fail without being sent.
+    let (client, _server) = duplex(64);
+
+    let mut redis = Redis::from_stream(client);
+
+    let err = redis.setbit("mykey", 1 << 32, true).await.unwrap_err();
+    assert_eq!(
+      Some(&RedisError::InvalidArgument(String::from(
+        "bit offset must be less than 4294967296, got 4294967296"
+      ))),
+      err.downcast_ref::<RedisError>()
+    );
+
+    let err = redis.expire("mykey", -1).await.unwrap_err();
+    assert_eq!(
+      Some(&RedisError::InvalidArgument(String::from(
+        "expire time must not be negative, got -1"
+      ))),
+      err.downcast_ref::<RedisError>()
+    );
+
+    let err = redis.del_matching("user:*", 0).await.unwrap_err();
+    assert_eq!(
+      Some(&RedisError::InvalidArgument(String::from(
+        "batch must be greater than 0"
+      ))),
+      err.downcast_ref::<RedisError>()
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn setbit() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis.send("DEL setbit:mykey").await?;
+
+    assert!(!redis.setbit("setbit:mykey", 7, true).await?);
+    assert!(redis.getbit("setbit:mykey", 7).await?);
+    assert!(!redis.getbit("setbit:mykey", 6).await?);
+
+    // Past the end of the string.
+    assert!(!redis.getbit("setbit:mykey", 100_000).await?);
+
+    assert!(redis.setbit("setbit:mykey", 7, false).await?);
+    assert!(!redis.getbit("setbit:mykey", 7).await?);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn expire() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis.send("DEL expire:mykey").await?;
+
+    assert!(!redis.expire("expire:mykey", 10).await?);
+
+    redis.send("SET expire:mykey Hello").await?;
+
+    assert!(redis.expire("expire:mykey", 10).await?);
+    assert_reply_int!(redis.send("TTL expire:mykey").await?, 10);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn client_no_evict() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis.client_no_evict(true).await?;
+    redis.client_no_evict(false).await?;
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn client_no_touch() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis.client_no_touch(true).await?;
+    redis.client_no_touch(false).await?;
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_expect_ok() {
+    assert_eq!(
+      Ok(()),
+      expect_ok(DataType::SimpleString(String::from("OK")))
+    );
+    assert_eq!(
+      Err(RedisError::UnexpectedReply(DataType::SimpleString(
+        String::from("QUEUED")
+      ))),
+      expect_ok(DataType::SimpleString(String::from("QUEUED")))
+    );
+  }
+
+  #[test]
+  fn test_stream_info() {
+    let reply = resp::parse(
+      b"*20\r\n\
+      $6\r\nlength\r\n:2\r\n\
+      $15\r\nradix-tree-keys\r\n:1\r\n\
+      $16\r\nradix-tree-nodes\r\n:2\r\n\
+      $17\r\nlast-generated-id\r\n$15\r\n1638125141232-0\r\n\
+      $20\r\nmax-deleted-entry-id\r\n$3\r\n0-0\r\n\
+      $13\r\nentries-added\r\n:2\r\n\
+      $23\r\nrecorded-first-entry-id\r\n$15\r\n1638125133432-0\r\n\
+      $6\r\ngroups\r\n:1\r\n\
+      $11\r\nfirst-entry\r\n*2\r\n$15\r\n1638125133432-0\r\n*2\r\n$7\r\nmessage\r\n$5\r\napple\r\n\
+      $10\r\nlast-entry\r\n*2\r\n$15\r\n1638125141232-0\r\n*2\r\n$7\r\nmessage\r\n$6\r\nbanana\r\n"
+        .to_vec(),
+    )
+    .unwrap();
+
+    assert_eq!(
+      Ok(StreamInfo {
+        length: 2,
+        last_generated_id: String::from("1638125141232-0"),
+        groups: 1,
+        first_entry: Some(StreamEntry {
+          id: String::from("1638125133432-0"),
+          fields: vec![(b"message".to_vec(), b"apple".to_vec())],
+        }),
+        last_entry: Some(StreamEntry {
+          id: String::from("1638125141232-0"),
+          fields: vec![(b"message".to_vec(), b"banana".to_vec())],
+        }),
+      }),
+      stream_info(reply)
+    );
+  }
+
+  #[tokio::test]
+  async fn xinfo_stream() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis.send("DEL xinfo:mystream").await?;
+    redis.send("XADD xinfo:mystream 1-1 message apple").await?;
+    redis.send("XADD xinfo:mystream 2-1 message banana").await?;
+
+    let info = redis.xinfo_stream("xinfo:mystream").await?;
+
+    assert_eq!(2, info.length);
+    assert_eq!("2-1", info.last_generated_id);
+    assert_eq!("1-1", info.first_entry.unwrap().id);
+    assert_eq!(
+      StreamEntry {
+        id: String::from("2-1"),
+        fields: vec![(b"message".to_vec(), b"banana".to_vec())],
+      },
+      info.last_entry.unwrap()
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn lrange() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis.send("DEL lrange:mylist").await?;
+    redis.send("RPUSH lrange:mylist one two three").await?;
+
+    assert_eq!(
+      vec![b"one".to_vec(), b"two".to_vec(), b"three".to_vec()],
+      redis.lrange("lrange:mylist", 0, -1).await?
+    );
+    assert_eq!(
+      vec![b"two".to_vec(), b"three".to_vec()],
+      redis.lrange("lrange:mylist", -2, -1).await?
+    );
+    assert_eq!(
+      Vec::<Vec<u8>>::new(),
+      redis.lrange("lrange:mylist", 5, 10).await?
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn lindex() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis.send("DEL lindex:mylist").await?;
+    redis.send("RPUSH lindex:mylist one two three").await?;
+
+    assert_eq!(
+      Some(b"one".to_vec()),
+      redis.lindex("lindex:mylist", 0).await?
+    );
+    assert_eq!(
+      Some(b"three".to_vec()),
+      redis.lindex("lindex:mylist", -1).await?
+    );
+    assert_eq!(None, redis.lindex("lindex:mylist", 3).await?);
+    assert_eq!(None, redis.lindex("lindex:mylist", -4).await?);
+
+    Ok(())
+  }
+
+  #[test]
+  fn sort_options() {
+    let tests = vec![
+      (Sort::new(), Command::new("SORT").arg("mylist")),
+      (
+        Sort::new()
+          .alpha()
+          .desc()
+          .get("#")
+          .get("weight_*")
+          .limit(0, 10)
+          .by("weight_*"),
+        Command::new("SORT")
+          .arg("mylist")
+          .arg("BY")
+          .arg("weight_*")
+          .arg("LIMIT")
+          .arg("0")
+          .arg("10")
+          .arg("GET")
+          .arg("#")
+          .arg("GET")
+          .arg("weight_*")
+          .arg("DESC")
+          .arg("ALPHA"),
+      ),
+    ];
+
+    for (options, expected) in tests {
+      assert_eq!(expected, options.apply(Command::new("SORT").arg("mylist")));
+    }
+  }
+
+  #[tokio::test]
+  async fn sort() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis.send("DEL sort:numbers sort:words").await?;
+    let mut command = Command::new("RPUSH").arg("sort:numbers");
+    for n in [3, 10, 1, 2] {
+      command = command.arg_int(n);
+    }
+    redis.send_command(command).await?;
+    redis.send("RPUSH sort:words banana apple cherry").await?;
+
+    assert_eq!(
+      vec![b"1".to_vec(), b"2".to_vec(), b"3".to_vec(), b"10".to_vec()],
+      redis.sort("sort:numbers", Sort::new()).await?
+    );
+
+    assert_eq!(
+      vec![b"3".to_vec(), b"2".to_vec()],
+      redis
+        .sort("sort:numbers", Sort::new().desc().limit(1, 2))
+        .await?
+    );
+
+    assert_eq!(
+      vec![b"apple".to_vec(), b"banana".to_vec(), b"cherry".to_vec()],
+      redis.sort("sort:words", Sort::new().alpha()).await?
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn in_memory_get_and_set() -> Result<()> {
+    let (mut redis, server) = fake_redis(&[
+      (&["SET", "foo", "bar"], b"+OK\r\n"),
+      (&["GET", "foo"], b"$3\r\nbar\r\n"),
+      (&["GET", "missing"], b"$-1\r\n"),
+    ]);
+
+    assert_eq!(
+      Reply::Ok(DataType::SimpleString(String::from("OK"))),
+      redis.send("SET foo bar").await?
+    );
+
+    for _ in 0..2 {
+      assert_reply_bulk!(
+        redis.send_command(Command::new("GET").arg("foo")).await?,
+        "bar"
+      );
+    }
+
+    assert_eq!(
+      Reply::Ok(DataType::Null),
+      redis
+        .send_command(Command::new("GET").arg("missing"))
+        .await?
+    );
+
+    assert_eq!(
+      Reply::Error(String::from("ERR no reply scripted for the command")),
+      redis.send_command(Command::new("DEL").arg("foo")).await?
+    );
+
+    drop(redis);
+
+    assert_eq!(
+      vec![
+        command_args(&["SET", "foo", "bar"]),
+        command_args(&["GET", "foo"]),
+        command_args(&["GET", "foo"]),
+        command_args(&["GET", "missing"]),
+        command_args(&["DEL", "foo"]),
+      ],
+      server.await.unwrap()
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn blocking_commands_timeout_units() -> Result<()> {
+    let (mut redis, server) = scripted_server(&[b"*-1\r\n"]);
+
+    assert_eq!(
+      None,
+      redis
+        .blpop(&["list1", "list2"], Duration::from_millis(500))
+        .await?
+    );
+    assert_eq!(
+      vec![command_args(&["BLPOP", "list1", "list2", "0.5"])],
+      server.await.into_diagnostic()?
+    );
+
+    let (mut redis, server) = scripted_server(&[b":0\r\n"]);
+
+    assert_eq!(0, redis.wait(1, Duration::from_millis(500)).await?);
+    assert_eq!(
+      vec![command_args(&["WAIT", "1", "500"])],
+      server.await.into_diagnostic()?
+    );
+
+    let (mut redis, server) = scripted_server(&[b"*2\r\n:1\r\n:0\r\n"]);
+
+    assert_eq!(
+      (1, 0),
+      redis.waitaof(1, 0, Duration::from_millis(500)).await?
+    );
+    assert_eq!(
+      vec![command_args(&["WAITAOF", "1", "0", "500"])],
+      server.await.into_diagnostic()?
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn blpop() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis.send("DEL blpop:list1 blpop:list2").await?;
+    redis.send("RPUSH blpop:list2 a b").await?;
+
+    assert_eq!(
+      Some((b"blpop:list2".to_vec(), b"a".to_vec())),
+      redis
+        .blpop(&["blpop:list1", "blpop:list2"], Duration::from_millis(100))
+        .await?
+    );
+
+    assert_eq!(
+      None,
+      redis
+        .blpop(&["blpop:list1"], Duration::from_millis(100))
+        .await?
+    );
+
+    Ok(())
+  }
+
+  #[test]
+  fn getex_expiry() {
+    let tests = vec![
+      (GetExExpiry::Keep, vec!["GETEX", "mykey"]),
+      (GetExExpiry::Persist, vec!["GETEX", "mykey", "PERSIST"]),
+      (GetExExpiry::Ex(10), vec!["GETEX", "mykey", "EX", "10"]),
+      (GetExExpiry::Px(1500), vec!["GETEX", "mykey", "PX", "1500"]),
+      (
+        GetExExpiry::ExAt(1893456000),
+        vec!["GETEX", "mykey", "EXAT", "1893456000"],
+      ),
+      (
+        GetExExpiry::PxAt(1893456000000),
+        vec!["GETEX", "mykey", "PXAT", "1893456000000"],
+      ),
+    ];
+
+    for (expiry, expected) in tests {
+      assert_eq!(
+        command_args(&expected),
+        resp::parse(expiry.apply(Command::new("GETEX").arg("mykey")).encode()).unwrap()
+      );
+    }
+  }
+
+  #[tokio::test]
+  async fn getex() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis.send("DEL getex:mykey").await?;
+
+    assert_eq!(None, redis.getex("getex:mykey", GetExExpiry::Keep).await?);
+
+    redis.send("SET getex:mykey Hello").await?;
+
+    assert_eq!(
+      Some(b"Hello".to_vec()),
+      redis.getex("getex:mykey", GetExExpiry::Ex(10)).await?
+    );
+    assert_reply_int!(redis.send("TTL getex:mykey").await?, 10);
+
+    assert_eq!(
+      Some(b"Hello".to_vec()),
+      redis.getex("getex:mykey", GetExExpiry::Persist).await?
+    );
+    assert_reply_int!(redis.send("TTL getex:mykey").await?, -1);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn protocol_detection() -> Result<()> {
+    let (mut redis, server) = scripted_server(&[b"+OK\r\n"]);
+
+    redis.auto_detect_protocol();
+
+    assert_eq!(
+      Reply::Ok(DataType::SimpleString(String::from("OK"))),
+      redis.send_command(Command::new("PING")).await?
+    );
+    // A Simple String could have been sent by either protocol.
+    assert_eq!(Protocol::Resp2, redis.protocol());
+
+    server.await.unwrap();
+
+    let (mut redis, server) = scripted_server(&[b"%1\r\n+proto\r\n:3\r\n"]);
+
+    redis.auto_detect_protocol();
+
+    assert_eq!(
+      Reply::Ok(DataType::Map(vec![(
+        DataType::SimpleString(String::from("proto")),
+        DataType::Int(3)
+      )])),
+      redis.send_command(Command::new("HELLO")).await?
+    );
+    assert_eq!(Protocol::Resp3, redis.protocol());
+
+    server.await.unwrap();
+
+    let (mut redis, server) = scripted_server(&[b"%1\r\n+proto\r\n:3\r\n"]);
+
+    redis.set_protocol(Protocol::Resp2);
+
+    let err = redis.send_command(Command::new("HELLO")).await.unwrap_err();
+
+    assert!(matches!(
+      err.downcast_ref::<resp::ParserError>(),
+      Some(resp::ParserError::ProtocolMismatch { type_name, .. }) if type_name == "map"
+    ));
+
+    server.await.unwrap();
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_lcs_result() {
+    let tests = vec![
+      (
+        "$6\r\nmytext\r\n",
+        Ok(LcsResult::String(b"mytext".to_vec())),
+      ),
+      (":6\r\n", Ok(LcsResult::Len(6))),
+      (
+        "*4\r\n$7\r\nmatches\r\n*2\r\n\
+        *2\r\n*2\r\n:4\r\n:7\r\n*2\r\n:5\r\n:8\r\n\
+        *2\r\n*2\r\n:2\r\n:3\r\n*2\r\n:0\r\n:1\r\n\
+        $3\r\nlen\r\n:6\r\n",
+        Ok(LcsResult::Matches {
+          matches: vec![
+            LcsMatch {
+              key1: (4, 7),
+              key2: (5, 8),
+              len: None,
+            },
+            LcsMatch {
+              key1: (2, 3),
+              key2: (0, 1),
+              len: None,
+            },
+          ],
+          len: 6,
+        }),
+      ),
+      (
+        "%2\r\n+matches\r\n*1\r\n*3\r\n*2\r\n:4\r\n:7\r\n*2\r\n:5\r\n:8\r\n:4\r\n+len\r\n:6\r\n",
+        Ok(LcsResult::Matches {
+          matches: vec![LcsMatch {
+            key1: (4, 7),
+            key2: (5, 8),
+            len: Some(4),
+          }],
+          len: 6,
+        }),
+      ),
+      (
+        "*2\r\n$7\r\nmatches\r\n*1\r\n*1\r\n:4\r\n",
+        Err(RedisError::UnexpectedReply(DataType::Array(vec![
+          DataType::Int(4),
+        ]))),
+      ),
+    ];
+
+    for (input, expected) in tests {
+      let reply = resp::parse(input.as_bytes().to_vec()).unwrap();
+      assert_eq!(expected, lcs_result(reply));
+    }
+  }
+
+  #[tokio::test]
+  async fn lcs() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis
+      .send("MSET lcs:key1 ohmytext lcs:key2 mynewtext")
+      .await?;
+
+    assert_eq!(
+      LcsResult::String(b"mytext".to_vec()),
+      redis.lcs("lcs:key1", "lcs:key2", LcsOptions::new()).await?
+    );
+
+    assert_eq!(
+      LcsResult::Len(6),
+      redis
+        .lcs("lcs:key1", "lcs:key2", LcsOptions::new().len())
+        .await?
+    );
+
+    assert_eq!(
+      LcsResult::Matches {
+        matches: vec![
+          LcsMatch {
+            key1: (4, 7),
+            key2: (5, 8),
+            len: Some(4),
+          },
+          LcsMatch {
+            key1: (2, 3),
+            key2: (0, 1),
+            len: Some(2),
+          },
+        ],
+        len: 6,
+      },
+      redis
+        .lcs(
+          "lcs:key1",
+          "lcs:key2",
+          LcsOptions::new().idx().with_match_len()
+        )
+        .await?
+    );
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_expire_time() {
+    let tests = vec![
+      (
+        DataType::Int(-2),
+        Duration::from_secs as fn(u64) -> Duration,
+        Ok(ExpireTime::NoKey),
+      ),
+      (
+        DataType::Int(-1),
+        Duration::from_secs,
+        Ok(ExpireTime::NoExpiry),
+      ),
+      (
+        DataType::Int(33177117420),
+        Duration::from_secs,
+        Ok(ExpireTime::At(
+          UNIX_EPOCH + Duration::from_secs(33177117420),
+        )),
+      ),
+      (
+        DataType::Int(33177117420000),
+        Duration::from_millis,
+        Ok(ExpireTime::At(
+          UNIX_EPOCH + Duration::from_secs(33177117420),
+        )),
+      ),
+      (
+        DataType::Int(-3),
+        Duration::from_secs,
+        Err(RedisError::UnexpectedReply(DataType::Int(-3))),
+      ),
+    ];
+
+    for (reply, unit, expected) in tests {
+      assert_eq!(expected, expire_time(reply, unit));
+    }
+  }
+
+  #[tokio::test]
+  async fn expiretime() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis.send("DEL expiretime:mykey").await?;
+
+    assert_eq!(
+      ExpireTime::NoKey,
+      redis.expiretime("expiretime:mykey").await?
+    );
+    assert_eq!(
+      ExpireTime::NoKey,
+      redis.pexpiretime("expiretime:mykey").await?
+    );
+
+    redis.send("SET expiretime:mykey Hello").await?;
+
+    assert_eq!(
+      ExpireTime::NoExpiry,
+      redis.expiretime("expiretime:mykey").await?
+    );
+    assert_eq!(
+      ExpireTime::NoExpiry,
+      redis.pexpiretime("expiretime:mykey").await?
+    );
+
+    redis
+      .send_command(
+        Command::new("EXPIREAT")
+          .arg("expiretime:mykey")
+          .arg_int(33177117420i64),
+      )
+      .await?;
+
+    let expected = ExpireTime::At(UNIX_EPOCH + Duration::from_secs(33177117420));
+
+    assert_eq!(expected, redis.expiretime("expiretime:mykey").await?);
+    assert_eq!(expected, redis.pexpiretime("expiretime:mykey").await?);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn encoding_after() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis.send("DEL encoding_after:mylist").await?;
+
+    assert_eq!(None, redis.object_encoding("encoding_after:mylist").await?);
+
+    let max_listpack_size = match redis
+      .send_command(
+        Command::new("CONFIG")
+          .arg("GET")
+          .arg("list-max-listpack-size"),
+      )
+      .await?
+      .into_data()?
+    {
+      DataType::Array(mut elements) => elements.pop(),
+      _ => None,
+    };
+
+    redis
+      .send_command(
+        Command::new("CONFIG")
+          .arg("SET")
+          .arg("list-max-listpack-size")
+          .arg_int(4),
+      )
+      .await?
+      .into_data()?;
+
+    let result = async {
+      assert_eq!(
+        "listpack",
+        redis
+          .encoding_after("encoding_after:mylist", async |redis| {
+            redis.send("RPUSH encoding_after:mylist a b c d").await?;
+            Ok(())
+          })
+          .await?
+      );
+
+      assert_eq!(
+        "quicklist",
+        redis
+          .encoding_after("encoding_after:mylist", async |redis| {
+            redis.send("RPUSH encoding_after:mylist e").await?;
+            Ok(())
+          })
+          .await?
+      );
+
+      Ok::<_, miette::Report>(())
+    }
+    .await;
+
+    if let Some(DataType::BulkString(size)) = max_listpack_size {
+      redis
+        .send_command(
+          Command::new("CONFIG")
+            .arg("SET")
+            .arg("list-max-listpack-size")
+            .arg(size),
+        )
+        .await?;
+    }
+
+    result
+  }
+
+  #[tokio::test]
+  async fn command_getkeys() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    assert_eq!(
+      vec![b"a".to_vec(), b"b".to_vec()],
+      redis
+        .command_getkeys(&[b"MSET", b"a", b"1", b"b", b"2"])
+        .await?
+    );
+
+    assert_eq!(
+      vec![b"key1".to_vec(), b"key2".to_vec()],
+      redis
+        .command_getkeys(&[b"SINTERCARD", b"2", b"key1", b"key2", b"LIMIT", b"1"])
+        .await?
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn on_push() -> Result<()> {
+    let (mut redis, server) =
+      scripted_server(&[b">2\r\n+invalidate\r\n*1\r\n$3\r\nfoo\r\n+PONG\r\n"]);
+
+    let pushes = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+
+    let handler_pushes = std::sync::Arc::clone(&pushes);
+    redis.on_push(move |push| handler_pushes.lock().unwrap().push(push));
+
+    assert_eq!(
+      Reply::Ok(DataType::SimpleString(String::from("PONG"))),
+      redis.send_command(Command::new("PING")).await?
+    );
+
+    assert_eq!(
+      vec![vec![
+        DataType::SimpleString(String::from("invalidate")),
+        DataType::Array(vec![DataType::BulkString(b"foo".to_vec())]),
+      ]],
+      *pushes.lock().unwrap()
+    );
+
+    assert_eq!(
+      vec![command_args(&["PING"])],
+      server.await.into_diagnostic()?
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn del_matching() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis.del_matching("del_matching:*", 100).await?;
+
+    for i in 0..250 {
+      redis
+        .set(
+          &format!("del_matching:user:{}", i),
+          i.to_string().as_bytes(),
+        )
+        .await?;
+    }
+
+    for i in 0..50 {
+      redis
+        .set(
+          &format!("del_matching:session:{}", i),
+          i.to_string().as_bytes(),
+        )
+        .await?;
+    }
+
+    assert_eq!(250, redis.del_matching("del_matching:user:*", 20).await?);
+
+    assert_eq!(
+      Reply::Ok(DataType::Int(0)),
+      redis
+        .send("EXISTS del_matching:user:0 del_matching:user:249")
+        .await?
+    );
+    assert_eq!(
+      Reply::Ok(DataType::Int(2)),
+      redis
+        .send("EXISTS del_matching:session:0 del_matching:session:49")
+        .await?
+    );
+
+    assert_eq!(0, redis.del_matching("del_matching:user:*", 20).await?);
+    assert_eq!(50, redis.del_matching("del_matching:*", 20).await?);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn waitaof() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis.send("SET waitaof:mykey Hello").await?;
+
+    // The test server has the append only file enabled and no replicas.
+    assert_eq!((1, 0), redis.waitaof(1, 0, Duration::from_secs(5)).await?);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn debug() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    assert_eq!(
+      Reply::Ok(DataType::SimpleString(String::from("OK"))),
+      redis.debug(&["SET-ACTIVE-EXPIRE", "0"]).await?
+    );
+    assert_eq!(
+      Reply::Ok(DataType::SimpleString(String::from("OK"))),
+      redis.debug(&["SET-ACTIVE-EXPIRE", "1"]).await?
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn connect_any() -> Result<()> {
+    let live = tokio::net::TcpListener::bind("127.0.0.1:0")
+      .await
+      .into_diagnostic()?;
+    let live_addr = live.local_addr().into_diagnostic()?.to_string();
+
+    // Nothing listens on the port of a listener that has been dropped.
+    let dead_addr = tokio::net::TcpListener::bind("127.0.0.1:0")
+      .await
+      .into_diagnostic()?
+      .local_addr()
+      .into_diagnostic()?
+      .to_string();
+
+    let redis = Redis::connect_any(&[&dead_addr, &live_addr]).await?;
+
+    assert_eq!(Some(live_addr.as_str()), redis.addr());
+
+    drop(live);
+
+    let err = Redis::connect_any(&[&dead_addr, &live_addr])
+      .await
+      .unwrap_err();
+
+    match err.downcast_ref::<RedisError>() {
+      Some(RedisError::NoAddressConnected(failures)) => {
+        assert_eq!(2, failures.len());
+        assert!(failures[0].starts_with(&dead_addr));
+        assert!(failures[1].starts_with(&live_addr));
+      }
+      _ => panic!("unexpected error: {:?}", err),
+    }
+
+    Ok(())
+  }
+
+  #[test]
+  fn is_queued() {
+    let tests = vec![
+      ("+QUEUED\r\n", true),
+      ("+OK\r\n", false),
+      ("$6\r\nQUEUED\r\n", false),
+      ("-ERR unknown command 'foo'\r\n", false),
+    ];
+
+    for (input, expected) in tests {
+      let reply = match resp::parse(input.as_bytes().to_vec()).unwrap() {
+        DataType::Error(message) => Reply::Error(message),
+        data_type => Reply::Ok(data_type),
+      };
+
+      assert_eq!(expected, reply.is_queued(), "{:?}", input);
+    }
+  }
+
+  #[tokio::test]
+  async fn transaction_is_discarded_when_a_command_is_not_queued() -> Result<()> {
+    let (mut redis, server) = scripted_server(&[
+      b"+OK\r\n",
+      b"+QUEUED\r\n",
+      b"-ERR unknown command 'FOO'\r\n",
+      b"+OK\r\n",
+    ]);
+
+    let err = redis
+      .transaction(vec![
+        Command::new("INCR").arg("counter"),
+        Command::new("FOO"),
+        Command::new("INCR").arg("counter"),
+      ])
+      .await
+      .unwrap_err();
+
+    assert_eq!(
+      Some(&RedisError::Server(String::from(
+        "ERR unknown command 'FOO'"
+      ))),
+      err.downcast_ref::<RedisError>()
+    );
+
+    assert_eq!(
+      vec![
+        command_args(&["MULTI"]),
+        command_args(&["INCR", "counter"]),
+        command_args(&["FOO"]),
+        command_args(&["DISCARD"]),
+      ],
+      server.await.into_diagnostic()?
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn transaction() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis.send("DEL transaction:counter").await?;
+
+    assert_eq!(
+      vec![
+        Reply::Ok(DataType::Int(1)),
+        Reply::Ok(DataType::Int(2)),
+        Reply::Error(String::from(
+          "WRONGTYPE Operation against a key holding the wrong kind of value"
+        )),
+      ],
+      redis
+        .transaction(vec![
+          Command::new("INCR").arg("transaction:counter"),
+          Command::new("INCR").arg("transaction:counter"),
+          Command::new("LPUSH").arg("transaction:counter").arg("a"),
+        ])
+        .await?
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn pubsub_resp2() -> Result<()> {
+    let (mut redis, server) = scripted_server(&[
+      b"*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n\
+        *3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$5\r\nhello\r\n",
+      b"*2\r\n$4\r\npong\r\n$0\r\n\r\n",
+      b"*3\r\n$11\r\nunsubscribe\r\n$4\r\nnews\r\n:0\r\n",
+      b"$5\r\nvalue\r\n",
+    ]);
+
+    redis.subscribe(&["news"]).await?;
+
+    assert_eq!(Protocol::Resp2, redis.protocol());
+
+    // Only a few commands are allowed, the others fail without being sent.
+    let err = redis
+      .send_command(Command::new("GET").arg("mykey"))
+      .await
+      .unwrap_err();
+    assert_eq!(
+      Some(&RedisError::NotAllowedWhileSubscribed(String::from("GET"))),
+      err.downcast_ref::<RedisError>()
+    );
+
+    assert_eq!(
+      Reply::Ok(DataType::Array(vec![
+        DataType::BulkString(b"pong".to_vec()),
+        DataType::BulkString(vec![]),
+      ])),
+      redis.send_command(Command::new("PING")).await?
+    );
+
+    assert_eq!(
+      Message {
+        channel: b"news".to_vec(),
+        payload: b"hello".to_vec(),
+      },
+      redis.next_message().await?
+    );
+
+    redis.unsubscribe(&["news"]).await?;
+
+    let err = redis.next_message().await.unwrap_err();
+    assert_eq!(
+      Some(&RedisError::NotSubscribed),
+      err.downcast_ref::<RedisError>()
+    );
+
+    assert_reply_bulk!(
+      redis.send_command(Command::new("GET").arg("mykey")).await?,
+      "value"
+    );
+
+    assert_eq!(
+      vec![
+        command_args(&["SUBSCRIBE", "news"]),
+        command_args(&["PING"]),
+        command_args(&["UNSUBSCRIBE", "news"]),
+        command_args(&["GET", "mykey"]),
+      ],
+      server.await.into_diagnostic()?
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn pubsub_resp3() -> Result<()> {
+    let (mut redis, server) = scripted_server(&[
+      b">3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n>3\r\n$9\r\nsubscribe\r\n$6\r\nsports\r\n:2\r\n",
+      b">3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$5\r\nhello\r\n$5\r\nvalue\r\n",
+      b">3\r\n$7\r\nmessage\r\n$6\r\nsports\r\n$4\r\ngoal\r\n+PONG\r\n",
+    ]);
+
+    redis.subscribe(&["news", "sports"]).await?;
+
+    assert_eq!(Protocol::Resp3, redis.protocol());
+
+    // The message that arrives before the reply is kept for later.
+    assert_reply_bulk!(
+      redis.send_command(Command::new("GET").arg("mykey")).await?,
+      "value"
+    );
+
+    assert_eq!(
+      Message {
+        channel: b"news".to_vec(),
+        payload: b"hello".to_vec(),
+      },
+      redis.next_message().await?
+    );
+
+    assert_eq!(
+      Reply::Ok(DataType::SimpleString(String::from("PONG"))),
+      redis.send_command(Command::new("PING")).await?
+    );
+
+    assert_eq!(
+      Message {
+        channel: b"sports".to_vec(),
+        payload: b"goal".to_vec(),
+      },
+      redis.next_message().await?
+    );
+
+    assert_eq!(
+      vec![
+        command_args(&["SUBSCRIBE", "news", "sports"]),
+        command_args(&["GET", "mykey"]),
+        command_args(&["PING"]),
+      ],
+      server.await.into_diagnostic()?
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn resp3_array_reply_that_looks_like_a_message() -> Result<()> {
+    let (mut redis, server) = scripted_server(&[
+      b">3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n",
+      b"*3\r\n$7\r\nmessage\r\n$1\r\na\r\n$1\r\nb\r\n",
+    ]);
+
+    redis.subscribe(&["news"]).await?;
+
+    // Messages are only sent as Pushes in RESP3, so an Array is the reply to the command.
+    assert_eq!(
+      Reply::Ok(DataType::Array(vec![
+        DataType::BulkString(b"message".to_vec()),
+        DataType::BulkString(b"a".to_vec()),
+        DataType::BulkString(b"b".to_vec()),
+      ])),
+      redis.send("LRANGE mylist 0 -1").await?
+    );
+
+    assert_eq!(
+      vec![
+        command_args(&["SUBSCRIBE", "news"]),
+        command_args(&["LRANGE", "mylist", "0", "-1"]),
+      ],
+      server.await.into_diagnostic()?
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn pubsub() -> Result<()> {
+    let mut subscriber = Redis::connect(TEST_REDIS_IP).await?;
+    let mut publisher = Redis::connect(TEST_REDIS_IP).await?;
+
+    subscriber.subscribe(&["pubsub:news"]).await?;
+
+    publisher.send("PUBLISH pubsub:news hello").await?;
+
+    assert_eq!(
+      Message {
+        channel: b"pubsub:news".to_vec(),
+        payload: b"hello".to_vec(),
+      },
+      subscriber.next_message().await?
+    );
+
+    subscriber.unsubscribe(&["pubsub:news"]).await?;
+
+    assert_eq!(
+      Reply::Ok(DataType::SimpleString(String::from("PONG"))),
+      subscriber.send_command(Command::new("PING")).await?
+    );
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_slot_range() {
+    let tests = vec![
+      (
+        "*4\r\n:0\r\n:5460\r\n\
+        *4\r\n$9\r\n127.0.0.1\r\n:30001\r\n$4\r\nabcd\r\n*0\r\n\
+        *4\r\n$9\r\n127.0.0.1\r\n:30004\r\n$4\r\nefgh\r\n*0\r\n",
+        Ok(SlotRange {
+          start: 0,
+          end: 5460,
+          master: NodeAddr {
+            host: String::from("127.0.0.1"),
+            port: 30001,
+            id: Some(String::from("abcd")),
+          },
+          replicas: vec![NodeAddr {
+            host: String::from("127.0.0.1"),
+            port: 30004,
+            id: Some(String::from("efgh")),
+          }],
+        }),
+      ),
+      (
+        "*3\r\n:5461\r\n:10922\r\n*2\r\n$9\r\n127.0.0.1\r\n:30002\r\n",
+        Ok(SlotRange {
+          start: 5461,
+          end: 10922,
+          master: NodeAddr {
+            host: String::from("127.0.0.1"),
+            port: 30002,
+            id: None,
+          },
+          replicas: vec![],
+        }),
+      ),
+      (
+        "*3\r\n:0\r\n:16384\r\n*2\r\n$9\r\n127.0.0.1\r\n:30002\r\n",
+        Err(RedisError::UnexpectedReply(DataType::Int(16384))),
+      ),
+      (
+        "*2\r\n:0\r\n:5460\r\n",
+        Err(RedisError::UnexpectedReply(DataType::Array(vec![
+          DataType::Int(0),
+          DataType::Int(5460),
+        ]))),
+      ),
+      (
+        "*3\r\n:0\r\n:5460\r\n*1\r\n$9\r\n127.0.0.1\r\n",
+        Err(RedisError::UnexpectedReply(DataType::Array(vec![
+          DataType::BulkString(b"127.0.0.1".to_vec()),
+        ]))),
+      ),
+    ];
+
+    for (input, expected) in tests {
+      let reply = resp::parse(input.as_bytes().to_vec()).unwrap();
+      assert_eq!(expected, slot_range(reply));
+    }
+  }
+
+  #[tokio::test]
+  async fn cluster_slots() -> Result<()> {
+    let (mut redis, server) = scripted_server(&[b"*2\r\n\
+      *3\r\n:0\r\n:8191\r\n*3\r\n$8\r\n10.0.0.1\r\n:6379\r\n$2\r\nm1\r\n\
+      *4\r\n:8192\r\n:16383\r\n*3\r\n$8\r\n10.0.0.2\r\n:6379\r\n$2\r\nm2\r\n\
+      *3\r\n$8\r\n10.0.0.3\r\n:6380\r\n$2\r\nr1\r\n"]);
+
+    let node = |host: &str, port, id: &str| NodeAddr {
+      host: host.to_owned(),
+      port,
+      id: Some(id.to_owned()),
+    };
+
+    assert_eq!(
+      vec![
+        SlotRange {
+          start: 0,
+          end: 8191,
+          master: node("10.0.0.1", 6379, "m1"),
+          replicas: vec![],
+        },
+        SlotRange {
+          start: 8192,
+          end: 16383,
+          master: node("10.0.0.2", 6379, "m2"),
+          replicas: vec![node("10.0.0.3", 6380, "r1")],
+        },
+      ],
+      redis.cluster_slots().await?
+    );
+
+    assert_eq!(
+      vec![command_args(&["CLUSTER", "SLOTS"])],
+      server.await.unwrap()
+    );
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_command_table() {
+    let reply = resp::parse(
+      b"*2\r\n\
+      *6\r\n$3\r\nget\r\n:2\r\n*2\r\n+readonly\r\n+fast\r\n:1\r\n:1\r\n:1\r\n\
+      *6\r\n$4\r\nmget\r\n:-2\r\n~2\r\n+readonly\r\n+fast\r\n:1\r\n:-1\r\n:1\r\n"
+        .to_vec(),
+    )
+    .unwrap();
+
+    let table = command_table(reply).unwrap();
+
+    assert_eq!(2, table.len());
+    assert_eq!(
+      Some(&CommandInfo {
+        name: String::from("get"),
+        arity: 2,
+        flags: vec![String::from("readonly"), String::from("fast")],
+      }),
+      table.get("GET")
+    );
+    assert_eq!(-2, table.get("mget").unwrap().arity);
+    assert_eq!(None, table.get("set"));
+
+    let tests = vec![
+      (
+        Command::new("GET"),
+        Err(RedisError::WrongArity(String::from("get"))),
+      ),
+      (Command::new("GET").arg("key"), Ok(())),
+      (
+        Command::new("GET").arg("key").arg("other"),
+        Err(RedisError::WrongArity(String::from("get"))),
+      ),
+      (
+        Command::new("MGET"),
+        Err(RedisError::WrongArity(String::from("mget"))),
+      ),
+      (Command::new("MGET").arg("key"), Ok(())),
+      (Command::new("MGET").arg("key").arg("other"), Ok(())),
+      (Command::new("SET"), Ok(())),
+    ];
+
+    for (command, expected) in tests {
+      assert_eq!(expected, table.check_arity(&command));
+    }
+
+    assert_eq!(
+      Err(RedisError::UnexpectedReply(DataType::Array(vec![
+        DataType::BulkString(b"get".to_vec()),
+        DataType::Int(2),
+      ]))),
+      command_table(DataType::Array(vec![DataType::Array(vec![
+        DataType::BulkString(b"get".to_vec()),
+        DataType::Int(2),
+      ])]))
+    );
+  }
+
+  #[tokio::test]
+  async fn commands_with_wrong_arity_are_not_sent() -> Result<()> {
+    let (mut redis, server) = scripted_server(&[
+      b"*1\r\n*3\r\n$3\r\nget\r\n:2\r\n*2\r\n+readonly\r\n+fast\r\n",
+      b"$3\r\nbar\r\n",
+    ]);
+
+    assert_eq!(None, redis.command_table());
+
+    redis.refresh_command_table().await?;
+
+    let err = redis.send_command(Command::new("GET")).await.unwrap_err();
+    assert_eq!(
+      Some(&RedisError::WrongArity(String::from("get"))),
+      err.downcast_ref::<RedisError>()
+    );
+
+    assert_reply_bulk!(
+      redis.send_command(Command::new("GET").arg("foo")).await?,
+      "bar"
+    );
+
+    assert_eq!(
+      vec![command_args(&["COMMAND"]), command_args(&["GET", "foo"])],
+      server.await.unwrap()
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn refresh_command_table() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    let table = redis.refresh_command_table().await?;
+
+    let get = table.get("GET").unwrap();
+    assert_eq!(2, get.arity);
+    assert!(get.flags.contains(&String::from("readonly")));
+
+    assert!(table.check_arity(&Command::new("GET").arg("key")).is_ok());
+    assert_eq!(
+      Err(RedisError::WrongArity(String::from("get"))),
+      table.check_arity(&Command::new("GET"))
+    );
+
+    Ok(())
+  }
+
+  #[test]
+  fn to_cli_string() {
+    let tests = vec![
+      (Reply::Ok(DataType::SimpleString(String::from("OK"))), "OK"),
+      (
+        Reply::Error(String::from("ERR unknown command 'foo'")),
+        "(error) ERR unknown command 'foo'",
+      ),
+      (Reply::Ok(DataType::Int(-3)), "(integer) -3"),
+      (Reply::Ok(DataType::Double(1.5)), "(double) 1.5"),
+      (Reply::Ok(DataType::Null), "(nil)"),
+      (Reply::Ok(DataType::Boolean(true)), "(true)"),
+      (
+        Reply::Ok(DataType::BigNumber(String::from(
+          "1234567999999999999999999",
+        ))),
+        "(big number) 1234567999999999999999999",
+      ),
+      (
+        Reply::Ok(DataType::BulkString(b"foo bar".to_vec())),
+        "\"foo bar\"",
+      ),
+      (
+        Reply::Ok(DataType::BulkString(b"a\"b\\c\r\n\x00\xff".to_vec())),
+        "\"a\\\"b\\\\c\\r\\n\\x00\\xff\"",
+      ),
+      (Reply::Ok(DataType::Array(vec![])), "(empty array)"),
+      (Reply::Ok(DataType::Map(vec![])), "(empty hash)"),
+      (
+        Reply::Ok(DataType::Array(vec![
+          DataType::BulkString(b"foo".to_vec()),
+          DataType::Array(vec![
+            DataType::Int(1),
+            DataType::Null,
+            DataType::Array(vec![DataType::BulkString(b"bar".to_vec())]),
+          ]),
+          DataType::Array(vec![]),
+          DataType::Error(String::from("WRONGTYPE Operation against a key")),
+        ])),
+        "1) \"foo\"\n\
+         2) 1) (integer) 1\n   \
+            2) (nil)\n   \
+            3) 1) \"bar\"\n\
+         3) (empty array)\n\
+         4) (error) WRONGTYPE Operation against a key",
+      ),
+      (
+        Reply::Ok(DataType::Array(
+          (1..=10)
+            .map(|i| DataType::Array(vec![DataType::Int(i), DataType::Int(-i)]))
+            .collect(),
+        )),
+        " 1) 1) (integer) 1\n    2) (integer) -1\n \
+          2) 1) (integer) 2\n    2) (integer) -2\n \
+          3) 1) (integer) 3\n    2) (integer) -3\n \
+          4) 1) (integer) 4\n    2) (integer) -4\n \
+          5) 1) (integer) 5\n    2) (integer) -5\n \
+          6) 1) (integer) 6\n    2) (integer) -6\n \
+          7) 1) (integer) 7\n    2) (integer) -7\n \
+          8) 1) (integer) 8\n    2) (integer) -8\n \
+          9) 1) (integer) 9\n    2) (integer) -9\n\
+         10) 1) (integer) 10\n    2) (integer) -10",
+      ),
+      (
+        Reply::Ok(DataType::Map(vec![
+          (
+            DataType::BulkString(b"server".to_vec()),
+            DataType::BulkString(b"redis".to_vec()),
+          ),
+          (
+            DataType::BulkString(b"modules".to_vec()),
+            DataType::Set(vec![DataType::SimpleString(String::from("json"))]),
+          ),
+        ])),
+        "1# \"server\" => \"redis\"\n\
+         2# \"modules\" => 1~ json",
+      ),
+    ];
+
+    for (reply, expected) in tests {
+      assert_eq!(expected, reply.to_cli_string());
+      assert_eq!(expected, reply.to_string());
+    }
+  }
+
+  #[test]
+  fn test_acl_user() {
+    let tests = vec![
+      // Redis 7, RESP2.
+      (
+        "*12\r\n\
+        $5\r\nflags\r\n*2\r\n$2\r\non\r\n$6\r\nnopass\r\n\
+        $9\r\npasswords\r\n*0\r\n\
+        $8\r\ncommands\r\n$18\r\n+@all -debug -keys\r\n\
+        $4\r\nkeys\r\n$17\r\n~cache:* %R~app:*\r\n\
+        $8\r\nchannels\r\n$2\r\n&*\r\n\
+        $9\r\nselectors\r\n*0\r\n",
+        Ok(AclUser {
+          flags: vec![String::from("on"), String::from("nopass")],
+          passwords: vec![],
+          commands: String::from("+@all -debug -keys"),
+          keys: vec![String::from("~cache:*"), String::from("%R~app:*")],
+          channels: vec![String::from("&*")],
+        }),
+      ),
+      // Redis 7, RESP3.
+      (
+        "%5\r\n\
+        +flags\r\n~1\r\n+off\r\n\
+        +passwords\r\n*1\r\n$4\r\n5e88\r\n\
+        +commands\r\n$5\r\n-@all\r\n\
+        +keys\r\n$0\r\n\r\n\
+        +channels\r\n$0\r\n\r\n",
+        Ok(AclUser {
+          flags: vec![String::from("off")],
+          passwords: vec![String::from("5e88")],
+          commands: String::from("-@all"),
+          keys: vec![],
+          channels: vec![],
+        }),
+      ),
+      // Redis 6, where keys and channels are lists.
+      (
+        "*10\r\n\
+        $5\r\nflags\r\n*1\r\n$2\r\non\r\n\
+        $9\r\npasswords\r\n*0\r\n\
+        $8\r\ncommands\r\n$4\r\n+get\r\n\
+        $4\r\nkeys\r\n*2\r\n$3\r\na:*\r\n$3\r\nb:*\r\n\
+        $8\r\nchannels\r\n*1\r\n$1\r\n*\r\n",
+        Ok(AclUser {
+          flags: vec![String::from("on")],
+          passwords: vec![],
+          commands: String::from("+get"),
+          keys: vec![String::from("a:*"), String::from("b:*")],
+          channels: vec![String::from("*")],
+        }),
+      ),
+      (
+        "*2\r\n$5\r\nflags\r\n:1\r\n",
+        Err(RedisError::UnexpectedReply(DataType::Int(1))),
+      ),
+      (
+        "*2\r\n$8\r\ncommands\r\n*1\r\n$4\r\n+get\r\n",
+        Err(RedisError::UnexpectedReply(DataType::Array(vec![
+          DataType::BulkString(b"+get".to_vec()),
+        ]))),
+      ),
+    ];
+
+    for (input, expected) in tests {
+      let reply = resp::parse(input.as_bytes().to_vec()).unwrap();
+      assert_eq!(expected, acl_user(reply));
+    }
+  }
+
+  #[tokio::test]
+  async fn acl() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    assert_eq!("default", redis.acl_whoami().await?);
+
+    redis
+      .send("ACL SETUSER acl:user on nopass +get ~acl:* &acl:*")
+      .await?;
+
+    let user = redis.acl_getuser("acl:user").await?.unwrap();
+    assert!(user.flags.contains(&String::from("on")));
+    assert!(user.flags.contains(&String::from("nopass")));
+    assert_eq!(Vec::<String>::new(), user.passwords);
+    assert!(user.commands.contains("+get"));
+    assert_eq!(vec![String::from("~acl:*")], user.keys);
+    assert_eq!(vec![String::from("&acl:*")], user.channels);
+
+    redis.send("ACL DELUSER acl:user").await?;
+
+    assert_eq!(None, redis.acl_getuser("acl:user").await?);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn parse_stats() -> Result<()> {
+    let (mut redis, _server) = scripted_server(&[
+      b"+PONG\r\n",
+      b"*2\r\n$3\r\nbar\r\n$-1\r\n",
+      b"-ERR unknown command\r\n",
+    ]);
+
+    redis.send("PING").await?;
+
+    assert_eq!(None, redis.parse_stats());
+
+    redis.enable_parse_stats();
+
+    redis.send("MGET foo baz").await?;
+    redis.send("FOO").await?;
+
+    let stats = redis.parse_stats().unwrap();
+    assert_eq!(0, stats.get(DataTypeKind::SimpleString));
+    assert_eq!(1, stats.get(DataTypeKind::Array));
+    assert_eq!(1, stats.get(DataTypeKind::BulkString));
+    assert_eq!(1, stats.get(DataTypeKind::Null));
+    assert_eq!(1, stats.get(DataTypeKind::Error));
+    assert_eq!(18 + 22, stats.bytes());
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn touch() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis
+      .send("DEL touch:key1 touch:key2 touch:missing")
+      .await?;
+    redis
+      .send_command(
+        Command::new("MSET")
+          .arg("touch:key1")
+          .arg_int(1)
+          .arg("touch:key2")
+          .arg_int(2),
+      )
+      .await?;
+
+    assert_eq!(
+      2,
+      redis
+        .touch(&["touch:key1", "touch:missing", "touch:key2"])
+        .await?
+    );
+    assert_eq!(0, redis.touch(&["touch:missing"]).await?);
+
+    // A key is counted every time it's given.
+    assert_eq!(2, redis.touch(&["touch:key1", "touch:key1"]).await?);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn reply_split_between_cr_and_lf() -> Result<()> {
+    let (client, mut server) = duplex(4096);
+
+    tokio::spawn(async move {
+      let mut buffer = [0; 4096];
+      let _ = server.read(&mut buffer).await.unwrap();
+
+      for chunk in [&b"*2\r\n$3\r\nfoo\r"[..], b"\n:1\r", b"\n"] {
+        server.write_all(chunk).await.unwrap();
+
+        // Gives the client time to read the chunk before the next one is written.
+        tokio::time::sleep(Duration::from_millis(10)).await;
+      }
+    });
+
+    let mut redis = Redis::from_stream(client);
+
+    assert_eq!(
+      Reply::Ok(DataType::Array(vec![
+        DataType::BulkString(b"foo".to_vec()),
+        DataType::Int(1),
+      ])),
+      redis.send("FOO").await?
+    );
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_typed_value() {
+    let tests = vec![
+      (
+        "string",
+        "$3\r\nbar\r\n",
+        Ok(Some(TypedValue::String(b"bar".to_vec()))),
+      ),
+      ("string", "$-1\r\n", Ok(None)),
+      (
+        "list",
+        "*2\r\n$1\r\na\r\n$1\r\nb\r\n",
+        Ok(Some(TypedValue::List(vec![b"a".to_vec(), b"b".to_vec()]))),
+      ),
+      ("list", "*0\r\n", Ok(None)),
+      (
+        "set",
+        "~1\r\n$1\r\na\r\n",
+        Ok(Some(TypedValue::Set(vec![b"a".to_vec()]))),
+      ),
+      (
+        "hash",
+        "*2\r\n$1\r\nf\r\n$1\r\nv\r\n",
+        Ok(Some(TypedValue::Hash(vec![(b"f".to_vec(), b"v".to_vec())]))),
+      ),
+      (
+        "hash",
+        "%1\r\n$1\r\nf\r\n$1\r\nv\r\n",
+        Ok(Some(TypedValue::Hash(vec![(b"f".to_vec(), b"v".to_vec())]))),
+      ),
+      (
+        "zset",
+        "*4\r\n$1\r\na\r\n$1\r\n1\r\n$1\r\nb\r\n$3\r\n2.5\r\n",
+        Ok(Some(TypedValue::SortedSet(vec![
+          (b"a".to_vec(), 1.0),
+          (b"b".to_vec(), 2.5),
+        ]))),
+      ),
+      (
+        "zset",
+        "*2\r\n*2\r\n$1\r\na\r\n,1\r\n*2\r\n$1\r\nb\r\n,2.5\r\n",
+        Ok(Some(TypedValue::SortedSet(vec![
+          (b"a".to_vec(), 1.0),
+          (b"b".to_vec(), 2.5),
+        ]))),
+      ),
+      (
+        "string",
+        ":1\r\n",
+        Err(RedisError::UnexpectedReply(DataType::Int(1))),
+      ),
+    ];
+
+    for (key_type, input, expected) in tests {
+      let reply = resp::parse(input.as_bytes().to_vec()).unwrap();
+      assert_eq!(expected, typed_value(key_type, reply));
+    }
+  }
+
+  #[tokio::test]
+  async fn type_cache() -> Result<()> {
+    let (mut redis, server) = scripted_server(&[
+      b"+string\r\n",
+      b"$3\r\nbar\r\n",
+      b"$3\r\nbar\r\n",
+      b"+OK\r\n",
+      b"+list\r\n",
+      b"*1\r\n$3\r\nbaz\r\n",
+      b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n",
+      b"+string\r\n",
+      b"$3\r\nqux\r\n",
+    ]);
+
+    redis.enable_type_cache(Duration::from_secs(60));
+
+    assert_eq!(
+      Some(TypedValue::String(b"bar".to_vec())),
+      redis.get_typed("foo").await?
+    );
+
+    // The type is cached, only GET is sent.
+    assert_eq!(
+      Some(TypedValue::String(b"bar".to_vec())),
+      redis.get_typed("foo").await?
+    );
+
+    // Writing to the key forgets its type.
+    redis.send("DEL foo").await?;
+
+    assert_eq!(
+      Some(TypedValue::List(vec![b"baz".to_vec()])),
+      redis.get_typed("foo").await?
+    );
+
+    // Another client replaced the list with a string.
+    assert!(redis.get_typed("foo").await.is_err());
+
+    assert_eq!(
+      Some(TypedValue::String(b"qux".to_vec())),
+      redis.get_typed("foo").await?
+    );
+
+    assert_eq!(
+      vec![
+        command_args(&["TYPE", "foo"]),
+        command_args(&["GET", "foo"]),
+        command_args(&["GET", "foo"]),
+        command_args(&["DEL", "foo"]),
+        command_args(&["TYPE", "foo"]),
+        command_args(&["LRANGE", "foo", "0", "-1"]),
+        command_args(&["LRANGE", "foo", "0", "-1"]),
+        command_args(&["TYPE", "foo"]),
+        command_args(&["GET", "foo"]),
+      ],
+      server.await.unwrap()
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn get_typed() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis
+      .send("DEL get_typed:string get_typed:list get_typed:zset get_typed:missing")
+      .await?;
+    redis.send("SET get_typed:string foo").await?;
+    redis.send("RPUSH get_typed:list a b").await?;
+    redis
+      .zadd_opts(
+        "get_typed:zset",
+        &[(2.0, b"b"), (1.0, b"a")],
+        ZAddOptions::new(),
+      )
+      .await?;
+
+    assert_eq!(
+      Some(TypedValue::String(b"foo".to_vec())),
+      redis.get_typed("get_typed:string").await?
+    );
+    assert_eq!(
+      Some(TypedValue::List(vec![b"a".to_vec(), b"b".to_vec()])),
+      redis.get_typed("get_typed:list").await?
+    );
+    assert_eq!(
+      Some(TypedValue::SortedSet(vec![
+        (b"a".to_vec(), 1.0),
+        (b"b".to_vec(), 2.0)
+      ])),
+      redis.get_typed("get_typed:zset").await?
+    );
+    assert_eq!(None, redis.get_typed("get_typed:missing").await?);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn xadd() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis.send("DEL xadd:stream").await?;
+
+    let id = redis
+      .xadd(
+        "xadd:stream",
+        XId::Explicit(String::from("1-1")),
+        &[
+          (&b"name"[..], &b"Sara"[..]),
+          (&b"surname"[..], &b"OConnor"[..]),
+        ],
+      )
+      .await?;
+    assert_eq!("1-1", id);
+
+    let id = redis
+      .xadd("xadd:stream", XId::Auto, &[(&b"field"[..], &b"value"[..])])
+      .await?;
+    assert!(!id.is_empty());
+    assert_ne!("1-1", id);
+
+    let info = redis.xinfo_stream("xadd:stream").await?;
+    assert_eq!(2, info.length);
+    assert_eq!(id, info.last_generated_id);
+    assert_eq!(
+      vec![
+        (b"name".to_vec(), b"Sara".to_vec()),
+        (b"surname".to_vec(), b"OConnor".to_vec()),
+      ],
+      info.first_entry.unwrap().fields
+    );
+
+    // Ids must be greater than the id of the last entry.
+    assert!(redis
+      .xadd(
+        "xadd:stream",
+        XId::Explicit(String::from("1-1")),
+        &[(&b"field"[..], &b"value"[..])]
+      )
+      .await
+      .is_err());
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn unsubscribe_all_resp2() -> Result<()> {
+    let (mut redis, server) = scripted_server(&[
+      b"*3\r\n$9\r\nsubscribe\r\n$1\r\na\r\n:1\r\n\
+        *3\r\n$9\r\nsubscribe\r\n$1\r\nb\r\n:2\r\n\
+        *3\r\n$9\r\nsubscribe\r\n$1\r\nc\r\n:3\r\n",
+      b"*3\r\n$11\r\nunsubscribe\r\n$1\r\nb\r\n:2\r\n\
+        *3\r\n$7\r\nmessage\r\n$1\r\na\r\n$5\r\nhello\r\n\
+        *3\r\n$11\r\nunsubscribe\r\n$1\r\na\r\n:1\r\n\
+        *3\r\n$11\r\nunsubscribe\r\n$1\r\nc\r\n:0\r\n",
+      b"*3\r\n$11\r\nunsubscribe\r\n$-1\r\n:0\r\n",
+      b"+PONG\r\n",
+    ]);
+
+    redis.subscribe(&["a", "b", "c"]).await?;
+    assert_eq!(3, redis.subscriptions());
+
+    redis.unsubscribe_all().await?;
+    assert_eq!(0, redis.subscriptions());
+
+    // Messages received before the confirmations can still be read.
+    assert_eq!(
+      Message {
+        channel: b"a".to_vec(),
+        payload: b"hello".to_vec(),
+      },
+      redis.next_message().await?
+    );
+
+    // Not being subscribed to any channel gets a single confirmation.
+    redis.unsubscribe_all().await?;
+    assert_eq!(0, redis.subscriptions());
+
+    assert_eq!(
+      Reply::Ok(DataType::SimpleString(String::from("PONG"))),
+      redis.send_command(Command::new("PING")).await?
+    );
+
+    assert_eq!(
+      vec![
+        command_args(&["SUBSCRIBE", "a", "b", "c"]),
+        command_args(&["UNSUBSCRIBE"]),
+        command_args(&["UNSUBSCRIBE"]),
+        command_args(&["PING"]),
+      ],
+      server.await.into_diagnostic()?
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn unsubscribe_all() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis
+      .subscribe(&[
+        "unsubscribe_all:a",
+        "unsubscribe_all:b",
+        "unsubscribe_all:c",
+      ])
+      .await?;
+    assert_eq!(3, redis.subscriptions());
+
+    redis.unsubscribe_all().await?;
+    assert_eq!(0, redis.subscriptions());
+
+    assert_eq!(
+      Reply::Ok(DataType::SimpleString(String::from("PONG"))),
+      redis.send_command(Command::new("PING")).await?
+    );
+
+    Ok(())
+  }
+
+  /// Collects the output of a tracing subscriber.
+  #[derive(Clone, Default)]
+  struct SharedBuffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+  impl std::io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+      self.0.lock().unwrap().extend_from_slice(buf);
+      Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+      Ok(())
+    }
+  }
+
+  #[tokio::test]
+  async fn send_traced() -> Result<()> {
+    let output = SharedBuffer::default();
+
+    let writer = output.clone();
+    let subscriber = tracing_subscriber::fmt()
+      .with_ansi(false)
+      .with_writer(move || writer.clone())
+      .finish();
+
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let (mut redis, server) = scripted_server(&[b"$3\r\nbar\r\n"]);
+
+    assert_eq!(
+      Reply::Ok(DataType::BulkString(b"bar".to_vec())),
+      redis
+        .send_traced(Command::new("GET").arg("foo"), "request-42")
+        .await?
+    );
+
+    // The id is not sent to the server.
+    assert_eq!(vec![command_args(&["GET", "foo"])], server.await.unwrap());
+
+    let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+
+    let lines: Vec<&str> = output
+      .lines()
+      .filter(|line| line.contains("correlation_id=request-42"))
+      .collect();
+
+    assert!(lines.iter().any(|line| line.contains("sending command")));
+    assert!(lines.iter().any(|line| line.contains("reply")));
+    assert!(lines
+      .iter()
+      .all(|line| line.contains("command{name=GET correlation_id=request-42}")));
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn setrange_past_the_maximum_length() -> Result<()> {
+    let (mut redis, server) = scripted_server(&[
+      b":536870912\r\n",
+      b":1025\r\n",
+      b"-ERR string exceeds maximum allowed size (proto-max-bulk-len)\r\n",
+    ]);
+
+    let err = redis
+      .setrange("mykey", MAX_STRING_LEN, b"a")
+      .await
+      .unwrap_err();
+    assert_eq!(
+      Some(&RedisError::InvalidArgument(String::from(
+        "the string would be longer than the maximum of 536870912 bytes"
+      ))),
+      err.downcast_ref::<RedisError>()
+    );
+
+    assert_eq!(
+      MAX_STRING_LEN,
+      redis.setrange("mykey", MAX_STRING_LEN - 1, b"a").await?
+    );
+
+    redis.set_max_string_len(Some(1025));
+
+    let err = redis.setrange("mykey", 1025, b"a").await.unwrap_err();
+    assert_eq!(
+      Some(&RedisError::InvalidArgument(String::from(
+        "the string would be longer than the maximum of 1025 bytes"
+      ))),
+      err.downcast_ref::<RedisError>()
+    );
+    assert_eq!(1025, redis.setrange("mykey", 1024, b"a").await?);
+
+    // Left to the server.
+    redis.set_max_string_len(None);
+
+    let err = redis
+      .setrange("mykey", MAX_STRING_LEN, b"a")
+      .await
+      .unwrap_err();
+    assert_eq!(
+      Some(&RedisError::Server(String::from(
+        "ERR string exceeds maximum allowed size (proto-max-bulk-len)"
+      ))),
+      err.downcast_ref::<RedisError>()
+    );
+
+    assert_eq!(
+      vec![
+        command_args(&["SETRANGE", "mykey", "536870911", "a"]),
+        command_args(&["SETRANGE", "mykey", "1024", "a"]),
+        command_args(&["SETRANGE", "mykey", "536870912", "a"]),
+      ],
+      server.await.unwrap()
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn getrange_and_setrange() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis.send("DEL range:key range:padded").await?;
+    redis.send("SET range:key Hello").await?;
+
+    assert_eq!(b"ell".to_vec(), redis.getrange("range:key", 1, 3).await?);
+    assert_eq!(b"llo".to_vec(), redis.getrange("range:key", -3, -1).await?);
+    assert_eq!(
+      b"Hello".to_vec(),
+      redis.getrange("range:key", 0, 100).await?
+    );
+
+    // Ranges outside of the string.
+    assert_eq!(Vec::<u8>::new(), redis.getrange("range:key", 10, 20).await?);
+    assert_eq!(Vec::<u8>::new(), redis.getrange("range:key", 3, 1).await?);
+    assert_eq!(
+      Vec::<u8>::new(),
+      redis.getrange("range:missing", 0, -1).await?
+    );
+
+    assert_eq!(5, redis.setrange("range:key", 1, b"ELL").await?);
+    assert_eq!(b"HELLo".to_vec(), redis.getrange("range:key", 0, -1).await?);
+
+    assert_eq!(1003, redis.setrange("range:padded", 1000, b"end").await?);
+
+    let padded = redis.getrange("range:padded", 0, -1).await?;
+    assert_eq!(vec![0; 1000], padded[..1000]);
+    assert_eq!(b"end", &padded[1000..]);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn pipeline_errors_are_kept_at_their_index() -> Result<()> {
+    let (mut redis, server) = scripted_server(&[
+      b"+OK\r\n",
+      b"-ERR value is not an integer or out of range\r\n",
+      b"$3\r\nbar\r\n",
+    ]);
+
+    assert_eq!(
+      vec![
+        Ok(DataType::SimpleString(String::from("OK"))),
+        Err(String::from("ERR value is not an integer or out of range")),
+        Ok(DataType::BulkString(b"bar".to_vec())),
+      ],
+      redis
+        .pipeline(vec![
+          Command::new("SET").arg("foo").arg("bar"),
+          Command::new("INCR").arg("foo"),
+          Command::new("GET").arg("foo"),
+        ])
+        .await?
+    );
+
+    assert_eq!(
+      vec![
+        command_args(&["SET", "foo", "bar"]),
+        command_args(&["INCR", "foo"]),
+        command_args(&["GET", "foo"]),
+      ],
+      server.await.unwrap()
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn pipeline() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    let replies = redis
+      .pipeline(vec![
+        Command::new("SET").arg("pipeline:key").arg("value"),
+        Command::new("INCR").arg("pipeline:key"),
+        Command::new("GET").arg("pipeline:key"),
+      ])
+      .await?;
+
+    assert_eq!(3, replies.len());
+    assert_eq!(Ok(DataType::SimpleString(String::from("OK"))), replies[0]);
+    assert!(replies[1].is_err());
+    assert_eq!(Ok(DataType::BulkString(b"value".to_vec())), replies[2]);
+
+    // The connection is still in sync.
+    assert_eq!(
+      Reply::Ok(DataType::SimpleString(String::from("PONG"))),
+      redis.send_command(Command::new("PING")).await?
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn reconnect_replays_state() -> Result<()> {
+    let (addr, server) = mock_server(2, |connection, command| {
+      let command: Vec<&str> = command.iter().map(String::as_str).collect();
+
+      match (connection, command.as_slice()) {
+        (_, ["AUTH", "app", "secret"]) | (_, ["SELECT", "2"]) => Some(b"+OK\r\n"),
+        (0, ["SUBSCRIBE", "news", "sports"]) => Some(
+          b"*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n\
+          *3\r\n$9\r\nsubscribe\r\n$6\r\nsports\r\n:2\r\n",
+        ),
+        // The connection is lost.
+        (0, ["PING"]) => None,
+        (1, ["SUBSCRIBE", "news", "sports"]) => Some(
+          b"*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n\
+          *3\r\n$9\r\nsubscribe\r\n$6\r\nsports\r\n:2\r\n\
+          *3\r\n$7\r\nmessage\r\n$6\r\nsports\r\n$4\r\ngoal\r\n",
+        ),
+        (1, ["PING"]) => Some(b"*2\r\n$4\r\npong\r\n$0\r\n\r\n"),
+        _ => Some(b"-ERR unexpected command\r\n"),
+      }
+    })
+    .await;
+
+    let mut redis = Redis::connect(&addr).await?;
+
+    redis.auth(Some("app"), "secret").await?;
+    redis.select(2).await?;
+    redis.subscribe(&["news", "sports"]).await?;
+
+    assert!(redis.send_command(Command::new("PING")).await.is_err());
+
+    redis.reconnect().await?;
+
+    assert_eq!(2, redis.subscriptions());
+    assert_eq!(
+      &[String::from("news"), String::from("sports")],
+      redis.channels()
+    );
+
+    // The first command after reconnecting works.
+    assert_eq!(
+      Reply::Ok(DataType::Array(vec![
+        DataType::BulkString(b"pong".to_vec()),
+        DataType::BulkString(vec![]),
+      ])),
+      redis.send_command(Command::new("PING")).await?
+    );
+
+    // Messages are received from the channels subscribed to before reconnecting.
+    assert_eq!(
+      Message {
+        channel: b"sports".to_vec(),
+        payload: b"goal".to_vec(),
+      },
+      redis.next_message().await?
+    );
+
+    drop(redis);
+
+    let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+    assert_eq!(
+      vec![
+        vec![
+          args(&["AUTH", "app", "secret"]),
+          args(&["SELECT", "2"]),
+          args(&["SUBSCRIBE", "news", "sports"]),
+          args(&["PING"]),
+        ],
+        vec![
+          args(&["AUTH", "app", "secret"]),
+          args(&["SELECT", "2"]),
+          args(&["SUBSCRIBE", "news", "sports"]),
+          args(&["PING"]),
+        ],
+      ],
+      server.await.unwrap()
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn reconnect_replays_hello() -> Result<()> {
+    let (addr, server) = mock_server(2, |connection, command| {
+      let command: Vec<&str> = command.iter().map(String::as_str).collect();
+
+      match (connection, command.as_slice()) {
+        (_, ["HELLO", "3", "AUTH", "app", "secret"]) => Some(
+          b"%3\r\n$6\r\nserver\r\n$5\r\nredis\r\n$7\r\nversion\r\n$5\r\n7.0.0\r\n\
+          $5\r\nproto\r\n:3\r\n",
+        ),
+        // The connection is lost.
+        (0, ["GET", "mykey"]) => None,
+        // Only a client that switched to RESP3 again can parse the RESP3 Null.
+        (1, ["GET", "mykey"]) => Some(b"_\r\n"),
+        _ => Some(b"-ERR unexpected command\r\n"),
+      }
+    })
+    .await;
+
+    let mut redis = Redis::connect(&addr).await?;
+
+    redis
+      .hello(Protocol::Resp3, Some(("app", "secret")))
+      .await?;
+
+    assert!(redis
+      .send_command(Command::new("GET").arg("mykey"))
+      .await
+      .is_err());
+
+    redis.reconnect().await?;
+
+    assert_eq!(Protocol::Resp3, redis.protocol());
+    assert_eq!(
+      Reply::Ok(DataType::Null),
+      redis.send_command(Command::new("GET").arg("mykey")).await?
+    );
+
+    drop(redis);
+
+    let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+    assert_eq!(
+      vec![
+        vec![
+          args(&["HELLO", "3", "AUTH", "app", "secret"]),
+          args(&["GET", "mykey"]),
+        ],
+        vec![
+          args(&["HELLO", "3", "AUTH", "app", "secret"]),
+          args(&["GET", "mykey"]),
+        ],
+      ],
+      server.await.unwrap()
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn hello_auth() -> Result<()> {
+    let (mut redis, server) = scripted_server(&[
+      b"-WRONGPASS invalid username-password pair or user is disabled.\r\n",
+      b"-NOPROTO unsupported protocol version\r\n",
+      b"%7\r\n$6\r\nserver\r\n$5\r\nredis\r\n$7\r\nversion\r\n$5\r\n7.0.0\r\n\
+      $5\r\nproto\r\n:3\r\n$2\r\nid\r\n:5\r\n$4\r\nmode\r\n$10\r\nstandalone\r\n\
+      $4\r\nrole\r\n$6\r\nmaster\r\n$7\r\nmodules\r\n*0\r\n",
+    ]);
+
+    redis.set_protocol(Protocol::Resp2);
+
+    let err = redis
+      .hello(Protocol::Resp3, Some(("app", "wrong")))
+      .await
+      .unwrap_err();
+    assert!(matches!(
+      err.downcast_ref::<RedisError>(),
+      Some(RedisError::AuthFailed(message)) if message.starts_with("WRONGPASS")
+    ));
+    assert_eq!(Protocol::Resp2, redis.protocol());
+
+    let err = redis.hello(Protocol::Resp3, None).await.unwrap_err();
+    assert!(matches!(
+      err.downcast_ref::<RedisError>(),
+      Some(RedisError::UnsupportedProtocol(_))
+    ));
+
+    assert_eq!(
+      ServerHello {
+        server: String::from("redis"),
+        version: String::from("7.0.0"),
+        protocol: Protocol::Resp3,
+        id: 5,
+        mode: String::from("standalone"),
+        role: String::from("master"),
+      },
+      redis
+        .hello(Protocol::Resp3, Some(("app", "secret")))
+        .await?
+    );
+    assert_eq!(Protocol::Resp3, redis.protocol());
+
+    drop(redis);
+
+    assert_eq!(
+      vec![
+        command_args(&["HELLO", "3", "AUTH", "app", "wrong"]),
+        command_args(&["HELLO", "3"]),
+        command_args(&["HELLO", "3", "AUTH", "app", "secret"]),
+      ],
+      server.await.unwrap()
+    );
+
+    Ok(())
+  }
 
-use crate::data_type::DataType;
-use crate::resp;
+  #[tokio::test]
+  async fn getbit_replies() -> Result<()> {
+    let (mut redis, server) = scripted_server(&[b":0\r\n", b":1\r\n", b":0\r\n", b":2\r\n"]);
 
-#[derive(Debug)]
-pub struct Redis {
-  stream: TcpStream,
-}
+    assert!(!redis.setbit("mykey", 7, true).await?);
+    assert!(redis.getbit("mykey", 7).await?);
+    assert!(!redis.getbit("mykey", 1 << 31).await?);
 
-#[derive(Debug, PartialEq)]
-pub enum Reply {
-  Error(String),
-  Ok(DataType),
-}
+    let err = redis.getbit("mykey", 1 << 32).await.unwrap_err();
+    assert_eq!(
+      Some(&RedisError::InvalidArgument(String::from(
+        "bit offset must be less than 4294967296, got 4294967296"
+      ))),
+      err.downcast_ref::<RedisError>()
+    );
 
-impl Redis {
-  pub async fn connect(ip: &str) -> Result<Self> {
-    info!(ip, "connecting");
+    let err = redis.getbit("mykey", 7).await.unwrap_err();
+    assert_eq!(
+      Some(&RedisError::UnexpectedReply(DataType::Int(2))),
+      err.downcast_ref::<RedisError>()
+    );
 
-    let stream = TcpStream::connect(ip).await.into_diagnostic()?;
+    drop(redis);
 
-    info!(ip, "connected");
+    assert_eq!(
+      vec![
+        command_args(&["SETBIT", "mykey", "7", "1"]),
+        command_args(&["GETBIT", "mykey", "7"]),
+        command_args(&["GETBIT", "mykey", "2147483648"]),
+        command_args(&["GETBIT", "mykey", "7"]),
+      ],
+      server.await.unwrap()
+    );
 
-    Ok(Self { stream })
+    Ok(())
   }
 
-  async fn send_request(&mut self, command: &str) -> Result<Reply> {
-    info!(command, "sending command");
+  #[tokio::test]
+  async fn slowlog_get() -> Result<()> {
+    let (mut redis, server) = scripted_server(&[b"*2\r\n\
+      *6\r\n:14\r\n:1309448221\r\n:15\r\n*1\r\n$4\r\nping\r\n$15\r\n127.0.0.1:58217\r\n$6\r\nworker\r\n\
+      *4\r\n:13\r\n:1309448128\r\n:30\r\n*3\r\n$7\r\nslowlog\r\n$3\r\nget\r\n$3\r\n100\r\n"]);
 
-    self
-      .stream
-      .write_all(command.as_bytes())
+    assert_eq!(
+      vec![
+        SlowlogEntry {
+          id: 14,
+          timestamp: 1309448221,
+          duration: Duration::from_micros(15),
+          args: vec![b"ping".to_vec()],
+          client_addr: String::from("127.0.0.1:58217"),
+          client_name: String::from("worker"),
+        },
+        SlowlogEntry {
+          id: 13,
+          timestamp: 1309448128,
+          duration: Duration::from_micros(30),
+          args: vec![b"slowlog".to_vec(), b"get".to_vec(), b"100".to_vec()],
+          client_addr: String::new(),
+          client_name: String::new(),
+        },
+      ],
+      redis.slowlog_get(Some(2)).await?
+    );
+
+    drop(redis);
+
+    assert_eq!(
+      vec![command_args(&["SLOWLOG", "GET", "2"])],
+      server.await.unwrap()
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn pipeline_ignore() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis.del_matching("pipeline_ignore:*", 100).await?;
+
+    let mut commands: Vec<Command> = (0..1000)
+      .map(|i| {
+        Command::new("SET")
+          .arg(format!("pipeline_ignore:{}", i))
+          .arg_int(i)
+      })
+      .collect();
+
+    // Fails because the value is not an integer, the error is discarded with the other replies.
+    commands.push(
+      Command::new("SET")
+        .arg("pipeline_ignore:string")
+        .arg("value"),
+    );
+    commands.push(Command::new("INCR").arg("pipeline_ignore:string"));
+
+    redis.pipeline_ignore(commands).await?;
+
+    // Every reply was read, the next reply is the one to the next command.
+    assert_eq!(Some(b"0".to_vec()), redis.get("pipeline_ignore:0").await?);
+    assert_eq!(
+      Some(b"999".to_vec()),
+      redis.get("pipeline_ignore:999").await?
+    );
+    assert_eq!(
+      Some(b"value".to_vec()),
+      redis.get("pipeline_ignore:string").await?
+    );
+    assert_eq!(1001, redis.del_matching("pipeline_ignore:*", 100).await?);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn pipeline_ignore_checks_arity() -> Result<()> {
+    let (mut redis, server) =
+      scripted_server(&[b"*1\r\n*3\r\n$3\r\nget\r\n:2\r\n*2\r\n+readonly\r\n+fast\r\n"]);
+
+    redis.refresh_command_table().await?;
+
+    let err = redis
+      .pipeline_ignore(vec![Command::new("GET").arg("foo"), Command::new("GET")])
       .await
-      .into_diagnostic()?;
+      .unwrap_err();
+    assert_eq!(
+      Some(&RedisError::WrongArity(String::from("get"))),
+      err.downcast_ref::<RedisError>()
+    );
+
+    // None of the commands were sent.
+    assert_eq!(vec![command_args(&["COMMAND"])], server.await.unwrap());
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn zadd_opts() -> Result<()> {
+    let (mut redis, server) = scripted_server(&[
+      b":1\r\n",
+      b":2\r\n",
+      b"$3\r\n6.5\r\n",
+      b",8.5\r\n",
+      b"$-1\r\n",
+    ]);
+
+    assert_eq!(
+      ZAddResult::Count(1),
+      redis
+        .zadd_opts(
+          "myzset",
+          &[(1.0, b"one"), (2.0, b"two")],
+          ZAddOptions::new()
+        )
+        .await?
+    );
+
+    // "one" is updated and "three" is added.
+    assert_eq!(
+      ZAddResult::Count(2),
+      redis
+        .zadd_opts(
+          "myzset",
+          &[(5.0, b"one"), (2.0, b"two"), (3.0, b"three")],
+          ZAddOptions::new().ch()
+        )
+        .await?
+    );
+
+    assert_eq!(
+      ZAddResult::Score(Some(6.5)),
+      redis
+        .zadd_opts("myzset", &[(1.5, b"one")], ZAddOptions::new().incr())
+        .await?
+    );
 
-    let mut buffer = vec![0; 4096];
+    assert_eq!(
+      ZAddResult::Score(Some(8.5)),
+      redis
+        .zadd_opts(
+          "myzset",
+          &[(2.0, b"one")],
+          ZAddOptions::new().xx().gt().incr()
+        )
+        .await?
+    );
+
+    // "one" exists, so NX does not update it.
+    assert_eq!(
+      ZAddResult::Score(None),
+      redis
+        .zadd_opts("myzset", &[(1.0, b"one")], ZAddOptions::new().nx().incr())
+        .await?
+    );
 
-    let _bytes_read = self.stream.read(&mut buffer).await.into_diagnostic()?;
+    type Members<'a> = &'a [(f64, &'a [u8])];
 
-    info!("reply: {}", String::from_utf8_lossy(&buffer));
+    let tests: Vec<(Members, ZAddOptions, &str)> = vec![
+      (
+        &[(1.0, b"one")],
+        ZAddOptions::new().nx().xx(),
+        "NX and XX can't be used together",
+      ),
+      (
+        &[(1.0, b"one")],
+        ZAddOptions::new().gt().lt(),
+        "GT and LT can't be used together",
+      ),
+      (
+        &[(1.0, b"one")],
+        ZAddOptions::new().nx().gt(),
+        "NX can't be used with GT or LT",
+      ),
+      (
+        &[(1.0, b"one"), (2.0, b"two")],
+        ZAddOptions::new().incr(),
+        "INCR can only be used with one member",
+      ),
+      (
+        &[(f64::NAN, b"one")],
+        ZAddOptions::new(),
+        "score must not be NaN",
+      ),
+    ];
 
-    match resp::parse(buffer)? {
-      DataType::Error(message) => Ok(Reply::Error(message)),
-      data_type => Ok(Reply::Ok(data_type)),
+    for (members, options, expected) in tests {
+      let err = redis
+        .zadd_opts("myzset", members, options)
+        .await
+        .unwrap_err();
+      assert_eq!(
+        Some(&RedisError::InvalidArgument(String::from(expected))),
+        err.downcast_ref::<RedisError>()
+      );
     }
+
+    drop(redis);
+
+    assert_eq!(
+      vec![
+        command_args(&["ZADD", "myzset", "1", "one", "2", "two"]),
+        command_args(&["ZADD", "myzset", "CH", "5", "one", "2", "two", "3", "three"]),
+        command_args(&["ZADD", "myzset", "INCR", "1.5", "one"]),
+        command_args(&["ZADD", "myzset", "XX", "GT", "INCR", "2", "one"]),
+        command_args(&["ZADD", "myzset", "NX", "INCR", "1", "one"]),
+      ],
+      server.await.unwrap()
+    );
+
+    Ok(())
   }
 
-  pub async fn send(&mut self, command: &str) -> Result<Reply> {
-    info!(command, "sending command");
+  #[tokio::test]
+  async fn lrange_stream() -> Result<()> {
+    let (client, mut server) = duplex(4096);
 
-    let encoded_command = resp::encode(command)?;
+    let mut reply = b"*5000\r\n".to_vec();
+    for i in 0..5000 {
+      let element = format!("element:{}", i);
+      reply.extend_from_slice(format!("${}\r\n{}\r\n", element.len(), element).as_bytes());
+    }
+    let reply_len = reply.len();
+
+    let server = tokio::spawn(async move {
+      let mut buffer = [0; 4096];
+
+      // LRANGE, read to the end, LRANGE, dropped after 10 elements, and then PING.
+      for reply in [&reply[..], &reply[..], b"+PONG\r\n"] {
+        assert!(server.read(&mut buffer).await.unwrap() > 0);
+        server.write_all(reply).await.unwrap();
+      }
+    });
+
+    let mut redis = Redis::from_stream(client);
+
+    let mut elements = redis.lrange_stream("mylist", 0, -1).await?;
+    assert_eq!(5000, elements.remaining());
+
+    let mut count = 0;
+    while let Some(element) = elements.next().await? {
+      assert_eq!(format!("element:{}", count).into_bytes(), element);
+      count += 1;
+    }
+    assert_eq!(5000, count);
+    drop(elements);
+
+    // The elements were read as they arrived instead of the whole reply being buffered.
+    assert!(redis.buffer.capacity() < reply_len);
+
+    let mut elements = redis.lrange_stream("mylist", 0, -1).await?;
+    for _ in 0..10 {
+      elements.next().await?;
+    }
+    drop(elements);
+
+    assert_eq!(
+      Reply::Ok(DataType::SimpleString(String::from("PONG"))),
+      redis.send_command(Command::new("PING")).await?
+    );
 
-    self.send_request(&encoded_command).await
+    server.await.unwrap();
+
+    Ok(())
   }
 
-  #[allow(dead_code)]
-  pub async fn flushall(&mut self) -> Result<Reply> {
-    self.send_request("FLUSHALL\r\n").await
+  #[tokio::test]
+  async fn retry_while_loading() -> Result<()> {
+    let (mut redis, server) = scripted_server(&[
+      b"-LOADING Redis is loading the dataset in memory\r\n",
+      b"-LOADING Redis is loading the dataset in memory\r\n",
+      b"-LOADING Redis is loading the dataset in memory\r\n",
+      b"$3\r\nbar\r\n",
+    ]);
+
+    let get = || Command::new("GET").arg("foo");
+
+    assert_eq!(
+      Err(RedisError::Loading(String::from(
+        "LOADING Redis is loading the dataset in memory"
+      ))),
+      redis.send_command(get()).await?.into_data()
+    );
+
+    redis.retry_while_loading(Duration::from_secs(10));
+
+    assert_reply_bulk!(redis.send_command(get()).await?, "bar");
+
+    drop(redis);
+
+    assert_eq!(
+      vec![command_args(&["GET", "foo"]); 4],
+      server.await.unwrap()
+    );
+
+    Ok(())
   }
-}
 
-#[cfg(test)]
-mod tests {
-  use super::*;
+  #[tokio::test]
+  async fn retry_while_loading_timeout() -> Result<()> {
+    let (mut redis, _server) = scripted_server(&[
+      b"-LOADING Redis is loading the dataset in memory\r\n",
+      b"-LOADING Redis is loading the dataset in memory\r\n",
+      b"-LOADING Redis is loading the dataset in memory\r\n",
+    ]);
+
+    // Gives up before the third retry, which would be 70ms after the command was first sent.
+    redis.retry_while_loading(Duration::from_millis(50));
+
+    assert_eq!(
+      Reply::Error(String::from(
+        "LOADING Redis is loading the dataset in memory"
+      )),
+      redis.send_command(Command::new("GET").arg("foo")).await?
+    );
 
-  const TEST_REDIS_IP: &'static str = "127.0.0.1:6380";
+    Ok(())
+  }
 
   #[tokio::test]
-  async fn basic_commands() -> Result<()> {
+  async fn xpending() -> Result<()> {
+    let (mut redis, server) = scripted_server(&[
+      b"*4\r\n:3\r\n$15\r\n1526984818136-0\r\n$15\r\n1526984818149-0\r\n\
+      *2\r\n*2\r\n$5\r\nAlice\r\n$1\r\n2\r\n*2\r\n$3\r\nBob\r\n$1\r\n1\r\n",
+      b"*4\r\n:0\r\n$-1\r\n$-1\r\n*-1\r\n",
+      b"*2\r\n*4\r\n$15\r\n1526984818136-0\r\n$5\r\nAlice\r\n:196415\r\n:1\r\n\
+      *4\r\n$15\r\n1526984818149-0\r\n$5\r\nAlice\r\n:196415\r\n:3\r\n",
+    ]);
+
+    assert_eq!(
+      PendingSummary {
+        count: 3,
+        min_id: Some(String::from("1526984818136-0")),
+        max_id: Some(String::from("1526984818149-0")),
+        consumers: vec![(String::from("Alice"), 2), (String::from("Bob"), 1)],
+      },
+      redis.xpending_summary("mystream", "group55").await?
+    );
+
+    assert_eq!(
+      PendingSummary::default(),
+      redis.xpending_summary("mystream", "group56").await?
+    );
+
+    assert_eq!(
+      vec![
+        PendingMessage {
+          id: String::from("1526984818136-0"),
+          consumer: String::from("Alice"),
+          idle: Duration::from_millis(196415),
+          deliveries: 1,
+        },
+        PendingMessage {
+          id: String::from("1526984818149-0"),
+          consumer: String::from("Alice"),
+          idle: Duration::from_millis(196415),
+          deliveries: 3,
+        },
+      ],
+      redis
+        .xpending_extended("mystream", "group55", "-", "+", 10, Some("Alice"))
+        .await?
+    );
+
+    drop(redis);
+
+    assert_eq!(
+      vec![
+        command_args(&["XPENDING", "mystream", "group55"]),
+        command_args(&["XPENDING", "mystream", "group56"]),
+        command_args(&["XPENDING", "mystream", "group55", "-", "+", "10", "Alice"]),
+      ],
+      server.await.unwrap()
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn getset_uses_set_get_when_supported() -> Result<()> {
+    let tests: Vec<(&'static [&'static [u8]], Vec<DataType>)> = vec![
+      (
+        &[
+          b"$41\r\n# Server\r\nredis_version:6.2.0\r\nrun_id:1\r\n\r\n",
+          b"$3\r\nold\r\n",
+        ],
+        vec![
+          command_args(&["INFO", "server"]),
+          command_args(&["SET", "mykey", "new", "GET"]),
+        ],
+      ),
+      (
+        &[
+          b"$42\r\n# Server\r\nredis_version:6.0.16\r\nrun_id:1\r\n\r\n",
+          b"$3\r\nold\r\n",
+        ],
+        vec![
+          command_args(&["INFO", "server"]),
+          command_args(&["GETSET", "mykey", "new"]),
+        ],
+      ),
+    ];
+
+    for (replies, expected) in tests {
+      let (mut redis, server) = scripted_server(replies);
+
+      assert_eq!(Some(b"old".to_vec()), redis.getset("mykey", b"new").await?);
+
+      drop(redis);
+
+      assert_eq!(expected, server.await.unwrap());
+    }
+
+    // The version in the reply to HELLO is used instead of asking for it.
+    let (mut redis, server) = scripted_server(&[
+      b"%2\r\n$6\r\nserver\r\n$5\r\nredis\r\n$7\r\nversion\r\n$5\r\n7.2.4\r\n",
+      b"$-1\r\n",
+    ]);
+
+    redis.hello(Protocol::Resp3, None).await?;
+
+    assert_eq!((7, 2, 4), redis.server_version().await?);
+    assert_eq!(None, redis.getset("mykey", b"new").await?);
+
+    drop(redis);
+
+    assert_eq!(
+      vec![
+        command_args(&["HELLO", "3"]),
+        command_args(&["SET", "mykey", "new", "GET"]),
+      ],
+      server.await.unwrap()
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn hgetall_smart() -> Result<()> {
+    let (mut redis, server) = fake_redis(&[
+      (&["OBJECT", "ENCODING", "small"], b"$8\r\nlistpack\r\n"),
+      (
+        &["HGETALL", "small"],
+        b"%2\r\n$2\r\nf1\r\n$2\r\nv1\r\n$2\r\nf2\r\n$2\r\nv2\r\n",
+      ),
+      (&["OBJECT", "ENCODING", "large"], b"$9\r\nhashtable\r\n"),
+      (
+        &["HSCAN", "large", "0", "COUNT", "1000"],
+        b"*2\r\n$2\r\n17\r\n*4\r\n$2\r\nf1\r\n$2\r\nv1\r\n$2\r\nf2\r\n$2\r\nv2\r\n",
+      ),
+      // A field returned again, which HSCAN may do.
+      (
+        &["HSCAN", "large", "17", "COUNT", "1000"],
+        b"*2\r\n$1\r\n0\r\n*2\r\n$2\r\nf2\r\n$2\r\nv2\r\n",
+      ),
+      (&["OBJECT", "ENCODING", "missing"], b"$-1\r\n"),
+    ]);
+
+    let expected: HashMap<Vec<u8>, Vec<u8>> = vec![
+      (b"f1".to_vec(), b"v1".to_vec()),
+      (b"f2".to_vec(), b"v2".to_vec()),
+    ]
+    .into_iter()
+    .collect();
+
+    assert_eq!(expected, redis.hgetall_smart("small").await?);
+    assert_eq!(expected, redis.hgetall_smart("large").await?);
+    assert_eq!(HashMap::new(), redis.hgetall_smart("missing").await?);
+
+    drop(redis);
+
+    assert_eq!(
+      vec![
+        command_args(&["OBJECT", "ENCODING", "small"]),
+        command_args(&["HGETALL", "small"]),
+        command_args(&["OBJECT", "ENCODING", "large"]),
+        command_args(&["HSCAN", "large", "0", "COUNT", "1000"]),
+        command_args(&["HSCAN", "large", "17", "COUNT", "1000"]),
+        command_args(&["OBJECT", "ENCODING", "missing"]),
+      ],
+      server.await.unwrap()
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn zrangebyscore() -> Result<()> {
+    let (mut redis, server) = scripted_server(&[
+      b"*6\r\n$3\r\none\r\n$1\r\n1\r\n$3\r\ntwo\r\n$1\r\n2\r\n$5\r\nthree\r\n$1\r\n3\r\n",
+      b"*2\r\n$3\r\ntwo\r\n$5\r\nthree\r\n",
+      b"*2\r\n*2\r\n$3\r\ntwo\r\n,2\r\n*2\r\n$5\r\nthree\r\n,3\r\n",
+      b"*2\r\n$1\r\nb\r\n$1\r\nc\r\n",
+    ]);
+
+    assert_eq!(
+      vec![
+        (b"one".to_vec(), Some(1.0)),
+        (b"two".to_vec(), Some(2.0)),
+        (b"three".to_vec(), Some(3.0)),
+      ],
+      redis
+        .zrangebyscore(
+          "myzset",
+          ScoreBound::Inclusive(1.0),
+          ScoreBound::Inclusive(3.0),
+          true,
+          None
+        )
+        .await?
+    );
+
+    assert_eq!(
+      vec![(b"two".to_vec(), None), (b"three".to_vec(), None)],
+      redis
+        .zrangebyscore(
+          "myzset",
+          ScoreBound::Exclusive(1.0),
+          ScoreBound::Inclusive(3.5),
+          false,
+          None
+        )
+        .await?
+    );
+
+    // A RESP3 reply, where each member and its score are a pair.
+    assert_eq!(
+      vec![(b"two".to_vec(), Some(2.0)), (b"three".to_vec(), Some(3.0))],
+      redis
+        .zrangebyscore(
+          "myzset",
+          ScoreBound::NegInf,
+          ScoreBound::PosInf,
+          true,
+          Some((1, 2))
+        )
+        .await?
+    );
+
+    assert_eq!(
+      vec![b"b".to_vec(), b"c".to_vec()],
+      redis
+        .zrangebylex(
+          "myzset",
+          LexBound::Exclusive(b"a".to_vec()),
+          LexBound::Max,
+          Some((0, 2))
+        )
+        .await?
+    );
+
+    drop(redis);
+
+    assert_eq!(
+      vec![
+        command_args(&["ZRANGEBYSCORE", "myzset", "1", "3", "WITHSCORES"]),
+        command_args(&["ZRANGEBYSCORE", "myzset", "(1", "3.5"]),
+        command_args(&[
+          "ZRANGEBYSCORE",
+          "myzset",
+          "-inf",
+          "+inf",
+          "WITHSCORES",
+          "LIMIT",
+          "1",
+          "2"
+        ]),
+        command_args(&["ZRANGEBYLEX", "myzset", "(a", "+", "LIMIT", "0", "2"]),
+      ],
+      server.await.unwrap()
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn values_survive_reload() -> Result<()> {
     let mut redis = Redis::connect(TEST_REDIS_IP).await?;
 
+    // Every byte value, including the ones that are not valid UTF-8 and "\r\n".
+    let bytes: Vec<u8> = (0..=255).collect();
+
+    redis
+      .send("DEL reload:string reload:int reload:list reload:hash reload:zset")
+      .await?;
+    redis
+      .send_command(
+        Command::new("SET")
+          .arg("reload:string")
+          .arg(bytes.as_slice()),
+      )
+      .await?;
+    redis
+      .send_command(Command::new("SET").arg("reload:int").arg_int(42))
+      .await?;
+    redis.send("RPUSH reload:list a b c").await?;
+    redis.send("HSET reload:hash field value").await?;
+    redis
+      .zadd_opts(
+        "reload:zset",
+        &[(1.5, b"one"), (2.0, b"two")],
+        ZAddOptions::new(),
+      )
+      .await?;
+
+    redis.reload().await?;
+
+    assert_reply_bulk!(redis.send("GET reload:string").await?, bytes);
+    assert_reply_int!(redis.send("INCR reload:int").await?, 43);
     assert_eq!(
-      Reply::Ok(DataType::SimpleString(String::from("OK"))),
-      redis.flushall().await?,
+      vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()],
+      redis.lrange("reload:list", 0, -1).await?
     );
+    assert_reply_bulk!(redis.send("HGET reload:hash field").await?, "value");
+    assert_eq!(
+      vec![(b"one".to_vec(), Some(1.5)), (b"two".to_vec(), Some(2.0))],
+      redis
+        .zrangebyscore(
+          "reload:zset",
+          ScoreBound::NegInf,
+          ScoreBound::PosInf,
+          true,
+          None
+        )
+        .await?
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn client_trackinginfo() -> Result<()> {
+    let (mut redis, server) = scripted_server(&[
+      b"*6\r\n$5\r\nflags\r\n*1\r\n$3\r\noff\r\n$8\r\nredirect\r\n:-1\r\n$8\r\nprefixes\r\n*0\r\n",
+      b"%3\r\n$5\r\nflags\r\n~2\r\n+on\r\n+bcast\r\n$8\r\nredirect\r\n:7\r\n$8\r\nprefixes\r\n*2\r\n$5\r\nuser:\r\n$5\r\npost:\r\n",
+    ]);
 
     assert_eq!(
-      Reply::Ok(DataType::Int(0)),
-      redis.send("LLEN mylist").await?,
+      TrackingInfo {
+        flags: vec![String::from("off")],
+        redirect: -1,
+        prefixes: vec![],
+      },
+      redis.client_trackinginfo().await?
     );
 
     assert_eq!(
-      Reply::Ok(DataType::Int(1)),
-      redis.send(r#"LPUSH mylist World"#).await?
+      TrackingInfo {
+        flags: vec![String::from("on"), String::from("bcast")],
+        redirect: 7,
+        prefixes: vec![b"user:".to_vec(), b"post:".to_vec()],
+      },
+      redis.client_trackinginfo().await?
     );
 
+    drop(redis);
+
     assert_eq!(
-      Reply::Ok(DataType::Int(2)),
-      redis.send(r#"LPUSH mylist Hello"#).await?
+      vec![
+        command_args(&["CLIENT", "TRACKINGINFO"]),
+        command_args(&["CLIENT", "TRACKINGINFO"]),
+      ],
+      server.await.unwrap()
     );
 
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn numkeys_commands() -> Result<()> {
+    let (mut redis, server) = scripted_server(&[
+      b":1\r\n",
+      b"*2\r\n$1\r\na\r\n$1\r\nb\r\n",
+      b"*1\r\n$1\r\nb\r\n",
+      b"*1\r\n$1\r\na\r\n",
+      b"*2\r\n$6\r\nlist:2\r\n*2\r\n$1\r\nx\r\n$1\r\ny\r\n",
+      b"*-1\r\n",
+    ]);
+
+    assert_eq!(1, redis.sintercard(&["s1", "s2", "s3"], Some(5)).await?);
     assert_eq!(
-      Reply::Ok(DataType::Int(2)),
-      redis.send("LLEN mylist").await?,
+      vec![b"a".to_vec(), b"b".to_vec()],
+      redis.zunion(&["z1", "z2", "z3"]).await?
+    );
+    assert_eq!(vec![b"b".to_vec()], redis.zinter(&["z1", "z2"]).await?);
+    assert_eq!(vec![b"a".to_vec()], redis.zdiff(&["z1", "z2"]).await?);
+    assert_eq!(
+      Some((String::from("list:2"), vec![b"x".to_vec(), b"y".to_vec()])),
+      redis.lmpop(&["list:1", "list:2"], ListEnd::Left, 2).await?
+    );
+    assert_eq!(None, redis.lmpop(&["list:1"], ListEnd::Right, 1).await?);
+
+    drop(redis);
+
+    assert_eq!(
+      vec![
+        command_args(&["SINTERCARD", "3", "s1", "s2", "s3", "LIMIT", "5"]),
+        command_args(&["ZUNION", "3", "z1", "z2", "z3"]),
+        command_args(&["ZINTER", "2", "z1", "z2"]),
+        command_args(&["ZDIFF", "2", "z1", "z2"]),
+        command_args(&["LMPOP", "2", "list:1", "list:2", "LEFT", "COUNT", "2"]),
+        command_args(&["LMPOP", "1", "list:1", "RIGHT", "COUNT", "1"]),
+      ],
+      server.await.unwrap()
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn persist() -> Result<()> {
+    let mut redis = Redis::connect(TEST_REDIS_IP).await?;
+
+    redis
+      .send("DEL persist:with_ttl persist:without_ttl persist:missing")
+      .await?;
+    redis.send("SET persist:with_ttl Hello").await?;
+    redis.send("SET persist:without_ttl Hello").await?;
+
+    assert!(redis.expire("persist:with_ttl", 100).await?);
+    assert!(redis.persist("persist:with_ttl").await?);
+    assert_reply_int!(redis.send("TTL persist:with_ttl").await?, -1);
+
+    assert!(!redis.persist("persist:without_ttl").await?);
+    assert!(!redis.persist("persist:missing").await?);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn rename() -> Result<()> {
+    let (mut redis, server) = scripted_server(&[
+      b"+OK\r\n",
+      b":1\r\n",
+      b":0\r\n",
+      b"-ERR no such key\r\n",
+      b"-ERR no such key\r\n",
+    ]);
+
+    redis.rename("old", "new").await?;
+    assert!(redis.renamenx("new", "newer").await?);
+    assert!(!redis.renamenx("newer", "existing").await?);
+
+    let err = redis.rename("missing", "new").await.unwrap_err();
+    assert_eq!(
+      Some(&RedisError::NoSuchKey(String::from("missing"))),
+      err.downcast_ref::<RedisError>()
     );
 
+    let err = redis.renamenx("missing", "new").await.unwrap_err();
     assert_eq!(
-      Reply::Ok(DataType::BulkString(String::from("Hello"))),
-      redis.send("LPOP mylist").await?,
+      Some(&RedisError::NoSuchKey(String::from("missing"))),
+      err.downcast_ref::<RedisError>()
     );
 
+    drop(redis);
+
     assert_eq!(
-      Reply::Ok(DataType::BulkString(String::from("World"))),
-      redis.send("LPOP mylist").await?,
+      vec![
+        command_args(&["RENAME", "old", "new"]),
+        command_args(&["RENAMENX", "new", "newer"]),
+        command_args(&["RENAMENX", "newer", "existing"]),
+        command_args(&["RENAME", "missing", "new"]),
+        command_args(&["RENAMENX", "missing", "new"]),
+      ],
+      server.await.unwrap()
     );
 
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn send_expect_int() -> Result<()> {
+    let (mut redis, server) = scripted_server(&[
+      b":42\r\n",
+      b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n",
+      b"$3\r\nfoo\r\n",
+      b":-1\r\n",
+    ]);
+
+    assert_eq!(42, redis.send_expect_int("INCR counter").await?);
+
+    let err = redis.send_expect_int("LLEN mykey").await.unwrap_err();
     assert_eq!(
-      Reply::Ok(DataType::Int(0)),
-      redis.send("LLEN mylist").await?
+      Some(&RedisError::Server(String::from(
+        "WRONGTYPE Operation against a key holding the wrong kind of value"
+      ))),
+      err.downcast_ref::<RedisError>()
+    );
+
+    let err = redis.send_expect_int("GET mykey").await.unwrap_err();
+    assert_eq!(
+      Some(&RedisError::UnexpectedReply(DataType::BulkString(
+        b"foo".to_vec()
+      ))),
+      err.downcast_ref::<RedisError>()
+    );
+
+    // The reply after a mismatch is read from where the previous one ended.
+    assert_eq!(-1, redis.send_expect_int("DECR counter").await?);
+
+    drop(redis);
+
+    assert_eq!(
+      vec![
+        command_args(&["INCR", "counter"]),
+        command_args(&["LLEN", "mykey"]),
+        command_args(&["GET", "mykey"]),
+        command_args(&["DECR", "counter"]),
+      ],
+      server.await.unwrap()
     );
 
     Ok(())
@@ -0,0 +1,77 @@
+/// Building the replies a Redis server sends to its clients.
+///
+/// Useful when writing a server, or a mock of one, with this crate.
+/// The values can be sent to a client after being encoded with [crate::resp::encode_value].
+///
+/// # Examples
+///
+/// ```terminal
+/// resp::encode_value(&reply::ok())                                -> "+OK\r\n"
+/// resp::encode_value(&reply::error("ERR", "unknown command"))     -> "-ERR unknown command\r\n"
+/// ```
+use crate::data_type::DataType;
+
+/// The "+OK" reply sent by commands that succeed without returning data.
+pub fn ok() -> DataType {
+  DataType::SimpleString(String::from("OK"))
+}
+
+pub fn integer(n: i64) -> DataType {
+  DataType::Int(n)
+}
+
+pub fn bulk(bytes: impl Into<Vec<u8>>) -> DataType {
+  DataType::BulkString(bytes.into())
+}
+
+/// The reply used to signal that a value does not exist.
+pub fn nil() -> DataType {
+  DataType::Null
+}
+
+pub fn array(elements: Vec<DataType>) -> DataType {
+  DataType::Array(elements)
+}
+
+/// An error reply, where `code` is the first word of the error, like "ERR" or "WRONGTYPE".
+pub fn error(code: &str, message: &str) -> DataType {
+  DataType::Error(format!("{} {}", code, message))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::resp;
+
+  #[test]
+  fn encoded_replies() {
+    let tests = vec![
+      (ok(), b"+OK\r\n".to_vec()),
+      (integer(0), b":0\r\n".to_vec()),
+      (integer(-3), b":-3\r\n".to_vec()),
+      (bulk("foobar"), b"$6\r\nfoobar\r\n".to_vec()),
+      (bulk(vec![0, 255]), b"$2\r\n\x00\xff\r\n".to_vec()),
+      (nil(), b"$-1\r\n".to_vec()),
+      (array(vec![]), b"*0\r\n".to_vec()),
+      (
+        array(vec![bulk("foo"), nil(), integer(1)]),
+        b"*3\r\n$3\r\nfoo\r\n$-1\r\n:1\r\n".to_vec(),
+      ),
+      (
+        error("ERR", "unknown command 'foobar'"),
+        b"-ERR unknown command 'foobar'\r\n".to_vec(),
+      ),
+      (
+        error(
+          "WRONGTYPE",
+          "Operation against a key holding the wrong kind of value",
+        ),
+        b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n".to_vec(),
+      ),
+    ];
+
+    for (reply, expected) in tests {
+      assert_eq!(expected, resp::encode_value(&reply));
+    }
+  }
+}
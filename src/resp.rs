@@ -4,9 +4,11 @@
 /// RESP uses prefixed lengths to transfer bulk data,
 /// so there is never a need to scan the payload for special characters like it happens for instance with JSON,
 /// nor to quote the payload that needs to be sent to the server.
-use crate::data_type::DataType;
+use crate::data_type::{DataType, DataTypeKind};
 use miette::{Diagnostic, Result, SourceSpan};
+use std::collections::HashMap;
 use std::fmt::Write;
+use std::io::{self, Read};
 use thiserror::Error;
 
 #[allow(clippy::enum_variant_names)]
@@ -46,22 +48,337 @@ pub enum ParserError {
     span: SourceSpan,
     message: String,
   },
+  #[error("arrays are nested too deeply")]
+  #[diagnostic(help("the maximum depth is {max_depth}"))]
+  DepthLimitExceeded {
+    #[source_code]
+    src: String,
+    #[label("here")]
+    span: SourceSpan,
+    max_depth: usize,
+  },
+  #[error("array is too long")]
+  #[diagnostic(help("the maximum array length is {max_array_len}"))]
+  ArrayLengthLimitExceeded {
+    #[source_code]
+    src: String,
+    #[label("here")]
+    span: SourceSpan,
+    max_array_len: usize,
+  },
+  #[error("bulk string is too long")]
+  #[diagnostic(help("the maximum bulk string length is {max_bulk_len}"))]
+  BulkLengthLimitExceeded {
+    #[source_code]
+    src: String,
+    #[label("here")]
+    span: SourceSpan,
+    max_bulk_len: usize,
+  },
+  #[error("too many elements")]
+  #[diagnostic(help("the maximum number of elements is {max_total_elements}"))]
+  ElementLimitExceeded {
+    #[source_code]
+    src: String,
+    #[label("here")]
+    span: SourceSpan,
+    max_total_elements: usize,
+  },
+  #[error("inline command is too long")]
+  #[diagnostic(help("the maximum inline command length is {max_inline_len}"))]
+  InlineCommandTooLong {
+    #[source_code]
+    src: String,
+    #[label("here")]
+    span: SourceSpan,
+    max_inline_len: usize,
+  },
+  #[error("unexpected bytes after the value")]
+  #[diagnostic(help("the value ends at byte {position}, the input should end there too"))]
+  TrailingBytes {
+    #[source_code]
+    src: String,
+    #[label("here")]
+    span: SourceSpan,
+    position: usize,
+  },
+  #[error("unexpected {type_name} for the protocol version")]
+  #[diagnostic(help("{type_name} is only sent by servers speaking RESP3"))]
+  ProtocolMismatch {
+    #[source_code]
+    src: String,
+    #[label("here")]
+    span: SourceSpan,
+    type_name: String,
+  },
+}
+
+impl ParserError {
+  /// Returns the source and the span of the error.
+  fn source_mut(&mut self) -> (&mut String, &mut SourceSpan) {
+    match self {
+      ParserError::UnexpectedByte { src, span }
+      | ParserError::UnexpectedEndOfInput { src, span }
+      | ParserError::UnexpectedType { src, span, .. }
+      | ParserError::UnexpectedValue { src, span, .. }
+      | ParserError::DepthLimitExceeded { src, span, .. }
+      | ParserError::ArrayLengthLimitExceeded { src, span, .. }
+      | ParserError::BulkLengthLimitExceeded { src, span, .. }
+      | ParserError::ElementLimitExceeded { src, span, .. }
+      | ParserError::InlineCommandTooLong { src, span, .. }
+      | ParserError::TrailingBytes { src, span, .. }
+      | ParserError::ProtocolMismatch { src, span, .. } => (src, span),
+    }
+  }
+
+  /// Replaces the source of the error with a hex dump of the bytes around the error in `input`,
+  /// pointing the span at the hex of the bytes the error is about.
+  fn with_hex_dump(mut self, input: &[u8]) -> Self {
+    let (src, span) = self.source_mut();
+
+    let (dump, dump_span) = hex_dump(input, span.offset(), span.len());
+
+    *src = dump;
+    *span = dump_span;
+
+    self
+  }
+}
+
+/// How many bytes are shown in each line of a hex dump.
+const HEX_DUMP_WIDTH: usize = 16;
+
+/// How many lines are shown before and after the line with the error in a hex dump.
+const HEX_DUMP_CONTEXT_LINES: usize = 2;
+
+/// Renders the lines of `input` around `position` like `hexdump -C` does:
+///
+/// ```terminal
+/// 00000000  2a 32 0d 0a 24 33 0d 0a  66 6f 6f 0d 0a 3f 00 ff  |*2..$3..foo..?..|
+/// ```
+///
+/// Returns the dump and the span of the hex of the `len` bytes starting at `position`,
+/// up to the end of the line.
+fn hex_dump(input: &[u8], position: usize, len: usize) -> (String, SourceSpan) {
+  let error_line = position / HEX_DUMP_WIDTH;
+  let last_line = input.len().saturating_sub(1) / HEX_DUMP_WIDTH;
+
+  let first_line = error_line.saturating_sub(HEX_DUMP_CONTEXT_LINES);
+  let last_line = last_line.min(error_line + HEX_DUMP_CONTEXT_LINES);
+
+  // Where the hex of a byte starts in a line.
+  let hex_column = |column: usize| 10 + column * 3 + if column >= 8 { 1 } else { 0 };
+
+  let mut dump = String::new();
+  let mut span = (0, 0).into();
+
+  for line in first_line..=last_line {
+    let line_starts_at = line * HEX_DUMP_WIDTH;
+    let bytes = input
+      .get(line_starts_at..input.len().min(line_starts_at + HEX_DUMP_WIDTH))
+      .unwrap_or_default();
+
+    if line == error_line {
+      let column = position % HEX_DUMP_WIDTH;
+      let last_column = (column + len.max(1) - 1).min(HEX_DUMP_WIDTH - 1);
+
+      span = (
+        dump.len() + hex_column(column),
+        hex_column(last_column) + 2 - hex_column(column),
+      )
+        .into();
+    }
+
+    let _ = write!(dump, "{:08x}  ", line_starts_at);
+
+    for column in 0..HEX_DUMP_WIDTH {
+      match bytes.get(column) {
+        Some(byte) => {
+          let _ = write!(dump, "{:02x} ", byte);
+        }
+        None => dump.push_str("   "),
+      }
+
+      if column == 7 {
+        dump.push(' ');
+      }
+    }
+
+    dump.push_str(" |");
+
+    for byte in bytes {
+      dump.push(if byte.is_ascii_graphic() || *byte == b' ' {
+        *byte as char
+      } else {
+        '.'
+      });
+    }
+
+    dump.push_str("|\n");
+  }
+
+  (dump, span)
+}
+
+/// The version of RESP the server was asked to speak.
+///
+/// RESP3 is a superset of RESP2, so a RESP2 reply is always valid RESP3
+/// but a server speaking RESP2 never sends the types added by RESP3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+  Resp2,
+  Resp3,
+}
+
+/// Returns the name of the type that starts with `byte` if the type was added in RESP3.
+fn resp3_type_name(byte: u8) -> Option<&'static str> {
+  match byte {
+    b'_' => Some("null"),
+    b',' => Some("double"),
+    b'#' => Some("boolean"),
+    b'!' => Some("blob error"),
+    b'=' => Some("verbatim string"),
+    b'(' => Some("big number"),
+    b'%' => Some("map"),
+    b'~' => Some("set"),
+    b'|' => Some("attribute"),
+    b'>' => Some("push"),
+    _ => None,
+  }
+}
+
+/// Bounds how much work parsing a value can take,
+/// used to parse input that can't be trusted with [parse_with_limits].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParseLimits {
+  /// How many arrays can be nested inside each other.
+  pub max_depth: usize,
+  /// How many elements an array can have.
+  pub max_array_len: usize,
+  /// How many bytes a Bulk String can have.
+  pub max_bulk_len: usize,
+  /// How many array elements can be parsed in total, counting every nested array.
+  pub max_total_elements: usize,
+  /// How many bytes an inline command can have, not counting the "\r\n", see [parse_inline_command].
+  pub max_inline_len: usize,
+}
+
+impl ParseLimits {
+  /// Limits that never stop a parse.
+  pub const UNLIMITED: ParseLimits = ParseLimits {
+    max_depth: usize::MAX,
+    max_array_len: usize::MAX,
+    max_bulk_len: usize::MAX,
+    max_total_elements: usize::MAX,
+    max_inline_len: usize::MAX,
+  };
+}
+
+impl Default for ParseLimits {
+  fn default() -> Self {
+    Self {
+      max_depth: 64,
+      max_array_len: 1024 * 1024,
+      // The largest Bulk String a Redis server accepts by default: 512 MB.
+      max_bulk_len: 512 * 1024 * 1024,
+      max_total_elements: 1024 * 1024,
+      // The longest inline command a Redis server accepts: 64 KB.
+      max_inline_len: 64 * 1024,
+    }
+  }
+}
+
+/// Tallies the values parsed during a session, to find out the mix of replies a workload gets.
+///
+/// Values nested in aggregates are counted too, so a reply like [1, 2] counts
+/// one Array and two Integers.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParseStats {
+  counts: HashMap<DataTypeKind, u64>,
+  bytes: u64,
+}
+
+impl ParseStats {
+  /// Records `value`, which was parsed from `bytes` bytes.
+  pub fn record(&mut self, value: &DataType, bytes: usize) {
+    self.bytes += bytes as u64;
+    self.count(value);
+  }
+
+  fn count(&mut self, value: &DataType) {
+    *self.counts.entry(value.kind()).or_insert(0) += 1;
+
+    match value {
+      DataType::Array(elements) | DataType::Set(elements) | DataType::Push(elements) => {
+        for element in elements {
+          self.count(element);
+        }
+      }
+      DataType::Map(entries) => {
+        for (key, value) in entries {
+          self.count(key);
+          self.count(value);
+        }
+      }
+      _ => {}
+    }
+  }
+
+  /// Returns how many values of `kind` have been parsed.
+  pub fn get(&self, kind: DataTypeKind) -> u64 {
+    self.counts.get(&kind).copied().unwrap_or(0)
+  }
+
+  /// Returns how many bytes have been parsed.
+  pub fn bytes(&self) -> u64 {
+    self.bytes
+  }
 }
 
 #[derive(Debug)]
-struct Parser {
+struct Parser<'a> {
   /// The current position we are looking at in `input`.
   position: usize,
-  input: Vec<u8>,
+  /// The parser only borrows `input`, so the same buffer can back several independent parses.
+  input: &'a [u8],
+  limits: ParseLimits,
+  /// Types added after this version are rejected.
+  protocol: Protocol,
+  /// Whether a "\n" without a "\r" before it terminates a line, see [parse_lenient].
+  lenient: bool,
+  /// How many arrays contain the value being parsed.
+  depth: usize,
+  /// How many array elements have been found so far.
+  total_elements: usize,
 }
 
-impl Parser {
-  fn new(input: Vec<u8>) -> Self {
-    Self { input, position: 0 }
+impl<'a> Parser<'a> {
+  fn new(input: &'a [u8]) -> Self {
+    Self::with_limits(input, ParseLimits::UNLIMITED)
+  }
+
+  fn with_limits(input: &'a [u8], limits: ParseLimits) -> Self {
+    Self {
+      input,
+      position: 0,
+      limits,
+      protocol: Protocol::Resp3,
+      lenient: false,
+      depth: 0,
+      total_elements: 0,
+    }
+  }
+
+  /// Creates a parser that starts parsing `input` at `position` instead of at the beginning.
+  fn new_at(input: &'a [u8], position: usize) -> Self {
+    Self {
+      position,
+      ..Self::new(input)
+    }
   }
 
   fn input_as_string(&self) -> String {
-    String::from_utf8_lossy(&self.input).to_string()
+    String::from_utf8_lossy(self.input).to_string()
   }
 
   /// Advances the current position by 1.
@@ -83,11 +400,16 @@ impl Parser {
     self.position < self.input.len() - 1
   }
 
-  /// Returns true when `position` points to the start of a termination: "\r\n"
+  /// Returns true when `position` points to the start of a termination: "\r\n",
+  /// or "\n" when the parser is lenient.
   fn is_at_crlf(&self) -> bool {
+    if self.lenient && self.input.get(self.position) == Some(&b'\n') {
+      return true;
+    }
+
     // "\r\n" occupies two bytes, if we don't have two bytes to look at,
-    // we know we aren't at a termination.
-    if self.position > self.input.len() - 2 {
+    // we know we aren't at a termination, even if the last byte is "\r".
+    if self.position + 2 > self.input.len() {
       return false;
     }
 
@@ -104,8 +426,10 @@ impl Parser {
         span: (self.position, 2).into(),
       })
     } else {
-      // Skip "\r".
-      self.skip();
+      // Skip "\r", which may be missing when the parser is lenient.
+      if self.input[self.position] == b'\r' {
+        self.skip();
+      }
       // Skip "\n".
       self.skip();
 
@@ -119,13 +443,33 @@ impl Parser {
         src: self.input_as_string(),
         span: (self.position, 1).into(),
       }),
+      Some(byte) if self.protocol == Protocol::Resp2 && resp3_type_name(byte).is_some() => {
+        Err(ParserError::ProtocolMismatch {
+          src: self.input_as_string(),
+          span: (self.position - 1, 1).into(),
+          type_name: resp3_type_name(byte).unwrap_or_default().to_string(),
+        })
+      }
       Some(byte) => match byte {
         b'+' => self.simple_string(),
         b'$' => self.bulk_string_or_null(),
         b'-' => self.error(),
         b':' => self.int(),
+        b',' => self.double(),
         b'*' => self.array_or_null(),
-        _ => todo!(),
+        b'_' => self.null(),
+        b'#' => self.boolean(),
+        b'!' => self.blob_error(),
+        b'=' => self.verbatim_string(),
+        b'(' => self.big_number(),
+        b'%' => self.map(),
+        b'~' => self.set(),
+        b'>' => self.push(),
+        b'|' => self.attribute(),
+        _ => Err(ParserError::UnexpectedByte {
+          src: self.input_as_string(),
+          span: (self.position - 1, 1).into(),
+        }),
       },
     }
   }
@@ -149,27 +493,120 @@ impl Parser {
 
   /// Parses a RESP Bulk String.
   fn bulk_string_or_null(&mut self) -> Result<DataType, ParserError> {
+    Ok(match self.blob()? {
+      None => DataType::Null,
+      Some(bytes) => DataType::BulkString(bytes),
+    })
+  }
+
+  /// Parses the length prefixed bytes of a Bulk String, Blob Error or Verbatim String.
+  ///
+  /// Returns `None` when the length is -1.
+  fn blob(&mut self) -> Result<Option<Vec<u8>>, ParserError> {
+    let string_length_starts_at = self.position;
+
     let string_length = self.parse_int()?;
 
+    if string_length > 0 && string_length as u64 > self.limits.max_bulk_len as u64 {
+      return Err(ParserError::BulkLengthLimitExceeded {
+        src: self.input_as_string(),
+        span: (string_length_starts_at, string_length.to_string().len()).into(),
+        max_bulk_len: self.limits.max_bulk_len,
+      });
+    }
+
     self.consume_crlf()?;
 
     if string_length == -1 {
-      return Ok(DataType::Null);
+      return Ok(None);
     }
 
     let string_starts_at = self.position;
 
-    for _ in 0..string_length {
+    // The length comes from the input, so it can go past the end of it.
+    if string_length.max(0) as u64 > (self.input.len() - string_starts_at) as u64 {
+      return Err(ParserError::UnexpectedEndOfInput {
+        src: self.input_as_string(),
+        span: (self.input.len(), 0).into(),
+      });
+    }
+
+    self.position += string_length.max(0) as usize;
+
+    let string = self.input[string_starts_at..self.position].to_vec();
+
+    self.consume_crlf()?;
+
+    Ok(Some(string))
+  }
+
+  /// Parses a RESP3 Blob Error.
+  fn blob_error(&mut self) -> Result<DataType, ParserError> {
+    let bytes = self.blob()?.unwrap_or_default();
+
+    Ok(DataType::Error(String::from_utf8_lossy(&bytes).to_string()))
+  }
+
+  /// Parses a RESP3 Verbatim String, dropping the format that prefixes it, like "txt:".
+  fn verbatim_string(&mut self) -> Result<DataType, ParserError> {
+    let mut bytes = self.blob()?.unwrap_or_default();
+
+    if bytes.get(3) == Some(&b':') {
+      bytes.drain(..4);
+    }
+
+    Ok(DataType::BulkString(bytes))
+  }
+
+  /// Parses a RESP3 Null.
+  fn null(&mut self) -> Result<DataType, ParserError> {
+    self.consume_crlf()?;
+
+    Ok(DataType::Null)
+  }
+
+  /// Parses a RESP3 Boolean.
+  fn boolean(&mut self) -> Result<DataType, ParserError> {
+    let boolean = match self.next_byte() {
+      Some(b't') => true,
+      Some(b'f') => false,
+      _ => {
+        return Err(ParserError::UnexpectedValue {
+          src: self.input_as_string(),
+          span: (self.position - 1, 1).into(),
+          message: String::from("expected t or f"),
+        })
+      }
+    };
+
+    self.consume_crlf()?;
+
+    Ok(DataType::Boolean(boolean))
+  }
+
+  /// Parses a RESP3 Big Number.
+  fn big_number(&mut self) -> Result<DataType, ParserError> {
+    let number_starts_at = self.position;
+
+    while self.has_bytes_to_parse() && !self.is_at_crlf() {
       self.skip();
     }
 
-    let string = DataType::BulkString(
-      String::from_utf8_lossy(&self.input[string_starts_at..self.position]).to_string(),
-    );
+    let lexeme = String::from_utf8_lossy(&self.input[number_starts_at..self.position]).to_string();
+
+    let digits = lexeme.strip_prefix('-').unwrap_or(&lexeme);
+
+    if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+      return Err(ParserError::UnexpectedType {
+        src: self.input_as_string(),
+        span: (number_starts_at, lexeme.len()).into(),
+        message: String::from("expected big number"),
+      });
+    }
 
     self.consume_crlf()?;
 
-    Ok(string)
+    Ok(DataType::BigNumber(lexeme))
   }
 
   /// Parses a RESP Error.
@@ -197,7 +634,8 @@ impl Parser {
       self.skip();
     }
 
-    let lexeme = String::from_utf8_lossy(&self.input[int_starts_at..self.position]).to_string();
+    // Borrowed unless the bytes are not valid UTF-8, in which case they are not an integer anyway.
+    let lexeme = String::from_utf8_lossy(&self.input[int_starts_at..self.position]);
 
     match lexeme.parse::<i64>() {
       Err(_) => Err(ParserError::UnexpectedType {
@@ -218,92 +656,1143 @@ impl Parser {
     Ok(DataType::Int(int))
   }
 
+  /// Parses a RESP3 Double.
+  fn double(&mut self) -> Result<DataType, ParserError> {
+    let double_starts_at = self.position;
+
+    while self.has_bytes_to_parse() && !self.is_at_crlf() {
+      self.skip();
+    }
+
+    let lexeme = String::from_utf8_lossy(&self.input[double_starts_at..self.position]).to_string();
+
+    let double = match lexeme.parse::<f64>() {
+      Err(_) => {
+        return Err(ParserError::UnexpectedType {
+          src: self.input_as_string(),
+          span: (double_starts_at, lexeme.len()).into(),
+          message: String::from("expected double"),
+        })
+      }
+      Ok(double) => double,
+    };
+
+    self.consume_crlf()?;
+
+    Ok(DataType::Double(double))
+  }
+
   /// Parses a RESP Array or Null.
   fn array_or_null(&mut self) -> Result<DataType, ParserError> {
-    let array_length_starts_at = self.position;
+    Ok(match self.aggregate_length(1)? {
+      None => DataType::Null,
+      Some(length) => DataType::Array(self.elements(length)?),
+    })
+  }
+
+  /// Parses a RESP3 Set.
+  fn set(&mut self) -> Result<DataType, ParserError> {
+    let length = self.aggregate_length(1)?.unwrap_or_default();
+
+    Ok(DataType::Set(self.elements(length)?))
+  }
+
+  /// Parses a RESP3 Push.
+  fn push(&mut self) -> Result<DataType, ParserError> {
+    let length = self.aggregate_length(1)?.unwrap_or_default();
+
+    Ok(DataType::Push(self.elements(length)?))
+  }
+
+  /// Parses a RESP3 Map.
+  fn map(&mut self) -> Result<DataType, ParserError> {
+    let length = self.aggregate_length(2)?.unwrap_or_default();
+
+    let mut elements = self.elements(length * 2)?.into_iter();
+
+    let mut entries = Vec::with_capacity(length);
+
+    while let (Some(key), Some(value)) = (elements.next(), elements.next()) {
+      entries.push((key, value));
+    }
+
+    Ok(DataType::Map(entries))
+  }
 
-    let array_length = self.parse_int()?;
+  /// Parses a RESP3 Attribute and returns the value that follows it,
+  /// since attributes only carry auxiliary data about that value.
+  fn attribute(&mut self) -> Result<DataType, ParserError> {
+    let length = self.aggregate_length(2)?.unwrap_or_default();
+
+    self.elements(length * 2)?;
+
+    self.data_type()
+  }
+
+  /// Parses the length of an Array, Map, Set, Push or Attribute,
+  /// checking that its `elements_per_entry * length` elements are within the limits.
+  ///
+  /// Returns `None` when the length is -1.
+  fn aggregate_length(&mut self, elements_per_entry: usize) -> Result<Option<usize>, ParserError> {
+    let length_starts_at = self.position;
+
+    let length = self.parse_int()?;
 
     self.consume_crlf()?;
 
-    if array_length == -1 {
-      return Ok(DataType::Null);
+    if length == -1 {
+      return Ok(None);
     }
 
-    if array_length < 0 {
+    if length < 0 {
       return Err(ParserError::UnexpectedValue {
         src: self.input_as_string(),
-        span: (array_length_starts_at, array_length.to_string().len()).into(),
+        span: (length_starts_at, length.to_string().len()).into(),
         message: String::from("expected integer greater than or equal to -1"),
       });
     }
 
-    let mut elements = Vec::with_capacity(array_length as usize);
+    let span = (length_starts_at, length.to_string().len()).into();
+
+    if self.depth >= self.limits.max_depth {
+      return Err(ParserError::DepthLimitExceeded {
+        src: self.input_as_string(),
+        span,
+        max_depth: self.limits.max_depth,
+      });
+    }
+
+    if length as u64 > self.limits.max_array_len as u64 {
+      return Err(ParserError::ArrayLengthLimitExceeded {
+        src: self.input_as_string(),
+        span,
+        max_array_len: self.limits.max_array_len,
+      });
+    }
+
+    self.total_elements = self
+      .total_elements
+      .saturating_add((length as usize).saturating_mul(elements_per_entry));
+
+    if self.total_elements > self.limits.max_total_elements {
+      return Err(ParserError::ElementLimitExceeded {
+        src: self.input_as_string(),
+        span,
+        max_total_elements: self.limits.max_total_elements,
+      });
+    }
+
+    Ok(Some(length as usize))
+  }
+
+  /// Parses `count` values nested inside an aggregate.
+  fn elements(&mut self, count: usize) -> Result<Vec<DataType>, ParserError> {
+    let mut elements = Vec::with_capacity(count);
 
-    for _ in 0..array_length as usize {
+    self.depth += 1;
+
+    for _ in 0..count {
       elements.push(self.data_type()?);
     }
 
-    Ok(DataType::Array(elements))
+    self.depth -= 1;
+
+    Ok(elements)
   }
 }
 
 pub fn parse(input: Vec<u8>) -> Result<DataType, ParserError> {
-  Parser::new(input).data_type()
+  Parser::new(&input).data_type()
 }
 
-#[derive(Debug, PartialEq, Diagnostic, Error)]
-pub enum EncodeError {
-  #[error(transparent)]
-  Fmt(std::fmt::Error),
+/// Parses `input` like [parse] does, except that errors show the bytes around
+/// the error as a hex dump instead of as text.
+///
+/// Useful when `input` is binary, since bytes that are not valid UTF-8
+/// would be replaced when shown as text, making the error hard to locate.
+///
+/// # Examples
+///
+/// Parsing "$3\r\n\xff\x00\xff\x00\r\n", where the Bulk String has one more byte than its length says:
+///
+/// ```terminal
+///   × unexpected byte sequence
+///    ╭────
+///  1 │ 00000000  24 33 0d 0a ff 00 ff 00  0d 0a                    |$3........|
+///    ·                                ───┬──
+///    ·                                   ╰── here
+///    ╰────
+/// ```
+pub fn parse_with_hex_dump(input: Vec<u8>) -> Result<DataType, ParserError> {
+  let mut parser = Parser::new(&input);
+
+  parser.data_type().map_err(|err| err.with_hex_dump(&input))
 }
 
-pub fn encode(input: &str) -> Result<String, EncodeError> {
-  let mut buffer = String::new();
+/// Parses `input` like [parse] does, except that lines can be terminated by "\n" instead of "\r\n".
+///
+/// Servers always terminate lines with "\r\n", this is meant for values written by hand,
+/// like test fixtures, or encoded with [LineEnding::Lf].
+pub fn parse_lenient(input: Vec<u8>) -> Result<DataType, ParserError> {
+  let mut parser = Parser::new(&input);
+  parser.lenient = true;
+  parser.data_type()
+}
 
-  let pieces: Vec<&str> = input
-    .split(' ')
-    .filter(|piece| !piece.is_empty() && *piece != " ")
-    .collect();
+/// Parses the value that starts at `offset` in `input`.
+///
+/// Returns the value and the offset right after it, where the next value would start,
+/// so a sequence of values can be parsed by advancing the offset.
+///
+/// `input` is only borrowed, so parses at different offsets of the same buffer
+/// don't depend on each other and don't copy it.
+///
+/// # Examples
+///
+/// ```terminal
+/// parse_at(b"+OK\r\n:1\r\n", 0) -> Ok((DataType::SimpleString("OK"), 5))
+/// parse_at(b"+OK\r\n:1\r\n", 5) -> Ok((DataType::Int(1), 9))
+/// ```
+pub fn parse_at(input: &[u8], offset: usize) -> Result<(DataType, usize), ParserError> {
+  let mut parser = Parser::new_at(input, offset);
 
-  // If we have a command with arguments, like LLEN mylist
-  // the command is encoded as an RESP array.
-  if pieces.len() > 1 {
-    write!(&mut buffer, "*{}\r\n", pieces.len()).map_err(EncodeError::Fmt)?;
-  }
+  let value = parser.data_type()?;
 
-  for piece in pieces {
-    if piece.chars().next().unwrap().is_digit(10) {
-      write!(&mut buffer, ":{}\r\n", piece).map_err(EncodeError::Fmt)?;
-    } else {
-      write!(&mut buffer, "${}\r\n{}\r\n", piece.len(), piece).map_err(EncodeError::Fmt)?;
-    }
+  Ok((value, parser.position))
+}
+
+/// Parses `input` like [parse] does, returning [ParserError::TrailingBytes]
+/// if there are any bytes after the value, where [parse] ignores them.
+///
+/// Useful to check that a buffer holds exactly one value, like in tests of framing code.
+///
+/// # Examples
+///
+/// ```terminal
+/// parse_exact(b"+OK\r\n")       -> Ok(DataType::SimpleString("OK"))
+/// parse_exact(b"+OK\r\njunk")   -> Err(TrailingBytes { position: 5 })
+/// ```
+pub fn parse_exact(input: &[u8]) -> Result<DataType, ParserError> {
+  let (value, position) = parse_at(input, 0)?;
+
+  if position < input.len() {
+    return Err(ParserError::TrailingBytes {
+      src: String::from_utf8_lossy(input).to_string(),
+      span: (position, input.len() - position).into(),
+      position,
+    });
   }
 
-  Ok(buffer)
+  Ok(value)
 }
 
-#[cfg(test)]
-mod tests {
-  use super::*;
+/// Parses `input` like [parse] does, returning a [ParserError::ProtocolMismatch]
+/// if it contains a type that did not exist in `protocol`.
+///
+/// [parse] accepts every RESP3 type, use this to flag a server that sends RESP3 types
+/// when the connection was expected to speak RESP2.
+///
+/// # Examples
+///
+/// ```terminal
+/// parse_with_protocol(b"%1\r\n+a\r\n:1\r\n", Protocol::Resp2) -> Err(ProtocolMismatch { type_name: "map" })
+/// parse_with_protocol(b"%1\r\n+a\r\n:1\r\n", Protocol::Resp3) -> Ok(DataType::Map(..))
+/// ```
+pub fn parse_with_protocol(input: &[u8], protocol: Protocol) -> Result<DataType, ParserError> {
+  let mut parser = Parser::new(input);
+  parser.protocol = protocol;
+  parser.data_type()
+}
 
-  fn bytes(s: &str) -> Vec<u8> {
-    s.as_bytes().to_vec()
+/// Parses `input` like [parse] does, returning [ParserError::UnexpectedType]
+/// if the value is not of the `expected` kind.
+///
+/// Error replies are always accepted, since any command can fail,
+/// and so is Null when a Bulk String or an Array is expected, since that's how RESP2 represents them.
+///
+/// # Examples
+///
+/// ```terminal
+/// parse_expecting(b":1\r\n", DataTypeKind::Int)         -> Ok(DataType::Int(1))
+/// parse_expecting(b"-ERR\r\n", DataTypeKind::Int)       -> Ok(DataType::Error("ERR"))
+/// parse_expecting(b"$1\r\n1\r\n", DataTypeKind::Int)    -> Err(UnexpectedType("expected integer, found bulk string"))
+/// ```
+pub fn parse_expecting(input: &[u8], expected: DataTypeKind) -> Result<DataType, ParserError> {
+  let value = parse(input.to_vec())?;
+
+  let actual = value.kind();
+
+  let allowed = actual == expected
+    || actual == DataTypeKind::Error
+    || (actual == DataTypeKind::Null
+      && matches!(expected, DataTypeKind::BulkString | DataTypeKind::Array));
+
+  if !allowed {
+    return Err(ParserError::UnexpectedType {
+      src: String::from_utf8_lossy(input).to_string(),
+      span: (0, 1).into(),
+      message: format!("expected {}, found {}", expected, actual),
+    });
   }
 
-  #[test]
-  fn simple_string() {
-    let tests = vec![("+OK\r\n", Ok(DataType::SimpleString(String::from("OK"))))];
+  Ok(value)
+}
 
-    for (input, expected) in tests {
-      let actual = parse(bytes(input));
-      assert_eq!(expected, actual);
+/// Parses `input` like [parse] does, returning an error as soon as any of the `limits` is exceeded.
+///
+/// Use it to parse input that can't be trusted, since the lengths in the input
+/// are checked before any memory is allocated for the value.
+pub fn parse_with_limits(input: Vec<u8>, limits: ParseLimits) -> Result<DataType, ParserError> {
+  Parser::with_limits(&input, limits).data_type()
+}
+
+/// Parses the inline command at the start of `input`, the space separated arguments
+/// that a user types in telnet, into the Array of Bulk Strings a client would have sent.
+///
+/// Returns the command and how many bytes it takes, or `None` if more bytes are needed to complete it.
+/// Since there's no length to check before buffering the command, it fails once more than
+/// `limits.max_inline_len` bytes are found without a "\r\n", instead of waiting for one forever.
+///
+/// Quotes are not interpreted, arguments can't contain spaces.
+///
+/// # Examples
+///
+/// ```terminal
+/// "SET key  value\r\n" -> Ok(Some((["SET", "key", "value"], 16)))
+/// "SET key"            -> Ok(None)
+/// ```
+pub fn parse_inline_command(
+  input: &[u8],
+  limits: ParseLimits,
+) -> Result<Option<(DataType, usize)>, ParserError> {
+  let line_ends_at = match find_crlf(input, 0) {
+    Some(line_ends_at) if line_ends_at <= limits.max_inline_len => line_ends_at,
+    Some(line_ends_at) => return Err(inline_command_too_long(input, line_ends_at, limits)),
+    None => {
+      // A "\r" at the end may be the start of the "\r\n".
+      let line_len = input.strip_suffix(b"\r").unwrap_or(input).len();
+
+      if line_len > limits.max_inline_len {
+        return Err(inline_command_too_long(input, line_len, limits));
+      }
+
+      return Ok(None);
     }
+  };
+
+  let args = input[..line_ends_at]
+    .split(|byte| *byte == b' ' || *byte == b'\t')
+    .filter(|arg| !arg.is_empty())
+    .map(|arg| DataType::BulkString(arg.to_vec()))
+    .collect();
+
+  Ok(Some((DataType::Array(args), line_ends_at + 2)))
+}
+
+/// Returns the error for an inline command that takes `line_len` bytes, pointing at the bytes past the limit.
+fn inline_command_too_long(input: &[u8], line_len: usize, limits: ParseLimits) -> ParserError {
+  ParserError::InlineCommandTooLong {
+    src: String::from_utf8_lossy(input).to_string(),
+    span: (limits.max_inline_len, line_len - limits.max_inline_len).into(),
+    max_inline_len: limits.max_inline_len,
   }
+}
 
-  #[test]
-  fn error() {
-    let tests = vec![(
+/// Returns true if `input` starts with a complete RESP value,
+/// false if more bytes are needed to complete it
+/// or an error if `input` is not valid RESP.
+///
+/// The value is not parsed, this is a cheap check to know when enough bytes
+/// have been read from a stream.
+///
+/// # Examples
+///
+/// ```terminal
+/// "$6\r\nfoobar\r\n" -> Ok(true)
+/// "$6\r\nfoo"        -> Ok(false)
+/// "?6\r\nfoobar\r\n" -> Err(UnexpectedByte)
+/// ```
+pub fn is_complete(input: &[u8]) -> Result<bool, ParserError> {
+  Ok(value_end(input, 0)?.is_some())
+}
+
+/// Returns how many bytes the value at the start of `input` takes,
+/// or `None` if more bytes are needed to complete it.
+pub(crate) fn complete_len(input: &[u8]) -> Result<Option<usize>, ParserError> {
+  value_end(input, 0)
+}
+
+/// Returns the position right after the value that starts at `position`,
+/// or `None` if `input` ends before the value does.
+///
+/// Nested values are walked without recursing, since the input comes from the other end
+/// of a connection and can be nested deeper than the call stack allows.
+fn value_end(input: &[u8], position: usize) -> Result<Option<usize>, ParserError> {
+  // How many values are still missing from each aggregate the walk is in, innermost last.
+  let mut missing: Vec<usize> = Vec::new();
+  let mut position = position;
+
+  loop {
+    let nested;
+
+    (position, nested) = match value_head_end(input, position)? {
+      None => return Ok(None),
+      Some(head) => head,
+    };
+
+    if nested > 0 {
+      missing.push(nested);
+      continue;
+    }
+
+    // The value is complete, which may complete the aggregates it's the last value of.
+    loop {
+      match missing.last_mut() {
+        None => return Ok(Some(position)),
+        Some(1) => {
+          missing.pop();
+        }
+        Some(count) => {
+          *count -= 1;
+          break;
+        }
+      }
+    }
+  }
+}
+
+/// Returns the position right after the value that starts at `position`, without the values
+/// nested in it, and how many values are nested in it,
+/// or `None` if `input` ends before that part of the value does.
+fn value_head_end(input: &[u8], position: usize) -> Result<Option<(usize, usize)>, ParserError> {
+  let type_byte = match input.get(position) {
+    None => return Ok(None),
+    Some(byte) => *byte,
+  };
+
+  // Checked before looking for the end of the line, otherwise garbage without "\r\n"
+  // would look like an incomplete value and keep being read forever.
+  if !matches!(
+    type_byte,
+    b'+'
+      | b'-'
+      | b':'
+      | b','
+      | b'_'
+      | b'#'
+      | b'('
+      | b'$'
+      | b'!'
+      | b'='
+      | b'*'
+      | b'~'
+      | b'>'
+      | b'%'
+      | b'|'
+  ) {
+    return Err(ParserError::UnexpectedByte {
+      src: String::from_utf8_lossy(input).to_string(),
+      span: (position, 1).into(),
+    });
+  }
+
+  // Every value starts with a line terminated by "\r\n".
+  let line_end = match find_crlf(input, position + 1) {
+    None => return Ok(None),
+    Some(line_end) => line_end,
+  };
+
+  match type_byte {
+    b'+' | b'-' | b':' | b',' | b'_' | b'#' | b'(' => Ok(Some((line_end + 2, 0))),
+    b'$' | b'!' | b'=' => {
+      let length = parse_length(input, position + 1, line_end)?;
+
+      if length == -1 {
+        return Ok(Some((line_end + 2, 0)));
+      }
+
+      let string_ends_at = line_end + 2 + length as usize;
+
+      if input.len() < string_ends_at + 2 {
+        return Ok(None);
+      }
+
+      if &input[string_ends_at..string_ends_at + 2] != b"\r\n" {
+        return Err(ParserError::UnexpectedByte {
+          src: String::from_utf8_lossy(input).to_string(),
+          span: (string_ends_at, 2).into(),
+        });
+      }
+
+      Ok(Some((string_ends_at + 2, 0)))
+    }
+    b'*' | b'~' | b'>' | b'%' | b'|' => {
+      let length = parse_length(input, position + 1, line_end)?;
+
+      let elements = length.max(0) as usize;
+
+      // Maps and Attributes have a key and a value for every entry,
+      // and an Attribute is followed by the value it describes.
+      let nested = match type_byte {
+        b'%' => elements.saturating_mul(2),
+        b'|' => elements.saturating_mul(2).saturating_add(1),
+        _ => elements,
+      };
+
+      Ok(Some((line_end + 2, nested)))
+    }
+    _ => unreachable!("the type byte was checked above"),
+  }
+}
+
+/// An error reading a value with [parse_from_reader].
+#[derive(Debug, Diagnostic, Error)]
+pub enum ReadError {
+  #[error("reading the value failed: {0}")]
+  #[diagnostic()]
+  Io(#[from] io::Error),
+  #[error(transparent)]
+  #[diagnostic(transparent)]
+  Parser(#[from] ParserError),
+}
+
+/// Reads one value from `reader` and parses it, without reading any byte after the value,
+/// so the next value can be read from `reader` afterwards.
+///
+/// Lines are read one byte at a time, since bytes read past the end of the value could not
+/// be given back to `reader`. Wrap readers like sockets in a [std::io::BufReader]
+/// and keep reading from it, so the bytes it buffers after a value are not lost.
+///
+/// Returns [ReadError::Io] with [io::ErrorKind::UnexpectedEof] if `reader` ends before the value does.
+///
+/// # Examples
+///
+/// ```terminal
+/// let mut reader = Cursor::new(b"+OK\r\n:1\r\n");
+///
+/// parse_from_reader(&mut reader)   -> Ok(DataType::SimpleString("OK"))
+/// parse_from_reader(&mut reader)   -> Ok(DataType::Int(1))
+/// ```
+pub fn parse_from_reader<R: io::Read>(reader: &mut R) -> Result<DataType, ReadError> {
+  let mut value = Vec::new();
+
+  read_value(reader, &mut value)?;
+
+  Ok(Parser::new(&value).data_type()?)
+}
+
+/// Reads the bytes of the next value of `reader` and appends them to `buffer`,
+/// using the lengths the value carries to know where it ends.
+fn read_value<R: io::Read>(reader: &mut R, buffer: &mut Vec<u8>) -> Result<(), ReadError> {
+  let position = buffer.len();
+
+  let mut byte = [0];
+  reader.read_exact(&mut byte)?;
+  buffer.push(byte[0]);
+
+  if !matches!(
+    byte[0],
+    b'+'
+      | b'-'
+      | b':'
+      | b','
+      | b'_'
+      | b'#'
+      | b'('
+      | b'$'
+      | b'!'
+      | b'='
+      | b'*'
+      | b'~'
+      | b'>'
+      | b'%'
+      | b'|'
+  ) {
+    return Err(
+      ParserError::UnexpectedByte {
+        src: String::from_utf8_lossy(buffer).to_string(),
+        span: (position, 1).into(),
+      }
+      .into(),
+    );
+  }
+
+  // Every value starts with a line terminated by "\r\n".
+  while buffer.len() < position + 3 || !buffer.ends_with(b"\r\n") {
+    reader.read_exact(&mut byte)?;
+    buffer.push(byte[0]);
+  }
+
+  let line_end = buffer.len() - 2;
+
+  match buffer[position] {
+    b'$' | b'!' | b'=' => {
+      let length = parse_length(buffer, position + 1, line_end)?;
+
+      if length == -1 {
+        return Ok(());
+      }
+
+      // The string and the "\r\n" after it. Reading with take, instead of allocating
+      // the whole length up front, stops a bogus length from allocating more than is received.
+      let expected = length as u64 + 2;
+
+      let bytes_read = reader.by_ref().take(expected).read_to_end(buffer)?;
+
+      if bytes_read as u64 != expected {
+        return Err(ReadError::Io(io::ErrorKind::UnexpectedEof.into()));
+      }
+
+      Ok(())
+    }
+    type_byte @ (b'*' | b'~' | b'>' | b'%' | b'|') => {
+      let length = parse_length(buffer, position + 1, line_end)?;
+
+      // Maps and Attributes have a key and a value for every entry.
+      let elements = match type_byte {
+        b'%' | b'|' => length.max(0) * 2,
+        _ => length.max(0),
+      };
+
+      for _ in 0..elements {
+        read_value(reader, buffer)?;
+      }
+
+      // An Attribute is followed by the value it describes.
+      if type_byte == b'|' {
+        read_value(reader, buffer)?;
+      }
+
+      Ok(())
+    }
+    _ => Ok(()),
+  }
+}
+
+/// A piece of RESP found by [parse_iter].
+///
+/// Strings borrow their bytes from the input instead of copying them,
+/// so values that are skipped never allocate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event<'a> {
+  SimpleString(&'a [u8]),
+  /// An Error or a RESP3 Blob Error.
+  Error(&'a [u8]),
+  Int(i64),
+  Double(f64),
+  /// A Bulk String or a RESP3 Verbatim String, without its format.
+  BulkStringRef(&'a [u8]),
+  Boolean(bool),
+  BigNumber(&'a [u8]),
+  Null,
+  /// The start of an Array, followed by the events of its `len` elements.
+  ArrayStart(usize),
+  /// The start of a Map, followed by the events of its `len` keys and values, in pairs.
+  MapStart(usize),
+  /// The start of a Set, followed by the events of its `len` elements.
+  SetStart(usize),
+  /// The start of a Push, followed by the events of its `len` elements.
+  PushStart(usize),
+  /// The start of an Attribute, followed by the events of its `len` keys and values, in pairs,
+  /// and then by the events of the value it describes.
+  AttributeStart(usize),
+}
+
+/// Returns an iterator over the [Event]s of the values in `input`, in the order they appear.
+///
+/// Unlike [parse], which builds a [DataType] owning copies of the strings,
+/// events borrow from `input`, so a consumer can look at the values
+/// it's interested in and skip the rest without allocating.
+///
+/// The iterator stops after the first error.
+///
+/// # Examples
+///
+/// ```terminal
+/// "*2\r\n$3\r\nfoo\r\n:1\r\n" -> [ArrayStart(2), BulkStringRef(b"foo"), Int(1)]
+/// ```
+pub fn parse_iter(input: &[u8]) -> ParseIter<'_> {
+  ParseIter {
+    input,
+    position: 0,
+    failed: false,
+  }
+}
+
+/// The iterator returned by [parse_iter].
+#[derive(Debug, Clone)]
+pub struct ParseIter<'a> {
+  input: &'a [u8],
+  /// Where the next event starts in `input`.
+  position: usize,
+  /// Whether an error was returned, after which no more events are returned.
+  failed: bool,
+}
+
+impl<'a> Iterator for ParseIter<'a> {
+  type Item = Result<Event<'a>, ParserError>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.failed || self.position >= self.input.len() {
+      return None;
+    }
+
+    let event = self.event();
+
+    self.failed = event.is_err();
+
+    Some(event)
+  }
+}
+
+impl<'a> ParseIter<'a> {
+  /// Returns where the next event starts in the input,
+  /// which is how many bytes the events returned so far took.
+  pub fn position(&self) -> usize {
+    self.position
+  }
+
+  fn event(&mut self) -> Result<Event<'a>, ParserError> {
+    let input = self.input;
+    let type_position = self.position;
+    let type_byte = input[type_position];
+
+    let line_end = match find_crlf(input, type_position + 1) {
+      None => return Err(self.end_of_input()),
+      Some(line_end) => line_end,
+    };
+
+    let line = &input[type_position + 1..line_end];
+
+    self.position = line_end + 2;
+
+    match type_byte {
+      b'+' => Ok(Event::SimpleString(line)),
+      b'-' => Ok(Event::Error(line)),
+      b':' => match std::str::from_utf8(line)
+        .ok()
+        .and_then(|line| line.parse().ok())
+      {
+        Some(int) => Ok(Event::Int(int)),
+        None => Err(self.unexpected_type(type_position + 1, line.len(), "expected integer")),
+      },
+      b',' => match std::str::from_utf8(line)
+        .ok()
+        .and_then(|line| line.parse().ok())
+      {
+        Some(double) => Ok(Event::Double(double)),
+        None => Err(self.unexpected_type(type_position + 1, line.len(), "expected double")),
+      },
+      b'#' => match line {
+        b"t" => Ok(Event::Boolean(true)),
+        b"f" => Ok(Event::Boolean(false)),
+        _ => Err(ParserError::UnexpectedValue {
+          src: String::from_utf8_lossy(input).to_string(),
+          span: (type_position + 1, line.len()).into(),
+          message: String::from("expected t or f"),
+        }),
+      },
+      b'(' => Ok(Event::BigNumber(line)),
+      b'_' => Ok(Event::Null),
+      b'$' | b'!' | b'=' => {
+        let length = parse_length(input, type_position + 1, line_end)?;
+
+        if length == -1 {
+          return Ok(Event::Null);
+        }
+
+        let string_starts_at = self.position;
+        let string_ends_at = string_starts_at + length as usize;
+
+        if input.len() < string_ends_at + 2 {
+          return Err(self.end_of_input());
+        }
+
+        if &input[string_ends_at..string_ends_at + 2] != b"\r\n" {
+          return Err(ParserError::UnexpectedByte {
+            src: String::from_utf8_lossy(input).to_string(),
+            span: (string_ends_at, 2).into(),
+          });
+        }
+
+        self.position = string_ends_at + 2;
+
+        let string = &input[string_starts_at..string_ends_at];
+
+        match type_byte {
+          b'!' => Ok(Event::Error(string)),
+          b'=' if string.get(3) == Some(&b':') => Ok(Event::BulkStringRef(&string[4..])),
+          _ => Ok(Event::BulkStringRef(string)),
+        }
+      }
+      b'*' | b'%' | b'~' | b'>' | b'|' => {
+        let length = parse_length(input, type_position + 1, line_end)?;
+
+        if length == -1 {
+          return Ok(Event::Null);
+        }
+
+        let length = length as usize;
+
+        Ok(match type_byte {
+          b'*' => Event::ArrayStart(length),
+          b'%' => Event::MapStart(length),
+          b'~' => Event::SetStart(length),
+          b'>' => Event::PushStart(length),
+          _ => Event::AttributeStart(length),
+        })
+      }
+      _ => Err(ParserError::UnexpectedByte {
+        src: String::from_utf8_lossy(input).to_string(),
+        span: (type_position, 1).into(),
+      }),
+    }
+  }
+
+  fn end_of_input(&self) -> ParserError {
+    ParserError::UnexpectedEndOfInput {
+      src: String::from_utf8_lossy(self.input).to_string(),
+      span: (self.input.len(), 0).into(),
+    }
+  }
+
+  fn unexpected_type(&self, position: usize, len: usize, message: &str) -> ParserError {
+    ParserError::UnexpectedType {
+      src: String::from_utf8_lossy(self.input).to_string(),
+      span: (position, len).into(),
+      message: String::from(message),
+    }
+  }
+}
+
+/// Parses an Array whose elements are all Integers, like the reply of BITFIELD, into the integers.
+///
+/// Faster than [parse] since the integers go straight into the `Vec`
+/// instead of a [DataType] being built for each one,
+/// see `cargo run --release --example int_array_bench`.
+///
+/// # Examples
+///
+/// ```terminal
+/// "*3\r\n:1\r\n:-2\r\n:3\r\n"  -> Ok([1, -2, 3])
+/// "*1\r\n$1\r\n1\r\n"         -> Err(UnexpectedType)
+/// ```
+pub fn parse_int_array(input: &[u8]) -> Result<Vec<i64>, ParserError> {
+  let end_of_input = || ParserError::UnexpectedEndOfInput {
+    src: String::from_utf8_lossy(input).to_string(),
+    span: (input.len(), 0).into(),
+  };
+
+  let unexpected_type = |position: usize, len: usize, message: &str| ParserError::UnexpectedType {
+    src: String::from_utf8_lossy(input).to_string(),
+    span: (position, len).into(),
+    message: String::from(message),
+  };
+
+  match input.first() {
+    None => return Err(end_of_input()),
+    Some(b'*') => {}
+    Some(_) => return Err(unexpected_type(0, 1, "expected array")),
+  }
+
+  let line_end = find_crlf(input, 1).ok_or_else(end_of_input)?;
+
+  let length = match parse_length(input, 1, line_end)? {
+    -1 => return Err(unexpected_type(0, line_end, "expected array, found null")),
+    length => length as usize,
+  };
+
+  // Every Integer takes at least 4 bytes, like ":1\r\n", so a length that is too large
+  // for the input can't make us allocate more than the input needs.
+  let mut ints = Vec::with_capacity(length.min(input.len() / 4));
+
+  let mut position = line_end + 2;
+
+  for _ in 0..length {
+    match input.get(position) {
+      None => return Err(end_of_input()),
+      Some(b':') => {}
+      Some(_) => return Err(unexpected_type(position, 1, "expected integer")),
+    }
+
+    let line_end = find_crlf(input, position + 1).ok_or_else(end_of_input)?;
+
+    let int = std::str::from_utf8(&input[position + 1..line_end])
+      .ok()
+      .and_then(|lexeme| lexeme.parse::<i64>().ok())
+      .ok_or_else(|| unexpected_type(position + 1, line_end - position - 1, "expected integer"))?;
+
+    ints.push(int);
+
+    position = line_end + 2;
+  }
+
+  Ok(ints)
+}
+
+/// Returns the position of the first "\r\n" found at or after `position`.
+fn find_crlf(input: &[u8], position: usize) -> Option<usize> {
+  input
+    .get(position..)?
+    .windows(2)
+    .position(|window| window == b"\r\n")
+    .map(|offset| position + offset)
+}
+
+/// Parses the length of a Bulk String or Array found in `input[starts_at..ends_at]`.
+///
+/// The length must be greater than or equal to -1.
+fn parse_length(input: &[u8], starts_at: usize, ends_at: usize) -> Result<i64, ParserError> {
+  // Parsed without copying the lexeme, since this runs for every string and aggregate.
+  let length = std::str::from_utf8(&input[starts_at..ends_at])
+    .ok()
+    .and_then(|lexeme| lexeme.parse::<i64>().ok());
+
+  match length {
+    None => Err(ParserError::UnexpectedType {
+      src: String::from_utf8_lossy(input).to_string(),
+      span: (starts_at, ends_at - starts_at).into(),
+      message: String::from("expected integer"),
+    }),
+    Some(length) if length < -1 => Err(ParserError::UnexpectedValue {
+      src: String::from_utf8_lossy(input).to_string(),
+      span: (starts_at, ends_at - starts_at).into(),
+      message: String::from("expected integer greater than or equal to -1"),
+    }),
+    Some(length) => Ok(length),
+  }
+}
+
+/// Encodes a RESP value into the bytes that represent it on the wire.
+///
+/// # Examples
+///
+/// ```terminal
+/// DataType::Int(1000)                         -> ":1000\r\n"
+/// DataType::BulkString(b"foobar".to_vec())    -> "$6\r\nfoobar\r\n"
+/// DataType::Array(vec![DataType::Null])       -> "*1\r\n$-1\r\n"
+/// ```
+pub fn encode_value(value: &DataType) -> Vec<u8> {
+  encode_value_with_line_ending(value, LineEnding::Crlf)
+}
+
+/// What terminates the lines of an encoded value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+  /// "\r\n", what the protocol requires.
+  Crlf,
+  /// "\n", easier to read in logs and test fixtures.
+  ///
+  /// Values encoded with it are NOT valid RESP and servers won't understand them,
+  /// they can only be parsed back with [parse_lenient].
+  Lf,
+}
+
+impl LineEnding {
+  fn as_str(&self) -> &'static str {
+    match self {
+      LineEnding::Crlf => "\r\n",
+      LineEnding::Lf => "\n",
+    }
+  }
+}
+
+/// Encodes a RESP value like [encode_value] does, terminating lines with `line_ending`.
+///
+/// # Examples
+///
+/// ```terminal
+/// DataType::Array(vec![DataType::Int(1)]), LineEnding::Lf     -> "*1\n:1\n"
+/// ```
+pub fn encode_value_with_line_ending(value: &DataType, line_ending: LineEnding) -> Vec<u8> {
+  let mut buffer = Vec::new();
+  encode_to_writer_with_line_ending(value, &mut buffer, line_ending)
+    .expect("writing to a Vec never fails");
+  buffer
+}
+
+/// Encodes a RESP value like [encode_value] does, writing the bytes to `writer` as they are produced.
+///
+/// The bytes of Bulk Strings are written straight from the value, so encoding
+/// a Bulk String of hundreds of megabytes doesn't need another buffer of the same size.
+///
+/// Values are written in small pieces, consider wrapping `writer` in a [std::io::BufWriter].
+pub fn encode_to_writer<W: io::Write>(value: &DataType, writer: &mut W) -> io::Result<()> {
+  encode_to_writer_with_line_ending(value, writer, LineEnding::Crlf)
+}
+
+fn encode_to_writer_with_line_ending<W: io::Write>(
+  value: &DataType,
+  writer: &mut W,
+  line_ending: LineEnding,
+) -> io::Result<()> {
+  let end = line_ending.as_str();
+
+  match value {
+    DataType::SimpleString(string) => write!(writer, "+{}{}", string, end),
+    DataType::Error(message) => write!(writer, "-{}{}", message, end),
+    DataType::Int(int) => write!(writer, ":{}{}", int, end),
+    DataType::Double(double) if double.is_nan() => write!(writer, ",nan{}", end),
+    DataType::Double(double) => write!(writer, ",{}{}", double, end),
+    DataType::BulkString(bytes) => {
+      write!(writer, "${}{}", bytes.len(), end)?;
+      writer.write_all(bytes)?;
+      writer.write_all(end.as_bytes())
+    }
+    DataType::Array(elements) => encode_aggregate_to_writer(b'*', elements, writer, line_ending),
+    DataType::Set(elements) => encode_aggregate_to_writer(b'~', elements, writer, line_ending),
+    DataType::Push(elements) => encode_aggregate_to_writer(b'>', elements, writer, line_ending),
+    DataType::Map(entries) => {
+      write!(writer, "%{}{}", entries.len(), end)?;
+
+      for (key, value) in entries {
+        encode_to_writer_with_line_ending(key, writer, line_ending)?;
+        encode_to_writer_with_line_ending(value, writer, line_ending)?;
+      }
+
+      Ok(())
+    }
+    DataType::Boolean(boolean) => write!(writer, "#{}{}", if *boolean { 't' } else { 'f' }, end),
+    DataType::BigNumber(number) => write!(writer, "({}{}", number, end),
+    DataType::Null => write!(writer, "$-1{}", end),
+  }
+}
+
+fn encode_aggregate_to_writer<W: io::Write>(
+  type_byte: u8,
+  elements: &[DataType],
+  writer: &mut W,
+  line_ending: LineEnding,
+) -> io::Result<()> {
+  write!(
+    writer,
+    "{}{}{}",
+    type_byte as char,
+    elements.len(),
+    line_ending.as_str()
+  )?;
+
+  for element in elements {
+    encode_to_writer_with_line_ending(element, writer, line_ending)?;
+  }
+
+  Ok(())
+}
+
+#[derive(Debug, PartialEq, Diagnostic, Error)]
+pub enum EncodeError {
+  #[error(transparent)]
+  Fmt(std::fmt::Error),
+  #[error("the encoded command takes {size} bytes, more than the maximum of {max_size} bytes")]
+  #[diagnostic(help("use encode_with_max_size to encode larger commands"))]
+  CommandTooLarge { size: usize, max_size: usize },
+}
+
+/// The maximum number of bytes a command encoded with [encode] can take: 4 MB.
+pub const DEFAULT_MAX_COMMAND_SIZE: usize = 4 * 1024 * 1024;
+
+/// Encodes a command written like it would be typed in redis-cli, like "LLEN mylist".
+///
+/// Arguments are separated by spaces. The command is encoded the only way Redis accepts it,
+/// as a RESP Array with every argument, numbers included, as a Bulk String prefixed by its length,
+/// even when there are no arguments besides the command name.
+///
+/// Since every argument is length-prefixed, an argument containing "\r\n"
+/// can't be used to inject other commands, like "SET key 1\r\nFLUSHALL\r\n".
+///
+/// Prefer building commands with [crate::command::Command] when arguments come from untrusted input.
+///
+/// Returns [EncodeError::CommandTooLarge] if the encoded command would take
+/// more than [DEFAULT_MAX_COMMAND_SIZE] bytes, to catch giant values being sent by accident.
+pub fn encode(input: &str) -> Result<String, EncodeError> {
+  encode_with_max_size(input, DEFAULT_MAX_COMMAND_SIZE)
+}
+
+/// Encodes a command like [encode] does, returning [EncodeError::CommandTooLarge]
+/// if the encoded command would take more than `max_size` bytes.
+///
+/// The size is checked before anything is encoded.
+pub fn encode_with_max_size(input: &str, max_size: usize) -> Result<String, EncodeError> {
+  let pieces: Vec<&str> = input
+    .split(' ')
+    .filter(|piece| !piece.is_empty() && *piece != " ")
+    .collect();
+
+  let size = encoded_len(&pieces);
+
+  if size > max_size {
+    return Err(EncodeError::CommandTooLarge { size, max_size });
+  }
+
+  let mut buffer = String::with_capacity(size);
+
+  write!(&mut buffer, "*{}\r\n", pieces.len()).map_err(EncodeError::Fmt)?;
+
+  for piece in pieces {
+    write!(&mut buffer, "${}\r\n{}\r\n", piece.len(), piece).map_err(EncodeError::Fmt)?;
+  }
+
+  Ok(buffer)
+}
+
+/// Returns how many bytes [encode] takes to encode `pieces`.
+fn encoded_len(pieces: &[&str]) -> usize {
+  // "*{pieces}\r\n"
+  let header = pieces.len().to_string().len() + 3;
+
+  header
+    + pieces
+      .iter()
+      // "${length}\r\n{piece}\r\n"
+      .map(|piece| piece.len().to_string().len() + piece.len() + 5)
+      .sum::<usize>()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn bytes(s: &str) -> Vec<u8> {
+    s.as_bytes().to_vec()
+  }
+
+  /// Parses `input`, encodes the result and parses the encoded bytes again,
+  /// asserting that both parses produce the same value.
+  ///
+  /// The encoded bytes are not compared with `input` because the input
+  /// may not be in its canonical form, like ":+10\r\n".
+  fn assert_roundtrip(input: &[u8]) {
+    let parsed = parse(input.to_vec()).unwrap_or_else(|err| {
+      panic!(
+        "failed to parse {:?}: {:?}",
+        String::from_utf8_lossy(input),
+        err
+      )
+    });
+
+    let encoded = encode_value(&parsed);
+
+    assert_eq!(
+      Ok(parsed),
+      parse(encoded.clone()),
+      "encoded as {:?}",
+      String::from_utf8_lossy(&encoded)
+    );
+  }
+
+  #[test]
+  fn simple_string() {
+    let tests = vec![("+OK\r\n", Ok(DataType::SimpleString(String::from("OK"))))];
+
+    for (input, expected) in tests {
+      let actual = parse(bytes(input));
+      assert_eq!(expected, actual);
+    }
+  }
+
+  #[test]
+  fn error() {
+    let tests = vec![(
       "-ERR unknown command 'foobar'\r\n",
       Ok(DataType::Error(String::from(
         "ERR unknown command 'foobar'",
@@ -330,13 +1819,47 @@ mod tests {
     }
   }
 
+  #[test]
+  fn double() {
+    let tests = vec![
+      (",1.23\r\n", Ok(DataType::Double(1.23))),
+      (",-10\r\n", Ok(DataType::Double(-10.0))),
+      (",inf\r\n", Ok(DataType::Double(f64::INFINITY))),
+      (",-inf\r\n", Ok(DataType::Double(f64::NEG_INFINITY))),
+    ];
+
+    for (input, expected) in tests {
+      let actual = parse(bytes(input));
+      assert_eq!(expected, actual);
+    }
+
+    assert!(matches!(
+      parse(bytes(",nan\r\n")),
+      Ok(DataType::Double(double)) if double.is_nan()
+    ));
+  }
+
   #[test]
   fn bulk_string() {
     let tests = vec![
-      ("$0\r\n\r\n", Ok(DataType::BulkString(String::new()))),
+      ("$0\r\n\r\n", Ok(DataType::BulkString(vec![]))),
       (
         "$6\r\nfoobar\r\n",
-        Ok(DataType::BulkString(String::from("foobar"))),
+        Ok(DataType::BulkString(b"foobar".to_vec())),
+      ),
+      (
+        "$100\r\nab\r\n",
+        Err(ParserError::UnexpectedEndOfInput {
+          src: String::from("$100\r\nab\r\n"),
+          span: (10, 0).into(),
+        }),
+      ),
+      (
+        "$9223372036854775807\r\nab\r\n",
+        Err(ParserError::UnexpectedEndOfInput {
+          src: String::from("$9223372036854775807\r\nab\r\n"),
+          span: (26, 0).into(),
+        }),
       ),
     ];
 
@@ -361,7 +1884,7 @@ mod tests {
       (
         "*3\r\n$3\r\nfoo\r\n:1\r\n:2\r\n",
         Ok(DataType::Array(vec![
-          DataType::BulkString(String::from("foo")),
+          DataType::BulkString(b"foo".to_vec()),
           DataType::Int(1),
           DataType::Int(2),
         ])),
@@ -379,9 +1902,9 @@ mod tests {
       (
         "*3\r\n$3\r\nfoo\r\n$-1\r\n$3\r\nbar\r\n",
         Ok(DataType::Array(vec![
-          DataType::BulkString(String::from("foo")),
+          DataType::BulkString(b"foo".to_vec()),
           DataType::Null,
-          DataType::BulkString(String::from("bar")),
+          DataType::BulkString(b"bar".to_vec()),
         ])),
       ),
     ];
@@ -403,17 +1926,1027 @@ mod tests {
   }
 
   #[test]
-  fn test_encode() {
+  fn resp3_types() {
     let tests = vec![
-      ("LLEN mylist", "*2\r\n$4\r\nLLEN\r\n$6\r\nmylist\r\n"),
+      ("_\r\n", Ok(DataType::Null)),
+      ("#t\r\n", Ok(DataType::Boolean(true))),
+      ("#f\r\n", Ok(DataType::Boolean(false))),
       (
-        r#"SETEX mykey 10 "Hello""#,
-        "*4\r\n$5\r\nSETEX\r\n$5\r\nmykey\r\n:10\r\n$7\r\n\"Hello\"\r\n",
+        "(3492890328409238509324850943850943825024385\r\n",
+        Ok(DataType::BigNumber(String::from(
+          "3492890328409238509324850943850943825024385",
+        ))),
+      ),
+      (
+        "!21\r\nSYNTAX invalid syntax\r\n",
+        Ok(DataType::Error(String::from("SYNTAX invalid syntax"))),
+      ),
+      (
+        "=15\r\ntxt:Some string\r\n",
+        Ok(DataType::BulkString(b"Some string".to_vec())),
+      ),
+      (
+        "%2\r\n+first\r\n:1\r\n+second\r\n:2\r\n",
+        Ok(DataType::Map(vec![
+          (
+            DataType::SimpleString(String::from("first")),
+            DataType::Int(1),
+          ),
+          (
+            DataType::SimpleString(String::from("second")),
+            DataType::Int(2),
+          ),
+        ])),
+      ),
+      (
+        "~2\r\n+orange\r\n+apple\r\n",
+        Ok(DataType::Set(vec![
+          DataType::SimpleString(String::from("orange")),
+          DataType::SimpleString(String::from("apple")),
+        ])),
+      ),
+      (
+        ">2\r\n+message\r\n:1\r\n",
+        Ok(DataType::Push(vec![
+          DataType::SimpleString(String::from("message")),
+          DataType::Int(1),
+        ])),
+      ),
+      (
+        "|1\r\n+key-popularity\r\n%1\r\n$1\r\na\r\n,0.1923\r\n*1\r\n:2039123\r\n",
+        Ok(DataType::Array(vec![DataType::Int(2039123)])),
+      ),
+      (
+        "#x\r\n",
+        Err(ParserError::UnexpectedValue {
+          src: String::from("#x\r\n"),
+          span: (1, 1).into(),
+          message: String::from("expected t or f"),
+        }),
       ),
     ];
 
     for (input, expected) in tests {
-      assert_eq!(String::from(expected), encode(input).unwrap());
+      assert_eq!(expected, parse(bytes(input)), "parsing {:?}", input);
+      assert_eq!(
+        Ok(true),
+        is_complete(input.as_bytes()),
+        "checking {:?}",
+        input
+      );
+    }
+  }
+
+  #[test]
+  fn protocol_mismatch() {
+    assert_eq!(
+      Err(ParserError::ProtocolMismatch {
+        src: String::from("%1\r\n+a\r\n:1\r\n"),
+        span: (0, 1).into(),
+        type_name: String::from("map"),
+      }),
+      parse_with_protocol(&bytes("%1\r\n+a\r\n:1\r\n"), Protocol::Resp2)
+    );
+
+    assert_eq!(
+      Err(ParserError::ProtocolMismatch {
+        src: String::from("*2\r\n:1\r\n,1.5\r\n"),
+        span: (8, 1).into(),
+        type_name: String::from("double"),
+      }),
+      parse_with_protocol(&bytes("*2\r\n:1\r\n,1.5\r\n"), Protocol::Resp2)
+    );
+
+    assert_eq!(
+      Ok(DataType::Map(vec![(
+        DataType::SimpleString(String::from("a")),
+        DataType::Int(1)
+      )])),
+      parse_with_protocol(&bytes("%1\r\n+a\r\n:1\r\n"), Protocol::Resp3)
+    );
+
+    assert_eq!(
+      Ok(DataType::Array(vec![DataType::Int(1), DataType::Null])),
+      parse_with_protocol(&bytes("*2\r\n:1\r\n$-1\r\n"), Protocol::Resp2)
+    );
+  }
+
+  #[test]
+  fn unknown_type() {
+    assert_eq!(
+      Err(ParserError::UnexpectedByte {
+        src: String::from("?foo\r\n"),
+        span: (0, 1).into(),
+      }),
+      parse(bytes("?foo\r\n"))
+    );
+  }
+
+  #[test]
+  fn values_split_at_every_byte() {
+    let tests = vec![
+      "+OK\r\n",
+      "$6\r\nfoo\r\nb\r\n",
+      "$0\r\n\r\n",
+      "*2\r\n$3\r\nfoo\r\n*1\r\n:1\r\n",
+      "%1\r\n+key\r\n$-1\r\n",
+    ];
+
+    for input in tests {
+      let input = input.as_bytes();
+
+      // The bytes read so far may end anywhere, including between "\r" and "\n".
+      for read in 0..input.len() {
+        assert_eq!(Ok(None), complete_len(&input[..read]));
+      }
+
+      assert_eq!(Ok(Some(input.len())), complete_len(input));
+    }
+
+    // A "\r" at the end of a value that is parsed anyway is not a terminator.
+    for input in ["+", "+OK\r", "$3\r\nfoo\r"] {
+      assert!(parse(input.as_bytes().to_vec()).is_err());
+    }
+  }
+
+  #[test]
+  fn test_parse_from_reader() {
+    /// Returns at most 3 bytes from each read, like a socket that receives a value in pieces.
+    struct SmallChunks(io::Cursor<Vec<u8>>);
+
+    impl io::Read for SmallChunks {
+      fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(3);
+        self.0.read(&mut buf[..len])
+      }
+    }
+
+    let input = b"$12\r\nHello\r\nWorld\r\n|1\r\n+ttl\r\n:3600\r\n%1\r\n+key\r\n*2\r\n:1\r\n$-1\r\n+OK\r\n$5\r\nabc";
+    let mut reader = SmallChunks(io::Cursor::new(input.to_vec()));
+
+    assert_eq!(
+      DataType::BulkString(b"Hello\r\nWorld".to_vec()),
+      parse_from_reader(&mut reader).unwrap()
+    );
+    // Nothing after the value was read.
+    assert_eq!(19, reader.0.position());
+
+    assert_eq!(
+      DataType::Map(vec![(
+        DataType::SimpleString(String::from("key")),
+        DataType::Array(vec![DataType::Int(1), DataType::Null]),
+      )]),
+      parse_from_reader(&mut reader).unwrap()
+    );
+    assert_eq!(
+      DataType::SimpleString(String::from("OK")),
+      parse_from_reader(&mut reader).unwrap()
+    );
+
+    // The input ends before the Bulk String does.
+    match parse_from_reader(&mut reader) {
+      Err(ReadError::Io(err)) => assert_eq!(io::ErrorKind::UnexpectedEof, err.kind()),
+      result => panic!("expected an unexpected end of input, got {:?}", result),
+    }
+
+    let mut reader = io::Cursor::new(b"?1\r\n".to_vec());
+    assert!(matches!(
+      parse_from_reader(&mut reader),
+      Err(ReadError::Parser(ParserError::UnexpectedByte { .. }))
+    ));
+    assert_eq!(1, reader.position());
+  }
+
+  #[test]
+  fn test_is_complete() {
+    let tests = vec![
+      ("", Ok(false)),
+      ("+OK\r\n", Ok(true)),
+      ("+OK\r", Ok(false)),
+      (":1000\r\n", Ok(true)),
+      (",1.23\r\n", Ok(true)),
+      ("$6\r\nfoobar\r\n", Ok(true)),
+      ("$6\r\nfoobar\r\n+OK\r\n", Ok(true)),
+      ("$6\r\nfoo", Ok(false)),
+      ("$6\r\nfoobar", Ok(false)),
+      ("$6\r\nfoobar\r", Ok(false)),
+      ("$6", Ok(false)),
+      ("$-1\r\n", Ok(true)),
+      ("$0\r\n\r\n", Ok(true)),
+      ("*0\r\n", Ok(true)),
+      ("*-1\r\n", Ok(true)),
+      ("*3\r\n:1\r\n:2\r\n:3\r\n", Ok(true)),
+      ("*3\r\n:1\r\n:2\r\n", Ok(false)),
+      ("*2\r\n*1\r\n$3\r\nfoo\r\n*1\r\n$3\r\nba", Ok(false)),
+      ("*2\r\n*0\r\n:1\r\n", Ok(true)),
+      ("%1\r\n+key\r\n:1\r\n", Ok(true)),
+      ("%1\r\n+key\r\n", Ok(false)),
+      ("|1\r\n+ttl\r\n:3600\r\n+OK\r\n", Ok(true)),
+      ("|1\r\n+ttl\r\n:3600\r\n", Ok(false)),
+      (
+        "?6\r\nfoobar\r\n",
+        Err(ParserError::UnexpectedByte {
+          src: String::from("?6\r\nfoobar\r\n"),
+          span: (0, 1).into(),
+        }),
+      ),
+      (
+        "garbage without a line terminator",
+        Err(ParserError::UnexpectedByte {
+          src: String::from("garbage without a line terminator"),
+          span: (0, 1).into(),
+        }),
+      ),
+      (
+        "*2\r\n:1\r\n?",
+        Err(ParserError::UnexpectedByte {
+          src: String::from("*2\r\n:1\r\n?"),
+          span: (8, 1).into(),
+        }),
+      ),
+      (
+        "$6\r\nfoobarbaz\r\n",
+        Err(ParserError::UnexpectedByte {
+          src: String::from("$6\r\nfoobarbaz\r\n"),
+          span: (10, 2).into(),
+        }),
+      ),
+      (
+        "$abc\r\nfoobar\r\n",
+        Err(ParserError::UnexpectedType {
+          src: String::from("$abc\r\nfoobar\r\n"),
+          span: (1, 3).into(),
+          message: String::from("expected integer"),
+        }),
+      ),
+      (
+        "*-2\r\n",
+        Err(ParserError::UnexpectedValue {
+          src: String::from("*-2\r\n"),
+          span: (1, 2).into(),
+          message: String::from("expected integer greater than or equal to -1"),
+        }),
+      ),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(
+        expected,
+        is_complete(input.as_bytes()),
+        "input: {:?}",
+        input
+      );
+    }
+
+    // Nested deeper than the stack would allow if every level took a frame.
+    let mut deep = b"*1\r\n".repeat(200_000);
+    assert_eq!(Ok(false), is_complete(&deep));
+
+    deep.extend_from_slice(b":1\r\n");
+    assert_eq!(Ok(Some(deep.len())), complete_len(&deep));
+
+    assert_eq!(Ok(false), is_complete(b"%9223372036854775807\r\n"));
+  }
+
+  #[test]
+  fn test_parse_at() {
+    let input = bytes("*2\r\n$3\r\nfoo\r\n:1\r\n$6\r\nfoobar\r\n");
+
+    let (first, first_ends_at) = parse_at(&input, 0).unwrap();
+
+    assert_eq!(
+      DataType::Array(vec![
+        DataType::BulkString(b"foo".to_vec()),
+        DataType::Int(1)
+      ]),
+      first
+    );
+    assert_eq!(17, first_ends_at);
+
+    let (second, second_ends_at) = parse_at(&input, first_ends_at).unwrap();
+
+    assert_eq!(DataType::BulkString(b"foobar".to_vec()), second);
+    assert_eq!(input.len(), second_ends_at);
+  }
+
+  #[test]
+  fn test_parse_exact() {
+    assert_eq!(
+      Ok(DataType::Array(vec![DataType::Int(1), DataType::Int(2)])),
+      parse_exact(b"*2\r\n:1\r\n:2\r\n")
+    );
+
+    let tests = vec![
+      ("+OK\r\njunk", 5),
+      ("+OK\r\n+OK\r\n", 5),
+      ("*1\r\n:1\r\n\r\n", 8),
+    ];
+
+    for (input, expected_position) in tests {
+      match parse_exact(input.as_bytes()) {
+        Err(ParserError::TrailingBytes { span, position, .. }) => {
+          assert_eq!(expected_position, position);
+          assert_eq!(expected_position, span.offset());
+          assert_eq!(input.len() - expected_position, span.len());
+        }
+        result => panic!("unexpected result for {:?}: {:?}", input, result),
+      }
+    }
+  }
+
+  #[test]
+  fn parse_at_shared_buffer() {
+    let input = bytes("+OK\r\n*2\r\n:1\r\n:2\r\n");
+
+    // Both parses borrow the same buffer at the same time.
+    let (first, second) = std::thread::scope(|scope| {
+      let second = scope.spawn(|| parse_at(&input, 5).unwrap());
+      let first = scope.spawn(|| parse_at(&input, 0).unwrap());
+
+      (first.join().unwrap(), second.join().unwrap())
+    });
+
+    assert_eq!((DataType::SimpleString(String::from("OK")), 5), first);
+    assert_eq!(
+      (
+        DataType::Array(vec![DataType::Int(1), DataType::Int(2)]),
+        input.len()
+      ),
+      second
+    );
+  }
+
+  #[test]
+  fn hex_dump_errors() {
+    let mut input = b"*3\r\n$4\r\n".to_vec();
+    input.extend((0..40).map(|i| (i * 7) as u8));
+    input.extend_from_slice(b"\r\n");
+
+    // The Bulk String has 4 bytes but the "\r\n" is not right after them.
+    let err = parse_with_hex_dump(input.clone()).unwrap_err();
+
+    let (src, span) = match &err {
+      ParserError::UnexpectedByte { src, span } => (src.clone(), span.clone()),
+      err => panic!("unexpected error: {:?}", err),
+    };
+
+    assert_eq!(
+      "00000000  2a 33 0d 0a 24 34 0d 0a  00 07 0e 15 1c 23 2a 31  |*3..$4.......#*1|\n\
+       00000010  38 3f 46 4d 54 5b 62 69  70 77 7e 85 8c 93 9a a1  |8?FMT[bipw~.....|\n\
+       00000020  a8 af b6 bd c4 cb d2 d9  e0 e7 ee f5 fc 03 0a 11  |................|\n",
+      src
+    );
+
+    // The error is at offset 12, where "\r\n" was expected after the 4 bytes,
+    // and only the lines up to 2 lines after it are shown.
+    assert_eq!("1c 23", &src[span.offset()..span.offset() + span.len()]);
+
+    // Errors far from the start only show the lines around them.
+    let mut input = vec![
+      b'*', b'1', b'\r', b'\n', b'$', b'2', b'0', b'0', b'\r', b'\n',
+    ];
+    input.extend_from_slice(&[0xff; 201]);
+    input.extend_from_slice(b"\r\n");
+
+    let err = parse_with_hex_dump(input).unwrap_err();
+
+    let (src, span) = match &err {
+      ParserError::UnexpectedByte { src, span } => (src.clone(), span.clone()),
+      err => panic!("unexpected error: {:?}", err),
+    };
+
+    assert_eq!(
+      vec!["000000b0", "000000c0", "000000d0"],
+      src.lines().map(|line| &line[..8]).collect::<Vec<_>>()
+    );
+    assert_eq!("ff 0d", &src[span.offset()..span.offset() + span.len()]);
+  }
+
+  #[test]
+  fn test_parse_iter() {
+    let input =
+      b"*3\r\n$3\r\nfoo\r\n%1\r\n+a\r\n,1.5\r\n$-1\r\n:42\r\n=8\r\ntxt:\x00\xff\r\n\r\n".to_vec();
+
+    let events: Vec<Event> = parse_iter(&input).collect::<Result<_, _>>().unwrap();
+
+    assert_eq!(
+      vec![
+        Event::ArrayStart(3),
+        Event::BulkStringRef(b"foo"),
+        Event::MapStart(1),
+        Event::SimpleString(b"a"),
+        Event::Double(1.5),
+        Event::Null,
+        Event::Int(42),
+        Event::BulkStringRef(b"\x00\xff\r\n"),
+      ],
+      events
+    );
+
+    // The strings point into the input instead of being copies of it.
+    let input_range = input.as_ptr_range();
+    for event in &events {
+      if let Event::BulkStringRef(bytes) | Event::SimpleString(bytes) = event {
+        assert!(input_range.contains(&bytes.as_ptr()));
+      }
+    }
+
+    // A large value that is skipped is never copied.
+    let mut input = b"*2\r\n$1048576\r\n".to_vec();
+    input.extend_from_slice(&vec![b'x'; 1048576]);
+    input.extend_from_slice(b"\r\n:1\r\n");
+
+    let mut ints = Vec::new();
+    for event in parse_iter(&input) {
+      match event.unwrap() {
+        Event::Int(int) => ints.push(int),
+        Event::BulkStringRef(bytes) => assert_eq!(input[14..14 + 1048576].as_ptr(), bytes.as_ptr()),
+        _ => {}
+      }
+    }
+    assert_eq!(vec![1], ints);
+
+    let tests = vec![
+      (
+        "$6\r\nfoo",
+        vec![Err(ParserError::UnexpectedEndOfInput {
+          src: String::from("$6\r\nfoo"),
+          span: (7, 0).into(),
+        })],
+      ),
+      (
+        ":1\r\n?\r\n:2\r\n",
+        vec![
+          Ok(Event::Int(1)),
+          Err(ParserError::UnexpectedByte {
+            src: String::from(":1\r\n?\r\n:2\r\n"),
+            span: (4, 1).into(),
+          }),
+        ],
+      ),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(expected, parse_iter(input.as_bytes()).collect::<Vec<_>>());
+    }
+  }
+
+  #[test]
+  fn test_parse_expecting() {
+    let tests = vec![
+      (":1\r\n", DataTypeKind::Int, Ok(DataType::Int(1))),
+      (
+        "-ERR unknown command 'foobar'\r\n",
+        DataTypeKind::Int,
+        Ok(DataType::Error(String::from(
+          "ERR unknown command 'foobar'",
+        ))),
+      ),
+      (
+        "$1\r\n1\r\n",
+        DataTypeKind::Int,
+        Err(ParserError::UnexpectedType {
+          src: String::from("$1\r\n1\r\n"),
+          span: (0, 1).into(),
+          message: String::from("expected integer, found bulk string"),
+        }),
+      ),
+      ("$-1\r\n", DataTypeKind::BulkString, Ok(DataType::Null)),
+      ("*-1\r\n", DataTypeKind::Array, Ok(DataType::Null)),
+      (
+        "$-1\r\n",
+        DataTypeKind::Int,
+        Err(ParserError::UnexpectedType {
+          src: String::from("$-1\r\n"),
+          span: (0, 1).into(),
+          message: String::from("expected integer, found null"),
+        }),
+      ),
+      (
+        "%0\r\n",
+        DataTypeKind::Array,
+        Err(ParserError::UnexpectedType {
+          src: String::from("%0\r\n"),
+          span: (0, 1).into(),
+          message: String::from("expected array, found map"),
+        }),
+      ),
+      (
+        ":abc\r\n",
+        DataTypeKind::Int,
+        Err(ParserError::UnexpectedType {
+          src: String::from(":abc\r\n"),
+          span: (1, 3).into(),
+          message: String::from("expected integer"),
+        }),
+      ),
+    ];
+
+    for (input, expected_kind, expected) in tests {
+      assert_eq!(expected, parse_expecting(input.as_bytes(), expected_kind));
+    }
+  }
+
+  #[test]
+  fn lf_line_endings() {
+    let value = DataType::Array(vec![
+      DataType::SimpleString(String::from("OK")),
+      DataType::Error(String::from("ERR unknown command 'foobar'")),
+      DataType::Int(-3),
+      DataType::BulkString(b"foo\nbar".to_vec()),
+      DataType::Null,
+      DataType::Double(1.5),
+      DataType::Map(vec![(DataType::Boolean(true), DataType::Array(vec![]))]),
+    ]);
+
+    let encoded = encode_value_with_line_ending(&value, LineEnding::Lf);
+
+    assert_eq!(
+      "*7\n+OK\n-ERR unknown command 'foobar'\n:-3\n$7\nfoo\nbar\n$-1\n,1.5\n%1\n#t\n*0\n",
+      String::from_utf8(encoded.clone()).unwrap()
+    );
+
+    assert_eq!(Ok(value), parse_lenient(encoded.clone()));
+
+    // Only the lenient parser accepts "\n" on its own.
+    assert!(parse(encoded).is_err());
+
+    assert_eq!(
+      Ok(DataType::Array(vec![DataType::Int(1), DataType::Int(2)])),
+      parse_lenient(b"*2\r\n:1\n:2\r\n".to_vec())
+    );
+  }
+
+  #[test]
+  fn inline_commands() {
+    let limits = ParseLimits {
+      max_inline_len: 10,
+      ..ParseLimits::default()
+    };
+
+    let command = |args: &[&str]| {
+      DataType::Array(
+        args
+          .iter()
+          .map(|arg| DataType::BulkString(arg.as_bytes().to_vec()))
+          .collect(),
+      )
+    };
+
+    let tests = vec![
+      ("PING\r\n", Ok(Some((command(&["PING"]), 6)))),
+      ("SET a  b\r\n", Ok(Some((command(&["SET", "a", "b"]), 10)))),
+      ("GET a\r\nGET b\r\n", Ok(Some((command(&["GET", "a"]), 7)))),
+      (
+        "SET\tkey 1\r\n",
+        Ok(Some((command(&["SET", "key", "1"]), 11))),
+      ),
+      ("\r\n", Ok(Some((command(&[]), 2)))),
+      ("", Ok(None)),
+      ("GET a", Ok(None)),
+      ("0123456789", Ok(None)),
+      ("0123456789\r", Ok(None)),
+      ("0123456789\r\n", Ok(Some((command(&["0123456789"]), 12)))),
+      (
+        "0123456789012",
+        Err(ParserError::InlineCommandTooLong {
+          src: String::from("0123456789012"),
+          span: (10, 3).into(),
+          max_inline_len: 10,
+        }),
+      ),
+      (
+        "01234567890\r\n",
+        Err(ParserError::InlineCommandTooLong {
+          src: String::from("01234567890\r\n"),
+          span: (10, 1).into(),
+          max_inline_len: 10,
+        }),
+      ),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(expected, parse_inline_command(input.as_bytes(), limits));
+    }
+
+    // A line that never ends is rejected once it's past the limit,
+    // no matter how many more bytes arrive.
+    let line = vec![b'a'; 64 * 1024 + 1];
+    assert!(matches!(
+      parse_inline_command(&line, ParseLimits::default()),
+      Err(ParserError::InlineCommandTooLong {
+        max_inline_len: 65536,
+        ..
+      })
+    ));
+    assert_eq!(
+      Ok(None),
+      parse_inline_command(&line, ParseLimits::UNLIMITED)
+    );
+  }
+
+  #[test]
+  fn parse_stats() {
+    let input = b"*5\r\n$3\r\nfoo\r\n:1\r\n*2\r\n:2\r\n$-1\r\n%1\r\n+key\r\n#t\r\n-ERR\r\n";
+
+    let mut stats = ParseStats::default();
+
+    stats.record(&parse(input.to_vec()).unwrap(), input.len());
+    stats.record(&parse(b":3\r\n".to_vec()).unwrap(), 4);
+
+    let tests = vec![
+      (DataTypeKind::Array, 2),
+      (DataTypeKind::BulkString, 1),
+      (DataTypeKind::Int, 3),
+      (DataTypeKind::Null, 1),
+      (DataTypeKind::Map, 1),
+      (DataTypeKind::SimpleString, 1),
+      (DataTypeKind::Boolean, 1),
+      (DataTypeKind::Error, 1),
+      (DataTypeKind::Double, 0),
+      (DataTypeKind::Set, 0),
+    ];
+
+    for (kind, expected) in tests {
+      assert_eq!(expected, stats.get(kind), "{}", kind);
+    }
+
+    assert_eq!(input.len() as u64 + 4, stats.bytes());
+  }
+
+  #[test]
+  fn test_parse_int_array() {
+    let tests = vec![
+      ("*0\r\n", Ok(vec![])),
+      ("*3\r\n:1\r\n:-2\r\n:3\r\n", Ok(vec![1, -2, 3])),
+      (
+        "*2\r\n:9223372036854775807\r\n:-9223372036854775808\r\n",
+        Ok(vec![i64::MAX, i64::MIN]),
+      ),
+      (
+        "*2\r\n:1\r\n",
+        Err(ParserError::UnexpectedEndOfInput {
+          src: String::from("*2\r\n:1\r\n"),
+          span: (8, 0).into(),
+        }),
+      ),
+      (
+        "*2\r\n:1\r\n$1\r\n2\r\n",
+        Err(ParserError::UnexpectedType {
+          src: String::from("*2\r\n:1\r\n$1\r\n2\r\n"),
+          span: (8, 1).into(),
+          message: String::from("expected integer"),
+        }),
+      ),
+      (
+        "*1\r\n:1a\r\n",
+        Err(ParserError::UnexpectedType {
+          src: String::from("*1\r\n:1a\r\n"),
+          span: (5, 2).into(),
+          message: String::from("expected integer"),
+        }),
+      ),
+      (
+        ":1\r\n",
+        Err(ParserError::UnexpectedType {
+          src: String::from(":1\r\n"),
+          span: (0, 1).into(),
+          message: String::from("expected array"),
+        }),
+      ),
+      (
+        "*-1\r\n",
+        Err(ParserError::UnexpectedType {
+          src: String::from("*-1\r\n"),
+          span: (0, 3).into(),
+          message: String::from("expected array, found null"),
+        }),
+      ),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(expected, parse_int_array(input.as_bytes()));
+    }
+
+    // The fast path agrees with the generic one.
+    let ints: Vec<i64> = (-5000..5000).map(|i| i * 7919).collect();
+    let input = encode_value(&DataType::Array(
+      ints.iter().map(|i| DataType::Int(*i)).collect(),
+    ));
+
+    assert_eq!(ints, parse_int_array(&input).unwrap());
+    assert_eq!(
+      Ok(DataType::Array(
+        ints.into_iter().map(DataType::Int).collect()
+      )),
+      parse(input)
+    );
+  }
+
+  #[test]
+  fn limits() {
+    let limits = ParseLimits {
+      max_depth: 2,
+      max_array_len: 3,
+      max_bulk_len: 5,
+      max_total_elements: 4,
+      max_inline_len: usize::MAX,
+    };
+
+    let tests = vec![
+      (
+        "*2\r\n*1\r\n$5\r\nHello\r\n:1\r\n",
+        Ok(DataType::Array(vec![
+          DataType::Array(vec![DataType::BulkString(b"Hello".to_vec())]),
+          DataType::Int(1),
+        ])),
+      ),
+      (
+        "*1\r\n*1\r\n*0\r\n",
+        Err(ParserError::DepthLimitExceeded {
+          src: String::from("*1\r\n*1\r\n*0\r\n"),
+          span: (9, 1).into(),
+          max_depth: 2,
+        }),
+      ),
+      (
+        "*4\r\n:1\r\n:2\r\n:3\r\n:4\r\n",
+        Err(ParserError::ArrayLengthLimitExceeded {
+          src: String::from("*4\r\n:1\r\n:2\r\n:3\r\n:4\r\n"),
+          span: (1, 1).into(),
+          max_array_len: 3,
+        }),
+      ),
+      (
+        "$6\r\nfoobar\r\n",
+        Err(ParserError::BulkLengthLimitExceeded {
+          src: String::from("$6\r\nfoobar\r\n"),
+          span: (1, 1).into(),
+          max_bulk_len: 5,
+        }),
+      ),
+      (
+        "*2\r\n*2\r\n:1\r\n:2\r\n*1\r\n:3\r\n",
+        Err(ParserError::ElementLimitExceeded {
+          src: String::from("*2\r\n*2\r\n:1\r\n:2\r\n*1\r\n:3\r\n"),
+          span: (17, 1).into(),
+          max_total_elements: 4,
+        }),
+      ),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(
+        expected,
+        parse_with_limits(bytes(input), limits),
+        "input: {:?}",
+        input
+      );
+    }
+
+    // A Bulk String shorter than its length, which is within the limits.
+    assert_eq!(
+      Err(ParserError::UnexpectedEndOfInput {
+        src: String::from("$100\r\nab\r\n"),
+        span: (10, 0).into(),
+      }),
+      parse_with_limits(bytes("$100\r\nab\r\n"), ParseLimits::default())
+    );
+  }
+
+  #[test]
+  fn parsed_value_outlives_input() {
+    let input = bytes("*2\r\n$3\r\nfoo\r\n+bar\r\n");
+
+    // Parsed from a borrowed slice of the input, the value would not compile
+    // past the drop if it borrowed from it.
+    let (value, _) = parse_at(&input, 0).unwrap();
+
+    drop(input);
+
+    assert_eq!(
+      DataType::Array(vec![
+        DataType::BulkString(b"foo".to_vec()),
+        DataType::SimpleString(String::from("bar")),
+      ]),
+      value
+    );
+  }
+
+  #[test]
+  fn test_encode() {
+    let tests = vec![
+      ("LLEN mylist", "*2\r\n$4\r\nLLEN\r\n$6\r\nmylist\r\n"),
+      (
+        r#"SETEX mykey 10 "Hello""#,
+        "*4\r\n$5\r\nSETEX\r\n$5\r\nmykey\r\n$2\r\n10\r\n$7\r\n\"Hello\"\r\n",
+      ),
+      (
+        "SET mykey 10abc",
+        "*3\r\n$3\r\nSET\r\n$5\r\nmykey\r\n$5\r\n10abc\r\n",
+      ),
+      ("PING", "*1\r\n$4\r\nPING\r\n"),
+      (
+        "INCRBY counter -5",
+        "*3\r\n$6\r\nINCRBY\r\n$7\r\ncounter\r\n$2\r\n-5\r\n",
+      ),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(String::from(expected), encode(input).unwrap());
+    }
+  }
+
+  #[test]
+  fn encode_max_size() {
+    let tests = vec![
+      "LLEN mylist",
+      "PING",
+      "SET mykey 10",
+      r#"SETEX mykey 10 "Hello""#,
+    ];
+
+    for input in tests {
+      let encoded = encode(input).unwrap();
+
+      assert_eq!(
+        Ok(encoded.clone()),
+        encode_with_max_size(input, encoded.len())
+      );
+      assert_eq!(
+        Err(EncodeError::CommandTooLarge {
+          size: encoded.len(),
+          max_size: encoded.len() - 1,
+        }),
+        encode_with_max_size(input, encoded.len() - 1)
+      );
+    }
+
+    let value = "a".repeat(DEFAULT_MAX_COMMAND_SIZE);
+
+    assert_eq!(
+      Err(EncodeError::CommandTooLarge {
+        size: DEFAULT_MAX_COMMAND_SIZE + 36,
+        max_size: DEFAULT_MAX_COMMAND_SIZE,
+      }),
+      encode(&format!("SET mykey {}", value))
+    );
+    assert!(encode(&format!(
+      "SET mykey {}",
+      &value[..DEFAULT_MAX_COMMAND_SIZE - 36]
+    ))
+    .is_ok());
+  }
+
+  #[test]
+  fn encode_does_not_allow_command_injection() {
+    let tests = vec![
+      "SET mykey 1\r\nFLUSHALL\r\n",
+      "SET mykey value\r\nFLUSHALL\r\n",
+      "SET mykey \r\n*1\r\n$8\r\nFLUSHALL\r\n",
+    ];
+
+    for input in tests {
+      let encoded = bytes(&encode(input).unwrap());
+
+      let (value, ends_at) = parse_at(&encoded, 0).unwrap();
+
+      // The whole input is encoded as a single command.
+      assert_eq!(encoded.len(), ends_at, "input: {:?}", input);
+
+      let last_argument = input.split(' ').next_back().unwrap();
+
+      match value {
+        DataType::Array(arguments) => {
+          assert_eq!(3, arguments.len());
+          assert_eq!(
+            DataType::BulkString(last_argument.as_bytes().to_vec()),
+            arguments[2]
+          );
+        }
+        value => panic!("expected array, got {:?}", value),
+      }
+    }
+  }
+
+  #[test]
+  fn test_encode_value() {
+    let tests = vec![
+      (DataType::SimpleString(String::from("OK")), "+OK\r\n"),
+      (
+        DataType::Error(String::from("ERR unknown command 'foobar'")),
+        "-ERR unknown command 'foobar'\r\n",
+      ),
+      (DataType::Int(-3), ":-3\r\n"),
+      (DataType::BulkString(vec![]), "$0\r\n\r\n"),
+      (DataType::BulkString(b"foobar".to_vec()), "$6\r\nfoobar\r\n"),
+      (DataType::Null, "$-1\r\n"),
+      (DataType::Double(3.3), ",3.3\r\n"),
+      (DataType::Double(f64::NEG_INFINITY), ",-inf\r\n"),
+      (DataType::Double(f64::NAN), ",nan\r\n"),
+      (DataType::Array(vec![]), "*0\r\n"),
+      (
+        DataType::Array(vec![
+          DataType::BulkString(b"foo".to_vec()),
+          DataType::Null,
+          DataType::Int(1),
+        ]),
+        "*3\r\n$3\r\nfoo\r\n$-1\r\n:1\r\n",
+      ),
+    ];
+
+    for (value, expected) in tests {
+      assert_eq!(bytes(expected), encode_value(&value));
+    }
+  }
+
+  #[test]
+  fn encode_large_bulk_string_to_writer() {
+    /// Remembers where the bytes written to it were.
+    struct RecordingWriter {
+      bytes: Vec<u8>,
+      writes: Vec<(*const u8, usize)>,
+    }
+
+    impl io::Write for RecordingWriter {
+      fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writes.push((buf.as_ptr(), buf.len()));
+        self.bytes.extend_from_slice(buf);
+        Ok(buf.len())
+      }
+
+      fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+      }
+    }
+
+    let payload: Vec<u8> = (0..16 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let value = DataType::BulkString(payload);
+
+    let mut writer = RecordingWriter {
+      bytes: Vec::new(),
+      writes: Vec::new(),
+    };
+
+    encode_to_writer(&value, &mut writer).unwrap();
+
+    let payload = match &value {
+      DataType::BulkString(payload) => payload,
+      _ => unreachable!(),
+    };
+
+    assert_eq!(b"$16777216\r\n", &writer.bytes[..11]);
+    assert_eq!(payload[..], writer.bytes[11..writer.bytes.len() - 2]);
+    assert_eq!(b"\r\n", &writer.bytes[writer.bytes.len() - 2..]);
+    assert_eq!(encode_value(&value), writer.bytes);
+
+    // The payload is handed to the writer as is instead of being copied into a buffer first.
+    assert!(writer.writes.contains(&(payload.as_ptr(), payload.len())));
+  }
+
+  #[test]
+  fn roundtrip() {
+    let tests = vec![
+      // Examples from the parser tests.
+      "+OK\r\n",
+      "-ERR unknown command 'foobar'\r\n",
+      ":0\r\n",
+      ":1000\r\n",
+      ":-3\r\n",
+      "$0\r\n\r\n",
+      "$6\r\nfoobar\r\n",
+      "*0\r\n",
+      "*3\r\n:1\r\n:2\r\n:3\r\n",
+      "*3\r\n$3\r\nfoo\r\n:1\r\n:2\r\n",
+      "*2\r\n*3\r\n:1\r\n:2\r\n:3\r\n*2\r\n+Foo\r\n-Bar\r\n",
+      "*3\r\n$3\r\nfoo\r\n$-1\r\n$3\r\nbar\r\n",
+      "$-1\r\n",
+      "*-1\r\n",
+      ",1.23\r\n",
+      ",-10\r\n",
+      ",inf\r\n",
+      ",-inf\r\n",
+      "#t\r\n",
+      "(3492890328409238509324850943850943825024385\r\n",
+      "%2\r\n+first\r\n:1\r\n+second\r\n:2\r\n",
+      "~2\r\n+orange\r\n+apple\r\n",
+      ">2\r\n+message\r\n:1\r\n",
+      // Replies from the client tests.
+      ":1\r\n",
+      ":2\r\n",
+      ":5\r\n",
+      ":11\r\n",
+      "$5\r\nHello\r\n",
+      "$5\r\nWorld\r\n",
+      "$11\r\nHello World\r\n",
+      // Integers that are not in their canonical form.
+      ":+10\r\n",
+      ":007\r\n",
+      ":-0\r\n",
+    ];
+
+    for input in tests {
+      assert_roundtrip(input.as_bytes());
     }
   }
 }
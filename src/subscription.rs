@@ -0,0 +1,325 @@
+/// Receiving the messages published to the channels a client is subscribed to as a stream.
+///
+/// A background task owns the client, reads the messages and hands them to the stream
+/// through a bounded channel. When the consumer falls behind and the channel is full,
+/// the task stops reading from the connection until the consumer catches up,
+/// instead of buffering every message it receives.
+///
+/// Messages that are not read pile up in the socket's receive buffer, and once it's full
+/// TCP flow control stops the server from sending more, so they pile up in the server's
+/// output buffer for the client instead. The server disconnects clients whose Pub/Sub
+/// output buffer grows past `client-output-buffer-limit pubsub`, 32MB by default,
+/// in which case the stream returns [crate::redis::RedisError::ConnectionClosed].
+///
+/// [PubSubClient] pairs a subscribed connection with a second one for every other command.
+///
+/// # Examples
+///
+/// ```terminal
+/// let mut redis = Redis::connect("127.0.0.1:6379").await?;
+/// redis.subscribe(&["news"]).await?;
+///
+/// let mut messages = redis.into_message_stream(128);
+///
+/// while let Some(message) = messages.next().await {
+///   println!("{:?}", message?);
+/// }
+/// ```
+use std::ops::{Deref, DerefMut};
+
+use miette::Result;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+
+use crate::redis::{Message, Redis};
+
+/// The messages received by a client moved to a background task, see [Redis::into_message_stream].
+#[derive(Debug)]
+pub struct MessageStream {
+  messages: mpsc::Receiver<Result<Message>>,
+}
+
+impl<S> Redis<S>
+where
+  S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+  /// Moves the client, which should be subscribed to channels, to a background task
+  /// that reads the messages published to them, and returns a stream of the messages.
+  ///
+  /// At most `capacity` messages that have not been read from the stream are kept,
+  /// once there are that many the task stops reading from the connection until some are read.
+  ///
+  /// The background task stops when the stream is dropped or when reading a message fails,
+  /// in which case the error is the last item of the stream.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `capacity` is 0.
+  pub fn into_message_stream(mut self, capacity: usize) -> MessageStream {
+    let (sender, receiver) = mpsc::channel(capacity);
+
+    tokio::spawn(async move {
+      loop {
+        let message = self.next_message().await;
+
+        let failed = message.is_err();
+
+        // Waits while the channel is full, which is what stops the task from reading.
+        if sender.send(message).await.is_err() || failed {
+          break;
+        }
+      }
+    });
+
+    MessageStream { messages: receiver }
+  }
+}
+
+impl MessageStream {
+  /// Waits for the next message, returning `None` once the background task has stopped.
+  pub async fn next(&mut self) -> Option<Result<Message>> {
+    self.messages.recv().await
+  }
+}
+
+/// A client that can receive messages and send any command at the same time on RESP2,
+/// where a connection subscribed to a channel can't send most commands.
+///
+/// It uses two connections: one for the subscriptions, which [PubSubClient::subscribe],
+/// [PubSubClient::unsubscribe], [PubSubClient::next_message] and [PubSubClient::channels] use,
+/// and one for every other command, which the client derefs to, so the [Redis] methods
+/// can be called on it directly.
+///
+/// # Examples
+///
+/// ```terminal
+/// let mut client = PubSubClient::connect("127.0.0.1:6379").await?;
+/// client.subscribe(&["news"]).await?;
+///
+/// client.send("GET mykey").await?;
+///
+/// let message = client.next_message().await?;
+/// ```
+#[derive(Debug)]
+pub struct PubSubClient<S = TcpStream> {
+  subscriber: Redis<S>,
+  commands: Redis<S>,
+}
+
+impl PubSubClient<TcpStream> {
+  /// Opens the two connections to the server at `ip`.
+  pub async fn connect(ip: &str) -> Result<Self> {
+    let subscriber = Redis::connect(ip).await?;
+    let commands = Redis::connect(ip).await?;
+
+    Ok(Self {
+      subscriber,
+      commands,
+    })
+  }
+
+  /// Reconnects both connections, see [Redis::reconnect],
+  /// subscribing the subscription connection to its channels again.
+  pub async fn reconnect(&mut self) -> Result<()> {
+    self.subscriber.reconnect().await?;
+    self.commands.reconnect().await
+  }
+}
+
+impl<S> PubSubClient<S>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  /// Creates a client that subscribes through `subscriber` and sends every other command through `commands`.
+  pub fn from_streams(subscriber: S, commands: S) -> Self {
+    Self {
+      subscriber: Redis::from_stream(subscriber),
+      commands: Redis::from_stream(commands),
+    }
+  }
+
+  /// Subscribes the subscription connection to `channels`, see [Redis::subscribe].
+  pub async fn subscribe(&mut self, channels: &[&str]) -> Result<()> {
+    self.subscriber.subscribe(channels).await
+  }
+
+  /// Unsubscribes the subscription connection from `channels`, see [Redis::unsubscribe].
+  pub async fn unsubscribe(&mut self, channels: &[&str]) -> Result<()> {
+    self.subscriber.unsubscribe(channels).await
+  }
+
+  /// Unsubscribes the subscription connection from every channel, see [Redis::unsubscribe_all].
+  pub async fn unsubscribe_all(&mut self) -> Result<()> {
+    self.subscriber.unsubscribe_all().await
+  }
+
+  /// Returns how many channels the subscription connection is subscribed to.
+  pub fn subscriptions(&self) -> usize {
+    self.subscriber.subscriptions()
+  }
+
+  /// Returns the channels the subscription connection is subscribed to, see [Redis::channels].
+  pub fn channels(&self) -> &[String] {
+    self.subscriber.channels()
+  }
+
+  /// Returns the next message published to one of the channels, see [Redis::next_message].
+  pub async fn next_message(&mut self) -> Result<Message> {
+    self.subscriber.next_message().await
+  }
+
+  /// Splits the client into its subscription connection and its command connection.
+  pub fn into_parts(self) -> (Redis<S>, Redis<S>) {
+    (self.subscriber, self.commands)
+  }
+}
+
+impl<S> Deref for PubSubClient<S> {
+  type Target = Redis<S>;
+
+  fn deref(&self) -> &Self::Target {
+    &self.commands
+  }
+}
+
+impl<S> DerefMut for PubSubClient<S> {
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    &mut self.commands
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use std::sync::Arc;
+  use std::time::Duration;
+  use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+
+  #[tokio::test]
+  async fn slow_consumer() -> Result<()> {
+    const MESSAGES: usize = 10_000;
+    const CAPACITY: usize = 16;
+
+    let (client, mut server) = duplex(4096);
+
+    let published = Arc::new(AtomicUsize::new(0));
+
+    let server = tokio::spawn({
+      let published = Arc::clone(&published);
+
+      async move {
+        let mut buffer = [0; 4096];
+        assert!(server.read(&mut buffer).await.unwrap() > 0);
+
+        server
+          .write_all(b">3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n")
+          .await
+          .unwrap();
+
+        for i in 0..MESSAGES {
+          let payload = format!("message {}", i);
+
+          let message = format!(
+            ">3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n${}\r\n{}\r\n",
+            payload.len(),
+            payload
+          );
+
+          server.write_all(message.as_bytes()).await.unwrap();
+
+          published.fetch_add(1, Ordering::SeqCst);
+        }
+      }
+    });
+
+    let mut redis = Redis::from_stream(client);
+    redis.subscribe(&["news"]).await?;
+
+    let mut messages = redis.into_message_stream(CAPACITY);
+
+    // Nothing is read from the stream, so the server can only publish what fits in the channel,
+    // the chunk the client is reading from and the in-memory connection.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let published_while_waiting = published.load(Ordering::SeqCst);
+    assert!(
+      published_while_waiting < CAPACITY + 300,
+      "{} messages were published",
+      published_while_waiting
+    );
+
+    for i in 0..MESSAGES {
+      let message = messages.next().await.unwrap()?;
+
+      assert_eq!(
+        Message {
+          channel: b"news".to_vec(),
+          payload: format!("message {}", i).into_bytes(),
+        },
+        message
+      );
+    }
+
+    server.await.unwrap();
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn commands_while_subscribed() -> Result<()> {
+    let (subscriber, mut subscriber_server) = duplex(4096);
+    let (commands, mut commands_server) = duplex(4096);
+
+    let subscriber_server = tokio::spawn(async move {
+      let mut buffer = [0; 4096];
+      let n = subscriber_server.read(&mut buffer).await.unwrap();
+      assert_eq!(b"*2\r\n$9\r\nSUBSCRIBE\r\n$4\r\nnews\r\n", &buffer[..n]);
+
+      // RESP2, the client can't send other commands on this connection.
+      subscriber_server
+        .write_all(b"*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n")
+        .await
+        .unwrap();
+      subscriber_server
+        .write_all(b"*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$5\r\nhello\r\n")
+        .await
+        .unwrap();
+
+      // Keeps the connection open until the client is done.
+      assert_eq!(0, subscriber_server.read(&mut buffer).await.unwrap());
+    });
+
+    let commands_server = tokio::spawn(async move {
+      let mut buffer = [0; 4096];
+      let n = commands_server.read(&mut buffer).await.unwrap();
+      assert_eq!(b"*2\r\n$3\r\nGET\r\n$5\r\nmykey\r\n", &buffer[..n]);
+
+      commands_server.write_all(b"$5\r\nvalue\r\n").await.unwrap();
+    });
+
+    let mut client = PubSubClient::from_streams(subscriber, commands);
+
+    client.subscribe(&["news"]).await?;
+    assert_eq!(1, client.subscriptions());
+    assert_eq!(&[String::from("news")], client.channels());
+
+    assert_reply_bulk!(client.send("GET mykey").await?, "value");
+
+    assert_eq!(
+      Message {
+        channel: b"news".to_vec(),
+        payload: b"hello".to_vec(),
+      },
+      client.next_message().await?
+    );
+
+    drop(client);
+
+    commands_server.await.unwrap();
+    subscriber_server.await.unwrap();
+
+    Ok(())
+  }
+}
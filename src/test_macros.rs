@@ -0,0 +1,73 @@
+/// Asserts that a [crate::redis::Reply] is an Integer equal to `expected`,
+/// panicking with the actual reply otherwise.
+///
+/// # Examples
+///
+/// ```terminal
+/// assert_reply_int!(redis.send("LLEN mylist").await?, 2);
+/// ```
+macro_rules! assert_reply_int {
+  ($reply:expr, $expected:expr $(,)?) => {
+    match $reply {
+      $crate::redis::Reply::Ok($crate::data_type::DataType::Int(actual)) if actual == $expected => {
+      }
+      reply => panic!("expected the integer reply {}, got {:?}", $expected, reply),
+    }
+  };
+}
+
+/// Asserts that a [crate::redis::Reply] is a Bulk String with the bytes of `expected`,
+/// which can be a `&str` or bytes, panicking with the actual reply otherwise.
+///
+/// # Examples
+///
+/// ```terminal
+/// assert_reply_bulk!(redis.send("GET mykey").await?, "Hello");
+/// ```
+macro_rules! assert_reply_bulk {
+  ($reply:expr, $expected:expr $(,)?) => {
+    match $reply {
+      $crate::redis::Reply::Ok($crate::data_type::DataType::BulkString(actual))
+        if actual.as_slice() == AsRef::<[u8]>::as_ref(&$expected) => {}
+      reply => panic!(
+        "expected the bulk string reply {:?}, got {:?}",
+        String::from_utf8_lossy(AsRef::<[u8]>::as_ref(&$expected)),
+        reply
+      ),
+    }
+  };
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::data_type::DataType;
+  use crate::redis::Reply;
+
+  #[test]
+  fn matching_replies() {
+    assert_reply_int!(Reply::Ok(DataType::Int(2)), 2);
+    assert_reply_int!(Reply::Ok(DataType::Int(-1)), -1);
+    assert_reply_bulk!(Reply::Ok(DataType::BulkString(b"Hello".to_vec())), "Hello");
+    assert_reply_bulk!(Reply::Ok(DataType::BulkString(vec![0, 255])), [0, 255]);
+  }
+
+  #[test]
+  #[should_panic(expected = "expected the integer reply 2, got Ok(Int(3))")]
+  fn int_mismatch() {
+    assert_reply_int!(Reply::Ok(DataType::Int(3)), 2);
+  }
+
+  #[test]
+  #[should_panic(expected = "expected the integer reply 2, got Ok(BulkString([50]))")]
+  fn int_wrong_type() {
+    assert_reply_int!(Reply::Ok(DataType::BulkString(b"2".to_vec())), 2);
+  }
+
+  #[test]
+  #[should_panic(
+    expected = "expected the bulk string reply \"Hello\", got Error(\"ERR wrong type\")"
+  )]
+  fn bulk_error() {
+    assert_reply_bulk!(Reply::Error(String::from("ERR wrong type")), "Hello");
+  }
+}
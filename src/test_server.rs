@@ -0,0 +1,192 @@
+/// In-memory and TCP servers the tests talk to instead of a real Redis server.
+///
+/// Every server reads the commands the client sends, which are RESP Arrays of Bulk Strings,
+/// replies to them with what the test wants, and returns the commands it received
+/// so the test can check what was sent.
+use tokio::io::{duplex, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream};
+use tokio::task::JoinHandle;
+
+use crate::data_type::DataType;
+use crate::redis::Redis;
+use crate::resp;
+
+/// Returns `args` the way a client sends them, as an Array of Bulk Strings.
+pub(crate) fn command_args(args: &[&str]) -> DataType {
+  DataType::Array(
+    args
+      .iter()
+      .map(|arg| DataType::BulkString(arg.as_bytes().to_vec()))
+      .collect(),
+  )
+}
+
+/// Returns a client connected to a server that replies to the commands it receives
+/// with `replies`, in order, one reply per command.
+///
+/// The server task returns the commands it received, once it has sent every reply.
+pub(crate) fn scripted_server(
+  replies: &'static [&'static [u8]],
+) -> (Redis<DuplexStream>, JoinHandle<Vec<DataType>>) {
+  let mut replies = replies.iter();
+
+  spawn_server(Some(replies.len()), move |_| {
+    replies.next().map(|reply| reply.to_vec())
+  })
+}
+
+/// Returns a client connected to an in-memory server that replies to every command
+/// it receives with the reply `responses` has for it, or with an error if it has none,
+/// until the client is dropped.
+///
+/// The server task returns the commands it received.
+pub(crate) fn fake_redis(
+  responses: &'static [(&'static [&'static str], &'static [u8])],
+) -> (Redis<DuplexStream>, JoinHandle<Vec<DataType>>) {
+  stateful_server(move |args| {
+    responses
+      .iter()
+      .find(|(expected, _)| {
+        expected
+          .iter()
+          .map(|arg| arg.as_bytes())
+          .eq(args.iter().map(Vec::as_slice))
+      })
+      .map(|(_, reply)| *reply)
+      .unwrap_or(b"-ERR no reply scripted for the command\r\n")
+      .to_vec()
+  })
+}
+
+/// Returns a client connected to an in-memory server that replies to every command
+/// with what `handler` returns for its arguments, until the client is dropped.
+///
+/// `handler` can keep state between commands, like the keys that have been set.
+///
+/// The server task returns the commands it received.
+pub(crate) fn stateful_server(
+  mut handler: impl FnMut(&[Vec<u8>]) -> Vec<u8> + Send + 'static,
+) -> (Redis<DuplexStream>, JoinHandle<Vec<DataType>>) {
+  spawn_server(None, move |args| Some(handler(args)))
+}
+
+/// What a [mock_server] does when it receives a command.
+pub(crate) type MockHandler = fn(connection: usize, command: &[String]) -> Option<&'static [u8]>;
+
+/// Starts a TCP server that accepts `connections` connections, one after the other,
+/// and replies to every command with what `handler` returns for it,
+/// or drops the connection when `handler` returns `None`.
+///
+/// Returns the address of the server and a task that returns the commands received
+/// by each connection, once every connection has been closed.
+pub(crate) async fn mock_server(
+  connections: usize,
+  handler: MockHandler,
+) -> (String, JoinHandle<Vec<Vec<Vec<String>>>>) {
+  let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let addr = listener.local_addr().unwrap().to_string();
+
+  let server = tokio::spawn(async move {
+    let mut commands_by_connection = Vec::new();
+
+    for connection in 0..connections {
+      let (socket, _) = listener.accept().await.unwrap();
+
+      let commands = serve(socket, None, |args| {
+        let command: Vec<String> = args
+          .iter()
+          .map(|arg| String::from_utf8(arg.clone()).unwrap())
+          .collect();
+
+        handler(connection, &command).map(<[u8]>::to_vec)
+      })
+      .await;
+
+      commands_by_connection.push(
+        commands
+          .into_iter()
+          .map(|command| {
+            arguments(&command)
+              .into_iter()
+              .map(|arg| String::from_utf8(arg).unwrap())
+              .collect()
+          })
+          .collect(),
+      );
+    }
+
+    commands_by_connection
+  });
+
+  (addr, server)
+}
+
+/// Returns a client connected to an in-memory server that answers with `handler`, see [serve].
+fn spawn_server(
+  limit: Option<usize>,
+  handler: impl FnMut(&[Vec<u8>]) -> Option<Vec<u8>> + Send + 'static,
+) -> (Redis<DuplexStream>, JoinHandle<Vec<DataType>>) {
+  let (client, server) = duplex(4096);
+
+  let server = tokio::spawn(serve(server, limit, handler));
+
+  (Redis::from_stream(client), server)
+}
+
+/// Reads commands from `stream` and writes the reply `handler` returns for the arguments of each one,
+/// until the client closes the connection, `handler` returns `None`, which closes the connection,
+/// or `limit` commands have been answered.
+///
+/// Returns the commands it received.
+async fn serve<S>(
+  mut stream: S,
+  limit: Option<usize>,
+  mut handler: impl FnMut(&[Vec<u8>]) -> Option<Vec<u8>>,
+) -> Vec<DataType>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  let mut commands = Vec::new();
+  let mut received = Vec::new();
+  let mut buffer = [0; 4096];
+
+  while limit.is_none_or(|limit| commands.len() < limit) {
+    while !resp::is_complete(&received).unwrap() {
+      let bytes_read = stream.read(&mut buffer).await.unwrap();
+
+      // The client has been dropped.
+      if bytes_read == 0 {
+        return commands;
+      }
+
+      received.extend_from_slice(&buffer[..bytes_read]);
+    }
+
+    let (command, command_ends_at) = resp::parse_at(&received, 0).unwrap();
+    received.drain(..command_ends_at);
+
+    let reply = handler(&arguments(&command));
+
+    commands.push(command);
+
+    match reply {
+      Some(reply) => stream.write_all(&reply).await.unwrap(),
+      None => break,
+    }
+  }
+
+  commands
+}
+
+/// Returns the arguments of `command`, panicking if it's not an Array of Bulk Strings.
+fn arguments(command: &DataType) -> Vec<Vec<u8>> {
+  match command {
+    DataType::Array(args) => args
+      .iter()
+      .map(|arg| match arg {
+        DataType::BulkString(arg) => arg.clone(),
+        arg => panic!("unexpected argument {:?}", arg),
+      })
+      .collect(),
+    command => panic!("unexpected command {:?}", command),
+  }
+}
@@ -0,0 +1,81 @@
+//! Checks that [Redis::read_reply_into] does not allocate once its buffer is big enough.
+//!
+//! It lives in its own test binary because counting allocations needs a global allocator,
+//! which would count the allocations of every other test too.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::io::Write;
+
+use miette::{IntoDiagnostic, Result};
+
+use redis::data_type::DataType;
+use redis::redis::Redis;
+
+/// Counts the allocations made by each thread, see [allocations].
+struct CountingAllocator;
+
+thread_local! {
+  static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+  unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+    System.alloc(layout)
+  }
+
+  unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    System.dealloc(ptr, layout)
+  }
+
+  unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+    let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+    System.realloc(ptr, layout, new_size)
+  }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Returns how many allocations the current thread has made.
+fn allocations() -> usize {
+  ALLOCATIONS.with(|allocations| allocations.get())
+}
+
+#[tokio::test]
+async fn read_reply_into_reuses_buffer() -> Result<()> {
+  let listener = std::net::TcpListener::bind("127.0.0.1:0").into_diagnostic()?;
+  let addr = listener.local_addr().into_diagnostic()?.to_string();
+
+  // The server runs on its own thread so its allocations are not counted.
+  let server = std::thread::spawn(move || {
+    let (mut socket, _) = listener.accept().unwrap();
+
+    for counter in 1..=100 {
+      socket
+        .write_all(format!(":{}\r\n", counter).as_bytes())
+        .unwrap();
+    }
+  });
+
+  let mut redis = Redis::connect(&addr).await?;
+  let mut buf = Vec::new();
+
+  let mut allocations_per_reply = Vec::new();
+
+  for i in 1..=100 {
+    let allocations_before = allocations();
+
+    assert_eq!(DataType::Int(i), redis.read_reply_into(&mut buf).await?);
+
+    allocations_per_reply.push(allocations() - allocations_before);
+  }
+
+  // The buffers may have to grow while reading the first replies.
+  assert_eq!(vec![0; 90], allocations_per_reply[10..]);
+
+  drop(redis);
+  server.join().unwrap();
+
+  Ok(())
+}